@@ -0,0 +1,213 @@
+//! # Analog comparators
+//!
+//! COMP1 and COMP2 compare a positive input pin against a negative input pin or a fraction of
+//! the internal reference voltage. The output can be polled, routed to the timer break inputs
+//! (SYSCFG) or raise an interrupt / wake-up event through EXTI lines 17 and 18, see
+//! [`Comparator::exti_line`].
+//!
+//! Input pins have to be in analog mode:
+//!
+//! | Comparator | Positive input   | Negative input   |
+//! |------------|------------------|------------------|
+//! | COMP1      | PB8, PB2, PA1    | PB1, PA0         |
+//! | COMP2      | PB4, PB6, PA3    | PB3, PB7, PA2    |
+//!
+//! The VREFINT fractions are generated by a scaler that is controlled from COMP1, selecting one
+//! of them on either comparator enables it.
+
+use crate::exti::ExtiLine;
+use crate::gpio::{
+    gpioa::{PA0, PA1, PA2, PA3},
+    gpiob::{PB1, PB2, PB3, PB4, PB6, PB7, PB8},
+    Analog,
+};
+use crate::pac::{COMP1, COMP2};
+use crate::rcc::{Peripheral, Rcc};
+
+/// Pin usable as the positive (non-inverting) input of `COMP`
+pub trait PositiveInput<COMP> {
+    /// `INPSEL` value selecting this input
+    const INPSEL: u8;
+}
+
+/// Input usable as the negative (inverting) input of `COMP`
+pub trait NegativeInput<COMP> {
+    /// `INMSEL` value selecting this input
+    fn inmsel(&self) -> u8;
+    /// Returns true if the input needs the VREFINT scaler
+    fn uses_scaler(&self) -> bool {
+        false
+    }
+}
+
+/// Internal reference voltage as negative input
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub enum Reference {
+    /// 1/4 VREFINT
+    Quarter,
+    /// 1/2 VREFINT
+    Half,
+    /// 3/4 VREFINT
+    ThreeQuarters,
+    /// VREFINT
+    Full,
+}
+
+impl<COMP> NegativeInput<COMP> for Reference {
+    fn inmsel(&self) -> u8 {
+        match self {
+            Reference::Quarter => 0b0000,
+            Reference::Half => 0b0001,
+            Reference::ThreeQuarters => 0b0010,
+            Reference::Full => 0b0011,
+        }
+    }
+
+    fn uses_scaler(&self) -> bool {
+        true
+    }
+}
+
+macro_rules! comp_pins {
+    ($($COMP:ident: {
+        positive: [$($PPIN:ident: $inpsel:expr,)+],
+        negative: [$($NPIN:ident: $inmsel:expr,)+],
+    })+) => {
+        $(
+            $(
+                impl PositiveInput<$COMP> for $PPIN<Analog> {
+                    const INPSEL: u8 = $inpsel;
+                }
+            )+
+            $(
+                impl NegativeInput<$COMP> for $NPIN<Analog> {
+                    fn inmsel(&self) -> u8 {
+                        $inmsel
+                    }
+                }
+            )+
+        )+
+    };
+}
+
+comp_pins! {
+    COMP1: {
+        positive: [PB8: 0b00, PB2: 0b01, PA1: 0b10,],
+        negative: [PB1: 0b0110, PA0: 0b1000,],
+    }
+    COMP2: {
+        positive: [PB4: 0b00, PB6: 0b01, PA3: 0b10,],
+        negative: [PB3: 0b0110, PB7: 0b0111, PA2: 0b1000,],
+    }
+}
+
+/// Comparator speed / power trade-off (`PWRMODE`)
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub enum PowerMode {
+    /// Fastest response
+    HighSpeed = 0b00,
+    /// Slower response with lower supply current
+    MediumSpeed = 0b01,
+}
+
+/// Analog comparator
+pub struct Comparator<COMP, PLUS, MINUS> {
+    comp: COMP,
+    plus: PLUS,
+    minus: MINUS,
+}
+
+macro_rules! comparator {
+    ($($COMP:ident: ($comp:ident, $PER:ident, $line:ident),)+) => {
+        $(
+            impl<PLUS, MINUS> Comparator<$COMP, PLUS, MINUS>
+            where
+                PLUS: PositiveInput<$COMP>,
+                MINUS: NegativeInput<$COMP>,
+            {
+                /// Configures the comparator inputs, it starts disabled
+                pub fn $comp(comp: $COMP, plus: PLUS, minus: MINUS, rcc: &mut Rcc) -> Self {
+                    // Not reset, COMP1 holds the scaler enable shared with COMP2
+                    rcc.enable(Peripheral::$PER);
+
+                    if minus.uses_scaler() {
+                        // NOTE(unsafe) the scaler bit is shared between both comparators and
+                        // only ever set
+                        cortex_m::interrupt::free(|_| unsafe {
+                            (*COMP1::ptr()).csr.modify(|_, w| w.scaler_en().set_bit())
+                        });
+                    }
+                    comp.csr.modify(|_, w| unsafe {
+                        w.inpsel().bits(PLUS::INPSEL).inmsel().bits(minus.inmsel())
+                    });
+
+                    Comparator { comp, plus, minus }
+                }
+
+                /// Starts comparing
+                pub fn enable(&mut self) {
+                    self.comp.csr.modify(|_, w| w.comp_en().set_bit());
+                }
+
+                /// Stops comparing to save power
+                pub fn disable(&mut self) {
+                    self.comp.csr.modify(|_, w| w.comp_en().clear_bit());
+                }
+
+                /// Returns the output level, high if the positive input is above the negative
+                /// one (unless inverted)
+                pub fn output(&self) -> bool {
+                    self.comp.csr.read().comp_out().bit_is_set()
+                }
+
+                /// Inverts the output
+                pub fn set_inverted(&mut self, inverted: bool) {
+                    self.comp.csr.modify(|_, w| w.polarity().bit(inverted));
+                }
+
+                /// Selects the speed / power trade-off
+                pub fn set_power_mode(&mut self, mode: PowerMode) {
+                    self.comp.csr.modify(|_, w| unsafe { w.pwrmode().bits(mode as u8) });
+                }
+
+                /// Enables the digital output filter
+                ///
+                /// The output only changes once the comparison result was stable for `count`
+                /// PCLK cycles, `0` disables the filter.
+                pub fn set_filter(&mut self, count: u16) {
+                    self.comp
+                        .fr
+                        .write(|w| unsafe { w.fltcnt().bits(count).flten().bit(count != 0) });
+                }
+
+                /// Returns the EXTI line the output is connected to
+                ///
+                /// Configure the edge with [`ExtiExt::listen`](crate::exti::ExtiExt::listen) to
+                /// get an interrupt or a wake-up from Stop mode on output transitions.
+                pub fn exti_line(&self) -> ExtiLine {
+                    ExtiLine::$line
+                }
+
+                /// Disables the comparator and releases the peripheral and inputs
+                pub fn release(self) -> ($COMP, PLUS, MINUS) {
+                    self.comp.csr.modify(|_, w| w.comp_en().clear_bit());
+                    (self.comp, self.plus, self.minus)
+                }
+            }
+        )+
+    };
+}
+
+comparator! {
+    COMP1: (comp1, Comp1, COMP1),
+    COMP2: (comp2, Comp2, COMP2),
+}
+
+impl<PLUS, MINUS> Comparator<COMP1, PLUS, MINUS> {
+    /// Enables the input hysteresis to suppress chatter on slow or noisy signals
+    ///
+    /// Only COMP1 has a hysteresis option.
+    pub fn set_hysteresis(&mut self, enable: bool) {
+        self.comp.csr.modify(|_, w| w.hyst().bit(enable));
+    }
+}
@@ -0,0 +1,115 @@
+//! # Cyclic Redundancy Check calculation unit
+//!
+//! The CRC unit computes the CRC-32 (Ethernet) polynomial `0x04C11DB7` over 32-bit words, starting
+//! from `0xFFFF_FFFF`. Unlike on larger parts, neither the polynomial nor the initial value can be
+//! configured, and there is no input/output bit reversal, so the result matches the MPEG-2 variant
+//! (no reflection, no final XOR) of CRC-32.
+//!
+//! The data register only accepts whole words, which are shifted in most significant bit first.
+//! [`Crc::feed`] packs bytes into big-endian words, so the first byte is shifted in first and the
+//! result matches a byte-wise CRC-32/MPEG-2 when the length is a multiple of 4. A trailing
+//! partial word is padded with zeros.
+
+use crate::pac::CRC;
+use crate::rcc::{Peripheral, Rcc};
+
+/// Constrained CRC peripheral
+pub struct Crc {
+    crc: CRC,
+}
+
+impl Crc {
+    /// Enables the CRC clock and resets the calculation
+    pub fn new(crc: CRC, rcc: &mut Rcc) -> Self {
+        rcc.enable(Peripheral::Crc);
+        let mut crc = Crc { crc };
+        crc.reset();
+        crc
+    }
+
+    /// Restarts the calculation from the initial value `0xFFFF_FFFF`
+    pub fn reset(&mut self) {
+        self.crc.cr.write(|w| w.reset().set_bit());
+    }
+
+    /// Feeds a single word into the calculation
+    pub fn feed_word(&mut self, word: u32) {
+        self.crc.dr.write(|w| w.dr().variant(word));
+    }
+
+    /// Feeds `data` into the calculation, word by word
+    ///
+    /// A trailing partial word is padded with zero bytes.
+    pub fn feed(&mut self, data: &[u8]) {
+        for word in words(data) {
+            self.feed_word(word);
+        }
+    }
+
+    /// Returns the CRC of all data fed since the last reset
+    pub fn result(&self) -> u32 {
+        self.crc.dr.read().bits()
+    }
+
+    /// Returns the independent data register
+    ///
+    /// This byte is not touched by the calculation or by [`Crc::reset`] and can be used as
+    /// temporary storage.
+    pub fn idr(&self) -> u8 {
+        self.crc.idr.read().idr().bits()
+    }
+
+    /// Sets the independent data register
+    pub fn set_idr(&mut self, value: u8) {
+        self.crc.idr.write(|w| unsafe { w.idr().bits(value) });
+    }
+
+    /// Releases the CRC peripheral
+    pub fn release(self) -> CRC {
+        self.crc
+    }
+}
+
+/// Packs `data` into big-endian words, padding a trailing partial word with zeros
+fn words(data: &[u8]) -> impl Iterator<Item = u32> + '_ {
+    data.chunks(4).map(|chunk| {
+        let mut word = [0; 4];
+        word[..chunk.len()].copy_from_slice(chunk);
+        u32::from_be_bytes(word)
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    // Software model of the data register: the word is shifted in MSB first
+    fn feed_word(crc: u32, word: u32) -> u32 {
+        (0..32).fold(crc ^ word, |crc, _| {
+            if crc & 0x8000_0000 != 0 {
+                (crc << 1) ^ 0x04C1_1DB7
+            } else {
+                crc << 1
+            }
+        })
+    }
+
+    fn crc(data: &[u8]) -> u32 {
+        words(data).fold(0xFFFF_FFFF, feed_word)
+    }
+
+    #[test]
+    fn packs_big_endian_words() {
+        let mut words = words(b"12345");
+        assert_eq!(words.next(), Some(0x3132_3334));
+        assert_eq!(words.next(), Some(0x3500_0000));
+        assert_eq!(words.next(), None);
+    }
+
+    #[test]
+    fn matches_crc32_mpeg2() {
+        // CRC-32/MPEG-2 of the ASCII bytes
+        assert_eq!(crc(b"12345678"), 0x49E3_C2FB);
+        assert_eq!(crc(b""), 0xFFFF_FFFF);
+    }
+}
@@ -30,6 +30,82 @@ impl Delay {
         // As access to the count register is possible without a reference to the systick, we can
         // just drop it
     }
+
+    /// Delays for at least `duration`, e.g. `delay.delay(500.micros())`
+    pub fn delay<const NOM: u32, const DENOM: u32>(
+        &mut self,
+        duration: fugit::Duration<u32, NOM, DENOM>,
+    ) {
+        let us = u64::from(duration.ticks())
+            .saturating_mul(u64::from(NOM))
+            .saturating_mul(1_000_000)
+            .div_ceil(u64::from(DENOM));
+
+        self.delay_ms(u32::try_from(us / 1_000).unwrap_or(u32::MAX));
+        self.delay_us((us % 1_000) as u32);
+    }
+
+    /// Returns the number of SysTick ticks used per microsecond
+    pub fn ticks_per_us(&self) -> u32 {
+        self.scale
+    }
+
+    /// Overrides the number of SysTick ticks used per microsecond
+    pub fn set_ticks_per_us(&mut self, scale: u32) {
+        assert!(scale >= 1);
+        self.scale = scale;
+    }
+
+    /// Calibrates the delay against a reference clock
+    ///
+    /// `wait_reference` must block for `reference_us` microseconds as measured by an accurate
+    /// clock, e.g. until a timer clocked from the LSE has completed one period. The SysTick
+    /// ticks elapsed meanwhile replace the value derived from the nominal HSI frequency. The
+    /// reference period must be shorter than 2^24 SysTick ticks (about 349 ms at 48 MHz).
+    /// Returns the new number of ticks per microsecond, on error the previous value is kept.
+    pub fn calibrate<F>(
+        &mut self,
+        mut wait_reference: F,
+        reference_us: u32,
+    ) -> Result<u32, CalibrationError>
+    where
+        F: FnMut(),
+    {
+        if reference_us == 0 {
+            return Err(CalibrationError::ZeroReference);
+        }
+
+        let start_count = SYST::get_current();
+        wait_reference();
+        let ticks = start_count.wrapping_sub(SYST::get_current()) % SYSTICK_RANGE;
+
+        let scale = scale(ticks, reference_us)?;
+        self.set_ticks_per_us(scale);
+        Ok(scale)
+    }
+}
+
+/// Error of [`Delay::calibrate`]
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub enum CalibrationError {
+    /// The reference period is zero
+    ZeroReference,
+    /// Less than half a SysTick tick per microsecond was measured, the reference period was
+    /// shorter than given
+    TooFewTicks,
+}
+
+/// Returns `ticks / reference_us` rounded to the nearest integer
+fn scale(ticks: u32, reference_us: u32) -> Result<u32, CalibrationError> {
+    if reference_us == 0 {
+        return Err(CalibrationError::ZeroReference);
+    }
+    let scale = (u64::from(ticks) + u64::from(reference_us / 2)) / u64::from(reference_us);
+    match scale {
+        0 => Err(CalibrationError::TooFewTicks),
+        // `ticks` is below 2^24, so is the quotient
+        scale => Ok(scale as u32),
+    }
 }
 
 impl DelayMs<u32> for Delay {
@@ -66,7 +142,7 @@ impl DelayUs<u32> for Delay {
         // Here less than maximum is used so we have some play if there's a long running interrupt.
         const MAX_TICKS: u32 = 0x007F_FFFF;
 
-        let mut total_ticks = us * self.scale;
+        let mut total_ticks = us.saturating_mul(self.scale);
 
         while total_ticks != 0 {
             let current_ticks = if total_ticks <= MAX_TICKS {
@@ -96,3 +172,24 @@ impl DelayUs<u8> for Delay {
         self.delay_us(u32(us))
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn scale_rounds_to_nearest() {
+        assert_eq!(scale(48_000, 1_000), Ok(48));
+        assert_eq!(scale(23_600, 1_000), Ok(24));
+        assert_eq!(scale(23_400, 1_000), Ok(23));
+        assert_eq!(scale(SYSTICK_RANGE - 1, 349_525), Ok(48));
+    }
+
+    #[test]
+    fn scale_rejects_short_measurements() {
+        assert_eq!(scale(1, 0), Err(CalibrationError::ZeroReference));
+        assert_eq!(scale(0, 1_000), Err(CalibrationError::TooFewTicks));
+        assert_eq!(scale(499, 1_000), Err(CalibrationError::TooFewTicks));
+        assert_eq!(scale(500, 1_000), Ok(1));
+    }
+}
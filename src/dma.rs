@@ -0,0 +1,322 @@
+//! # Direct Memory Access controller
+//!
+//! The DMA controller has three channels. Unlike on the STM32F0, no channel is hard-wired to a
+//! peripheral: the request each channel serves is selected in `SYSCFG_CFGR3`, see
+//! [`Request`]. Channel 1 has its own interrupt vector, channels 2 and 3 share `DMA_CHANNEL2_3`.
+//!
+//! This module only covers the channel setup. Peripheral drivers build transfers on top of it,
+//! e.g. [`Tx::write_all_dma`](crate::serial::Tx::write_all_dma).
+//!
+//! With the `async` feature, futures waiting on a transfer are woken by
+//! `on_channel1_interrupt` and `on_channel2_3_interrupt`, which the application has to call
+//! from the `DMA_CHANNEL1` and `DMA_CHANNEL2_3` interrupt handlers.
+
+use crate::pac::{DMA, SYSCFG};
+use crate::rcc::{Peripheral, Rcc};
+#[cfg(feature = "async")]
+use crate::waker::WakerCell;
+
+/// Peripheral request served by a channel (`DMAx_MAP`)
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub enum Request {
+    Adc = 0,
+    Spi1Tx = 1,
+    Spi1Rx = 2,
+    Spi2Tx = 3,
+    Spi2Rx = 4,
+    Usart1Tx = 5,
+    Usart1Rx = 6,
+    Usart2Tx = 7,
+    Usart2Rx = 8,
+    I2cTx = 9,
+    I2cRx = 10,
+    Tim1Up = 16,
+    Tim3Up = 21,
+    Tim16Up = 24,
+    Tim17Up = 26,
+}
+
+/// Transfer direction
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub enum Direction {
+    /// Read from the peripheral address, write to memory
+    PeripheralToMemory,
+    /// Read from memory, write to the peripheral address
+    MemoryToPeripheral,
+}
+
+/// Extension trait to split the DMA peripheral into its channels
+pub trait DmaExt {
+    /// Enables the DMA clock and splits the peripheral into independent channels
+    fn split(self, rcc: &mut Rcc) -> Channels;
+}
+
+impl DmaExt for DMA {
+    fn split(self, rcc: &mut Rcc) -> Channels {
+        rcc.enable(Peripheral::Dma);
+        rcc.reset(Peripheral::Dma);
+        // The request mapping lives in SYSCFG
+        rcc.enable(Peripheral::Syscfg);
+
+        Channels {
+            ch1: Channel1 { _0: () },
+            ch2: Channel2 { _0: () },
+            ch3: Channel3 { _0: () },
+        }
+    }
+}
+
+/// DMA channels
+pub struct Channels {
+    pub ch1: Channel1,
+    pub ch2: Channel2,
+    pub ch3: Channel3,
+}
+
+/// DMA channel
+///
+/// Transfers move bytes ([`configure`](Self::configure)) or half-words
+/// ([`configure_u16`](Self::configure_u16)) one at a time between a peripheral register and
+/// memory. Bytes cover the 8 bit data registers of the USARTs and the SPI in 8-bit mode,
+/// half-words the 16 bit timer registers.
+pub trait Channel {
+    /// Selects the peripheral request that triggers the channel
+    fn set_request(&mut self, request: Request);
+
+    /// Configures a transfer of `len` bytes between `peripheral` and `memory`, the channel has to
+    /// be stopped
+    ///
+    /// The peripheral address stays fixed and the memory address is incremented.
+    fn configure(
+        &mut self,
+        peripheral: u32,
+        memory: u32,
+        len: u16,
+        direction: Direction,
+        circular: bool,
+    );
+
+    /// Configures a transfer of `len` half-words between `peripheral` and `memory`, the channel
+    /// has to be stopped
+    ///
+    /// Like [`configure`](Self::configure) with 16 bit accesses on both sides, `memory` has to
+    /// be 2 byte aligned.
+    fn configure_u16(
+        &mut self,
+        peripheral: u32,
+        memory: u32,
+        len: u16,
+        direction: Direction,
+        circular: bool,
+    );
+
+    /// Enables the channel, transfers start with the next request
+    fn start(&mut self);
+
+    /// Disables the channel, aborting a transfer in progress
+    fn stop(&mut self);
+
+    /// Returns the number of bytes left to transfer (`CNDTR`)
+    ///
+    /// In circular mode the counter restarts at the configured length after reaching zero.
+    fn remaining(&self) -> u16;
+
+    /// Returns true if the transfer complete flag is set
+    fn is_complete(&self) -> bool;
+
+    /// Returns true if the half transfer flag is set
+    fn is_half_complete(&self) -> bool;
+
+    /// Returns true if a bus error aborted the transfer, the channel is then disabled
+    fn has_error(&self) -> bool;
+
+    /// Clears all flags of the channel
+    fn clear_flags(&mut self);
+
+    /// Clears the half transfer and transfer complete flags and enables their interrupts
+    ///
+    /// The interrupt still has to be unmasked in the NVIC.
+    fn listen(&mut self);
+
+    /// Disables the half transfer and transfer complete interrupts
+    fn unlisten(&mut self);
+
+    #[doc(hidden)]
+    const INDEX: usize;
+}
+
+macro_rules! channels {
+    ($($CX:ident: ($index:expr, $shift:expr, $dmax_map:ident, $ccrx:ident, $cndtrx:ident, $cparx:ident, $cmarx:ident),)+) => {
+        $(
+            /// DMA channel
+            pub struct $CX {
+                _0: (),
+            }
+
+            impl Channel for $CX {
+                fn set_request(&mut self, request: Request) {
+                    // NOTE(unsafe) CFGR3 holds the mapping of all channels, the
+                    // read-modify-write is done in a critical section
+                    cortex_m::interrupt::free(|_| unsafe {
+                        (*SYSCFG::ptr())
+                            .cfgr3
+                            .modify(|_, w| w.$dmax_map().bits(request as u8))
+                    });
+                }
+
+                fn configure(
+                    &mut self,
+                    peripheral: u32,
+                    memory: u32,
+                    len: u16,
+                    direction: Direction,
+                    circular: bool,
+                ) {
+                    Self::setup(peripheral, memory, len, direction, circular, 0b00);
+                }
+
+                fn configure_u16(
+                    &mut self,
+                    peripheral: u32,
+                    memory: u32,
+                    len: u16,
+                    direction: Direction,
+                    circular: bool,
+                ) {
+                    Self::setup(peripheral, memory, len, direction, circular, 0b01);
+                }
+                fn start(&mut self) {
+                    // NOTE(unsafe) the channel registers are only accessed through this channel
+                    unsafe { (*DMA::ptr()).$ccrx.modify(|_, w| w.en().set_bit()) };
+                }
+
+                fn stop(&mut self) {
+                    // NOTE(unsafe) the channel registers are only accessed through this channel
+                    unsafe { (*DMA::ptr()).$ccrx.modify(|_, w| w.en().clear_bit()) };
+                }
+
+                fn remaining(&self) -> u16 {
+                    // NOTE(unsafe) atomic read with no side effects
+                    unsafe { (*DMA::ptr()).$cndtrx.read().ndt().bits() }
+                }
+
+                fn is_complete(&self) -> bool {
+                    flags($shift) & 0b0010 != 0
+                }
+
+                fn is_half_complete(&self) -> bool {
+                    flags($shift) & 0b0100 != 0
+                }
+
+                fn has_error(&self) -> bool {
+                    flags($shift) & 0b1000 != 0
+                }
+
+                fn clear_flags(&mut self) {
+                    clear($shift, 0b1111);
+                }
+
+                fn listen(&mut self) {
+                    clear($shift, 0b0110);
+                    // NOTE(unsafe) the interrupt handler clears the enables, the
+                    // read-modify-write is done in a critical section
+                    cortex_m::interrupt::free(|_| unsafe {
+                        (*DMA::ptr()).$ccrx.modify(|_, w| w.htie().set_bit().tcie().set_bit())
+                    });
+                }
+
+                fn unlisten(&mut self) {
+                    // NOTE(unsafe) see `listen`
+                    cortex_m::interrupt::free(|_| unsafe {
+                        (*DMA::ptr()).$ccrx.modify(|_, w| w.htie().clear_bit().tcie().clear_bit())
+                    });
+                }
+
+                const INDEX: usize = $index;
+            }
+
+            impl $CX {
+                /// Programs a transfer with `size` (`PSIZE`/`MSIZE`) accesses on both sides
+                fn setup(
+                    peripheral: u32,
+                    memory: u32,
+                    len: u16,
+                    direction: Direction,
+                    circular: bool,
+                    size: u8,
+                ) {
+                    // NOTE(unsafe) the channel registers are only accessed through this channel
+                    let dma = unsafe { &*DMA::ptr() };
+                    dma.$cparx.write(|w| unsafe { w.pa().bits(peripheral) });
+                    dma.$cmarx.write(|w| unsafe { w.ma().bits(memory) });
+                    dma.$cndtrx.write(|w| unsafe { w.ndt().bits(len) });
+                    // same access size on both sides, memory increment
+                    dma.$ccrx.write(|w| unsafe {
+                        w.psize()
+                            .bits(size)
+                            .msize()
+                            .bits(size)
+                            .minc()
+                            .set_bit()
+                            .pinc()
+                            .clear_bit()
+                            .circ()
+                            .bit(circular)
+                            .dir()
+                            .bit(direction == Direction::MemoryToPeripheral)
+                    });
+                }
+            }
+
+            #[cfg(feature = "async")]
+            impl $CX {
+                fn on_interrupt() {
+                    // NOTE(unsafe) only the interrupt enables are modified, which `listen` does
+                    // in a critical section
+                    let dma = unsafe { &*DMA::ptr() };
+                    let ccr = dma.$ccrx.read();
+                    let listening = ccr.htie().bit_is_set() || ccr.tcie().bit_is_set();
+                    if listening && flags($shift) & 0b0110 != 0 {
+                        dma.$ccrx.modify(|_, w| w.htie().clear_bit().tcie().clear_bit());
+                        WAKERS[$index].wake();
+                    }
+                }
+            }
+        )+
+    };
+}
+
+channels! {
+    Channel1: (0, 0, dma1_map, ccr1, cndtr1, cpar1, cmar1),
+    Channel2: (1, 4, dma2_map, ccr2, cndtr2, cpar2, cmar2),
+    Channel3: (2, 8, dma3_map, ccr3, cndtr3, cpar3, cmar3),
+}
+
+#[cfg(feature = "async")]
+pub(crate) static WAKERS: [WakerCell; 3] = [WakerCell::new(), WakerCell::new(), WakerCell::new()];
+
+/// Wakes the task waiting on channel 1, call this from the `DMA_CHANNEL1` interrupt handler
+#[cfg(feature = "async")]
+pub fn on_channel1_interrupt() {
+    Channel1::on_interrupt();
+}
+
+/// Wakes the tasks waiting on channels 2 and 3, call this from the `DMA_CHANNEL2_3` interrupt
+/// handler
+#[cfg(feature = "async")]
+pub fn on_channel2_3_interrupt() {
+    Channel2::on_interrupt();
+    Channel3::on_interrupt();
+}
+
+/// Returns the four flags of the channel whose flags start at bit `shift` of ISR
+fn flags(shift: u32) -> u32 {
+    // NOTE(unsafe) atomic read with no side effects
+    unsafe { ((*DMA::ptr()).isr.read().bits() >> shift) & 0b1111 }
+}
+
+/// Clears the `flags` of the channel whose flags start at bit `shift` of IFCR
+fn clear(shift: u32, flags: u32) {
+    // NOTE(unsafe) IFCR is write-one-to-clear, only this channel's bits are set
+    unsafe { (*DMA::ptr()).ifcr.write(|w| w.bits(flags << shift)) };
+}
@@ -0,0 +1,143 @@
+//! # External interrupt/event controller
+//!
+//! The GPIO lines 0 to 15 share three interrupt vectors: `EXTI0_1`, `EXTI2_3` and `EXTI4_15`.
+//! Lines 0 to 4 can be connected to a pin of any port, lines 5 to 8 to a pin of GPIOA or GPIOB,
+//! and lines 9 to 15 are fixed to GPIOA.
+//! [`ExtiLine::interrupt`] returns the vector of a line, to unmask it and set its priority in the
+//! NVIC. Keep in mind that all lines sharing a vector also share its priority.
+
+use cortex_m::peripheral::NVIC;
+
+use crate::pac::{Interrupt, EXTI};
+
+/// Edges an EXTI line triggers on
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub enum SignalEdge {
+    /// Rising edge
+    Rising,
+    /// Falling edge
+    Falling,
+    /// Both rising and falling edges
+    Both,
+}
+
+/// EXTI line
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub struct ExtiLine(u8);
+
+impl ExtiLine {
+    /// PVD output
+    pub const PVD: ExtiLine = ExtiLine(16);
+    /// Comparator 1 output
+    pub const COMP1: ExtiLine = ExtiLine(17);
+    /// Comparator 2 output
+    pub const COMP2: ExtiLine = ExtiLine(18);
+
+    /// GPIO line `line` (0 to 15), shared by the pins with the same number on every port
+    pub fn gpio(line: u8) -> Self {
+        assert!(line < 16);
+        ExtiLine(line)
+    }
+
+    /// Returns the line number
+    pub fn number(&self) -> u8 {
+        self.0
+    }
+
+    /// Returns the interrupt vector the line is routed to
+    pub fn interrupt(&self) -> Interrupt {
+        match self.0 {
+            0..=1 => Interrupt::EXTI0_1,
+            2..=3 => Interrupt::EXTI2_3,
+            4..=15 => Interrupt::EXTI4_15,
+            16 => Interrupt::PVD,
+            _ => Interrupt::ADC_COMP,
+        }
+    }
+
+    /// Unmasks the interrupt vector of the line in the NVIC
+    ///
+    /// # Safety
+    ///
+    /// See [`NVIC::unmask`], this can break mask based critical sections.
+    pub unsafe fn unmask(&self) {
+        NVIC::unmask(self.interrupt())
+    }
+
+    /// Sets the priority of the interrupt vector of the line
+    ///
+    /// This changes the priority of every other line on the same vector as well.
+    ///
+    /// # Safety
+    ///
+    /// See [`NVIC::set_priority`], this can break priority based critical sections.
+    pub unsafe fn set_priority(&self, nvic: &mut NVIC, priority: u8) {
+        nvic.set_priority(self.interrupt(), priority)
+    }
+
+    fn mask(&self) -> u32 {
+        1 << self.0
+    }
+}
+
+/// Extension trait to configure the EXTI lines
+pub trait ExtiExt {
+    /// Unmasks the interrupt of `line` and triggers it on `edge`
+    fn listen(&self, line: ExtiLine, edge: SignalEdge);
+    /// Masks the interrupt of `line`
+    fn unlisten(&self, line: ExtiLine);
+    /// Returns true if `line` has a pending interrupt
+    fn is_pending(&self, line: ExtiLine) -> bool;
+    /// Clears the pending interrupt of `line`
+    fn unpend(&self, line: ExtiLine);
+    /// Sets `line` pending from software through SWIER
+    ///
+    /// If the line's interrupt is unmasked the ISR runs as if the configured edge had occurred,
+    /// which allows the interrupt path to be exercised without driving the pin.
+    fn trigger_software(&self, line: ExtiLine);
+}
+
+impl ExtiExt for EXTI {
+    fn listen(&self, line: ExtiLine, edge: SignalEdge) {
+        let mask = line.mask();
+        let rising = matches!(edge, SignalEdge::Rising | SignalEdge::Both);
+        let falling = matches!(edge, SignalEdge::Falling | SignalEdge::Both);
+        // NOTE(unsafe) only the bit of `line` is changed
+        unsafe {
+            self.rtsr.modify(|r, w| {
+                w.bits(if rising {
+                    r.bits() | mask
+                } else {
+                    r.bits() & !mask
+                })
+            });
+            self.ftsr.modify(|r, w| {
+                w.bits(if falling {
+                    r.bits() | mask
+                } else {
+                    r.bits() & !mask
+                })
+            });
+            self.imr.modify(|r, w| w.bits(r.bits() | mask));
+        }
+    }
+
+    fn unlisten(&self, line: ExtiLine) {
+        // NOTE(unsafe) only the bit of `line` is changed
+        unsafe { self.imr.modify(|r, w| w.bits(r.bits() & !line.mask())) };
+    }
+
+    fn is_pending(&self, line: ExtiLine) -> bool {
+        self.pr.read().bits() & line.mask() != 0
+    }
+
+    fn unpend(&self, line: ExtiLine) {
+        // NOTE(unsafe) write 1 to clear, zeros have no effect
+        unsafe { self.pr.write(|w| w.bits(line.mask())) };
+    }
+
+    fn trigger_software(&self, line: ExtiLine) {
+        // NOTE(unsafe) zeros have no effect, the bit is cleared with the pending flag
+        unsafe { self.swier.write(|w| w.bits(line.mask())) };
+    }
+}
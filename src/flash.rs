@@ -0,0 +1,324 @@
+//! # Flash memory interface
+//!
+//! ## Main memory
+//!
+//! [`Flash`] erases and programs the 32 KiB main flash in 128-byte pages, the smallest unit of
+//! both operations. A page must be erased before it is programmed again, which is checked, and
+//! programming always writes a whole aligned page. With the `embedded-storage` feature, `Flash`
+//! implements `NorFlash` with these sizes.
+//!
+//! The CPU stalls while the flash is busy, interrupts are disabled while a page is loaded. The
+//! programming timing registers (`TS0` to `PRETPE`) are left at the values loaded at reset.
+//!
+//! ## Option bytes
+//!
+//! The user option bytes select the brown-out reset level, whether the watchdogs are started by
+//! hardware, the function of the NRST pin and the read protection level. They are copied to
+//! `FLASH_OPTR` at reset; [`OptionBytes::read`] decodes the currently loaded values.
+//!
+//! Programming them with [`program_option_bytes`] is unsafe: a wrong value can disable the debug
+//! port (read protection), turn the reset pin into a GPIO or make the device reset continuously
+//! (brown-out level above the supply voltage, hardware watchdog with a short timeout). The new
+//! values only take effect after they are reloaded, which resets the device.
+
+use core::ptr;
+
+use crate::pac::FLASH;
+
+/// Address of the first byte of main flash
+pub const FLASH_START: u32 = 0x0800_0000;
+/// Size of the main flash in bytes
+pub const FLASH_SIZE: u32 = 32 * 1024;
+/// Size of a page, the unit of erasing and programming
+pub const PAGE_SIZE: u32 = 128;
+
+const KEY1: u32 = 0x4567_0123;
+const KEY2: u32 = 0xCDEF_89AB;
+const OPTKEY1: u32 = 0x0819_2A3B;
+const OPTKEY2: u32 = 0x4C5D_6E7F;
+
+/// Any word written here starts the option byte programming once `OPTSTRT` is set
+const OPTION_TRIGGER: *mut u32 = 0x4002_2080 as *mut u32;
+
+/// Read protection level 0, any other value is level 1
+pub const RDP_LEVEL_0: u8 = 0xAA;
+
+/// Flash error
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[non_exhaustive]
+pub enum Error {
+    /// The offset or length is not a multiple of [`PAGE_SIZE`]
+    Alignment,
+    /// The range extends past the end of the main flash
+    OutOfBounds,
+    /// The page is write protected (`WRPERR`)
+    WriteProtected,
+    /// The page has to be erased before it is programmed
+    NotErased,
+}
+
+/// Main flash erase and program driver
+pub struct Flash {
+    flash: FLASH,
+}
+
+impl Flash {
+    pub fn new(flash: FLASH) -> Self {
+        Flash { flash }
+    }
+
+    /// Releases the FLASH peripheral
+    pub fn release(self) -> FLASH {
+        self.flash
+    }
+
+    /// Copies flash contents starting at `offset` (from [`FLASH_START`]) into `bytes`
+    pub fn read(&self, offset: u32, bytes: &mut [u8]) -> Result<(), Error> {
+        check_bounds(offset, bytes.len())?;
+        for (i, byte) in bytes.iter_mut().enumerate() {
+            //NOTE(unsafe) the range is within the main flash
+            *byte = unsafe { ptr::read_volatile((FLASH_START + offset + i as u32) as *const u8) };
+        }
+        Ok(())
+    }
+
+    /// Erases the page at `offset`, which has to be a multiple of [`PAGE_SIZE`]
+    pub fn erase_page(&mut self, offset: u32) -> Result<(), Error> {
+        check_page(offset)?;
+        self.unlock();
+        self.flash.cr.modify(|_, w| w.per().set_bit());
+        // Any write into the page starts the erase
+        //NOTE(unsafe) the address is a word within the main flash
+        unsafe { ptr::write_volatile((FLASH_START + offset) as *mut u32, 0xFFFF_FFFF) };
+        let result = self.wait();
+        self.flash.cr.modify(|_, w| w.per().clear_bit());
+        self.lock();
+        result
+    }
+
+    /// Erases all pages in `from..to`, both have to be multiples of [`PAGE_SIZE`]
+    pub fn erase(&mut self, from: u32, to: u32) -> Result<(), Error> {
+        if from > to {
+            return Err(Error::OutOfBounds);
+        }
+        check_page(from)?;
+        check_bounds(from, (to - from) as usize)?;
+        if !to.is_multiple_of(PAGE_SIZE) {
+            return Err(Error::Alignment);
+        }
+        for page in (from..to).step_by(PAGE_SIZE as usize) {
+            self.erase_page(page)?;
+        }
+        Ok(())
+    }
+
+    /// Programs the erased page at `offset` with `data`
+    pub fn program_page(&mut self, offset: u32, data: &[u32; 32]) -> Result<(), Error> {
+        check_page(offset)?;
+        let page = (FLASH_START + offset) as *mut u32;
+        //NOTE(unsafe) the page is within the main flash
+        if (0..32).any(|i| unsafe { ptr::read_volatile(page.add(i)) } != 0xFFFF_FFFF) {
+            return Err(Error::NotErased);
+        }
+
+        self.unlock();
+        self.flash.cr.modify(|_, w| w.pg().set_bit());
+        // The page buffer is written word by word, PGSTRT has to be set before the last word
+        cortex_m::interrupt::free(|_| {
+            for (i, word) in data.iter().enumerate() {
+                if i == 31 {
+                    self.flash.cr.modify(|_, w| w.pgtstrt().set_bit());
+                }
+                //NOTE(unsafe) the page is within the main flash
+                unsafe { ptr::write_volatile(page.add(i), *word) };
+            }
+        });
+        let result = self.wait();
+        self.flash.cr.modify(|_, w| w.pg().clear_bit());
+        self.lock();
+        result
+    }
+
+    fn unlock(&mut self) {
+        while self.flash.sr.read().bsy().bit_is_set() {}
+        if self.flash.cr.read().lock().bit_is_set() {
+            self.flash.keyr.write(|w| unsafe { w.key().bits(KEY1) });
+            self.flash.keyr.write(|w| unsafe { w.key().bits(KEY2) });
+        }
+        // Clear stale flags
+        self.flash
+            .sr
+            .write(|w| w.eop().set_bit().wrperr().set_bit());
+    }
+
+    fn lock(&mut self) {
+        self.flash.cr.modify(|_, w| w.lock().set_bit());
+    }
+
+    fn wait(&mut self) -> Result<(), Error> {
+        while self.flash.sr.read().bsy().bit_is_set() {}
+        let sr = self.flash.sr.read();
+        self.flash
+            .sr
+            .write(|w| w.eop().set_bit().wrperr().set_bit());
+        if sr.wrperr().bit_is_set() {
+            Err(Error::WriteProtected)
+        } else {
+            Ok(())
+        }
+    }
+}
+
+fn check_bounds(offset: u32, len: usize) -> Result<(), Error> {
+    match offset.checked_add(len as u32) {
+        Some(end) if end <= FLASH_SIZE => Ok(()),
+        _ => Err(Error::OutOfBounds),
+    }
+}
+
+fn check_page(offset: u32) -> Result<(), Error> {
+    if !offset.is_multiple_of(PAGE_SIZE) {
+        return Err(Error::Alignment);
+    }
+    check_bounds(offset, PAGE_SIZE as usize)
+}
+
+#[cfg(feature = "embedded-storage")]
+mod storage {
+    use embedded_storage::nor_flash::{
+        ErrorType, NorFlash, NorFlashError, NorFlashErrorKind, ReadNorFlash,
+    };
+
+    use super::{Error, Flash, FLASH_SIZE, PAGE_SIZE};
+
+    impl NorFlashError for Error {
+        fn kind(&self) -> NorFlashErrorKind {
+            match self {
+                Error::Alignment => NorFlashErrorKind::NotAligned,
+                Error::OutOfBounds => NorFlashErrorKind::OutOfBounds,
+                _ => NorFlashErrorKind::Other,
+            }
+        }
+    }
+
+    impl ErrorType for Flash {
+        type Error = Error;
+    }
+
+    impl ReadNorFlash for Flash {
+        const READ_SIZE: usize = 1;
+
+        fn read(&mut self, offset: u32, bytes: &mut [u8]) -> Result<(), Error> {
+            Flash::read(self, offset, bytes)
+        }
+
+        fn capacity(&self) -> usize {
+            FLASH_SIZE as usize
+        }
+    }
+
+    impl NorFlash for Flash {
+        const WRITE_SIZE: usize = PAGE_SIZE as usize;
+        const ERASE_SIZE: usize = PAGE_SIZE as usize;
+
+        fn erase(&mut self, from: u32, to: u32) -> Result<(), Error> {
+            Flash::erase(self, from, to)
+        }
+
+        fn write(&mut self, offset: u32, bytes: &[u8]) -> Result<(), Error> {
+            if !bytes.len().is_multiple_of(Self::WRITE_SIZE) {
+                return Err(Error::Alignment);
+            }
+            for (i, page) in bytes.chunks_exact(Self::WRITE_SIZE).enumerate() {
+                let mut data = [0u32; 32];
+                for (word, chunk) in data.iter_mut().zip(page.chunks_exact(4)) {
+                    *word = u32::from_le_bytes([chunk[0], chunk[1], chunk[2], chunk[3]]);
+                }
+                self.program_page(offset + (i * Self::WRITE_SIZE) as u32, &data)?;
+            }
+            Ok(())
+        }
+    }
+}
+
+/// Decoded user option bytes (`FLASH_OPTR`)
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub struct OptionBytes {
+    /// Read protection, [`RDP_LEVEL_0`] leaves the flash readable by a debugger
+    pub read_protection: u8,
+    /// Brown-out reset enabled
+    pub bor_enable: bool,
+    /// Brown-out reset threshold, `0` (lowest) to `7` (highest), see the datasheet for voltages
+    pub bor_level: u8,
+    /// The independent watchdog is started by software (`false`: by hardware after each reset)
+    pub iwdg_software: bool,
+    /// The window watchdog is started by software (`false`: by hardware after each reset)
+    pub wwdg_software: bool,
+    /// The NRST pin is a GPIO instead of the reset input
+    pub nrst_gpio: bool,
+    /// Boot configuration bit `nBOOT1`
+    pub n_boot1: bool,
+}
+
+impl OptionBytes {
+    /// Returns the option bytes loaded at the last reset
+    pub fn read(flash: &FLASH) -> Self {
+        let optr = flash.optr.read();
+        OptionBytes {
+            read_protection: optr.rdp().bits(),
+            bor_enable: optr.boren().bit_is_set(),
+            bor_level: optr.borf_lev().bits(),
+            iwdg_software: optr.idwg_sw().bit_is_set(),
+            wwdg_software: optr.wwdg_sw().bit_is_set(),
+            nrst_gpio: optr.nrst_mode().bit_is_set(),
+            n_boot1: optr.n_boot1().bit_is_set(),
+        }
+    }
+
+    fn bits(&self) -> u32 {
+        u32::from(self.read_protection)
+            | (u32::from(self.bor_enable) << 8)
+            | (u32::from(self.bor_level & 0b111) << 9)
+            | (u32::from(self.iwdg_software) << 12)
+            | (u32::from(self.wwdg_software) << 13)
+            | (u32::from(self.nrst_gpio) << 14)
+            | (u32::from(self.n_boot1) << 15)
+    }
+}
+
+/// Programs the user option bytes and resets the device to load them
+///
+/// The flash and option locks are opened, `options` is written and the option byte loading is
+/// forced, which triggers a system reset. This function never returns.
+///
+/// # Safety
+///
+/// The values are applied without validation. Setting `read_protection` to anything but
+/// [`RDP_LEVEL_0`] disconnects the debugger, and going back to level 0 mass erases the flash.
+/// A brown-out level above the supply voltage or a hardware watchdog that the application does
+/// not feed in time keeps the device in a reset loop. With `nrst_gpio` set, the NRST pin can no
+/// longer reset the device. Interrupts must not access the flash while programming.
+pub unsafe fn program_option_bytes(flash: &mut FLASH, options: &OptionBytes) -> ! {
+    while flash.sr.read().bsy().bit_is_set() {}
+
+    if flash.cr.read().lock().bit_is_set() {
+        flash.keyr.write(|w| w.key().bits(KEY1));
+        flash.keyr.write(|w| w.key().bits(KEY2));
+    }
+    if flash.cr.read().optlock().bit_is_set() {
+        flash.optkeyr.write(|w| w.optkey().bits(OPTKEY1));
+        flash.optkeyr.write(|w| w.optkey().bits(OPTKEY2));
+    }
+
+    flash.optr.write(|w| w.bits(options.bits()));
+    flash.cr.modify(|_, w| w.optstrt().set_bit());
+    core::ptr::write_volatile(OPTION_TRIGGER, 0xFFFF_FFFF);
+    while flash.sr.read().bsy().bit_is_set() {}
+    flash.sr.write(|w| w.eop().set_bit());
+
+    // Reloading the option bytes resets the device
+    flash.cr.modify(|_, w| w.obl_launch().set_bit());
+    loop {
+        cortex_m::asm::nop();
+    }
+}
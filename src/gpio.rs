@@ -1,4 +1,4 @@
-use core::{convert::Infallible, marker::PhantomData};
+use core::{convert::Infallible, fmt, marker::PhantomData};
 
 use crate::rcc::Rcc;
 
@@ -16,6 +16,35 @@ trait GpioRegExt {
     fn is_set_low(&self, pos: u8) -> bool;
     fn set_high(&self, pos: u8);
     fn set_low(&self, pos: u8);
+    fn mode(&self, pos: u8) -> PinMode;
+}
+
+/// Pull resistor configuration as read back from PUPDR
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub enum Pull {
+    /// No pull resistor
+    Floating,
+    /// Pull-up resistor
+    Up,
+    /// Pull-down resistor
+    Down,
+}
+
+/// Pin configuration as read back from the MODER, OTYPER, PUPDR and AFR registers
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub enum PinMode {
+    /// Digital input
+    Input(Pull),
+    /// General purpose output
+    Output { open_drain: bool, pull: Pull },
+    /// Alternate function `af`
+    Alternate {
+        af: u8,
+        open_drain: bool,
+        pull: Pull,
+    },
+    /// Analog mode
+    Analog,
 }
 
 /// Alternate function 0
@@ -84,6 +113,176 @@ pub struct Output<MODE> {
 /// Push pull output (type state)
 pub struct PushPull;
 
+/// Mode chosen at runtime through a [`PortConfig`] (type state)
+pub struct Configured;
+
+/// Pins a peripheral driver returns to analog mode when it is released
+///
+/// Implemented by every pin, and by `()` for drivers created without one of their pins.
+pub trait Deinit {
+    /// The pin in analog mode
+    type Analog;
+
+    /// Puts the pin in analog mode without pulls, its lowest power state
+    fn deinit(self, cs: &cortex_m::interrupt::CriticalSection) -> Self::Analog;
+}
+
+impl Deinit for () {
+    type Analog = ();
+
+    fn deinit(self, _cs: &cortex_m::interrupt::CriticalSection) {}
+}
+
+/// Printable name of a pin mode type state, used by the `Debug` impls of the pins
+pub trait PinModeName {
+    /// Name of the mode, e.g. `Output<PushPull>`
+    const NAME: &'static str;
+}
+
+macro_rules! mode_names {
+    ($($MODE:ty,)+) => {
+        $(
+            impl PinModeName for $MODE {
+                const NAME: &'static str = stringify!($MODE);
+            }
+        )+
+    };
+}
+
+mode_names! {
+    Input<Floating>,
+    Input<PullDown>,
+    Input<PullUp>,
+    Output<PushPull>,
+    Output<OpenDrain>,
+    Analog,
+    Configured,
+    Alternate<AF0>,
+    Alternate<AF1>,
+    Alternate<AF2>,
+    Alternate<AF3>,
+    Alternate<AF4>,
+    Alternate<AF5>,
+    Alternate<AF6>,
+    Alternate<AF7>,
+    Alternate<AF8>,
+    Alternate<AF9>,
+    Alternate<AF10>,
+    Alternate<AF11>,
+    Alternate<AF12>,
+    Alternate<AF13>,
+    Alternate<AF14>,
+    Alternate<AF15>,
+}
+
+/// Configuration of a whole GPIO port, applied with one write per register
+///
+/// Pins that are not selected as output, alternate or analog are configured as inputs. The
+/// builder methods can be chained; a later call overrides earlier ones for the pins in its mask.
+#[derive(Clone, Copy, Default, Debug)]
+pub struct PortConfig {
+    output: u16,
+    alternate: u16,
+    analog: u16,
+    open_drain: u16,
+    pull_up: u16,
+    pull_down: u16,
+    af: u64,
+}
+
+impl PortConfig {
+    /// Creates a configuration with every pin as floating input
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Configures the pins in `mask` as general purpose outputs
+    pub fn outputs(mut self, mask: u16) -> Self {
+        self.output |= mask;
+        self.alternate &= !mask;
+        self.analog &= !mask;
+        self
+    }
+
+    /// Configures the pins in `mask` as digital inputs
+    pub fn inputs(mut self, mask: u16) -> Self {
+        self.output &= !mask;
+        self.alternate &= !mask;
+        self.analog &= !mask;
+        self
+    }
+
+    /// Configures the pins in `mask` to alternate function `af`
+    pub fn alternate(mut self, mask: u16, af: u8) -> Self {
+        self.output &= !mask;
+        self.alternate |= mask;
+        self.analog &= !mask;
+        for i in 0..16 {
+            if mask & (1 << i) != 0 {
+                let offset = 4 * i;
+                self.af = (self.af & !(0b1111 << offset)) | (u64::from(af & 0b1111) << offset);
+            }
+        }
+        self
+    }
+
+    /// Configures the pins in `mask` as analog
+    pub fn analog(mut self, mask: u16) -> Self {
+        self.output &= !mask;
+        self.alternate &= !mask;
+        self.analog |= mask;
+        self
+    }
+
+    /// Makes the outputs in `mask` open drain instead of push pull
+    pub fn open_drain(mut self, mask: u16) -> Self {
+        self.open_drain |= mask;
+        self
+    }
+
+    /// Enables the internal pull up for the pins in `mask`
+    pub fn pull_up(mut self, mask: u16) -> Self {
+        self.pull_up |= mask;
+        self.pull_down &= !mask;
+        self
+    }
+
+    /// Enables the internal pull down for the pins in `mask`
+    pub fn pull_down(mut self, mask: u16) -> Self {
+        self.pull_up &= !mask;
+        self.pull_down |= mask;
+        self
+    }
+
+    fn moder(&self) -> u32 {
+        (0..16).fold(0, |moder, i| {
+            let mode = if self.output & (1 << i) != 0 {
+                0b01
+            } else if self.alternate & (1 << i) != 0 {
+                0b10
+            } else if self.analog & (1 << i) != 0 {
+                0b11
+            } else {
+                0b00
+            };
+            moder | (mode << (2 * i))
+        })
+    }
+
+    fn pupdr(&self) -> u32 {
+        (0..16).fold(0, |pupdr, i| {
+            let pull = if self.pull_up & (1 << i) != 0 {
+                0b01
+            } else if self.pull_down & (1 << i) != 0 {
+                0b10
+            } else {
+                0b00
+            };
+            pupdr | (pull << (2 * i))
+        })
+    }
+}
+
 use embedded_hal::digital::v2::{toggleable, InputPin, OutputPin, StatefulOutputPin};
 
 /// Fully erased pin
@@ -99,6 +298,55 @@ unsafe impl<MODE> Sync for Pin<MODE> {}
 // threads
 unsafe impl<MODE> Send for Pin<MODE> {}
 
+impl<MODE> Pin<MODE> {
+    fn port_name(&self) -> &'static str {
+        let port = self.port as *const () as usize;
+        if port == crate::pac::GPIOA::ptr() as usize {
+            "PA"
+        } else if port == crate::pac::GPIOB::ptr() as usize {
+            "PB"
+        } else {
+            "PF"
+        }
+    }
+}
+
+impl<MODE: PinModeName> fmt::Debug for Pin<MODE> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}{}<{}>", self.port_name(), self.i, MODE::NAME)
+    }
+}
+
+#[cfg(feature = "defmt")]
+impl<MODE: PinModeName> defmt::Format for Pin<MODE> {
+    fn format(&self, f: defmt::Formatter) {
+        defmt::write!(
+            f,
+            "{=str}{=u8}<{=str}>",
+            self.port_name(),
+            self.i,
+            MODE::NAME
+        )
+    }
+}
+
+impl<MODE> Pin<MODE> {
+    /// Reads back the current configuration of the pin from the port registers
+    pub fn mode(&self) -> PinMode {
+        unsafe { (*self.port).mode(self.i) }
+    }
+
+    /// Returns true if the pin is configured as a general purpose output
+    pub fn is_output(&self) -> bool {
+        matches!(self.mode(), PinMode::Output { .. })
+    }
+
+    /// Returns true if the pin is configured as a digital input
+    pub fn is_input(&self) -> bool {
+        matches!(self.mode(), PinMode::Input(_))
+    }
+}
+
 impl<MODE> StatefulOutputPin for Pin<Output<MODE>> {
     #[inline(always)]
     fn is_set_high(&self) -> Result<bool, Self::Error> {
@@ -157,6 +405,188 @@ impl<MODE> InputPin for Pin<Input<MODE>> {
     }
 }
 
+impl StatefulOutputPin for Pin<Configured> {
+    #[inline(always)]
+    fn is_set_high(&self) -> Result<bool, Self::Error> {
+        self.is_set_low().map(|v| !v)
+    }
+
+    #[inline(always)]
+    fn is_set_low(&self) -> Result<bool, Self::Error> {
+        Ok(unsafe { (*self.port).is_set_low(self.i) })
+    }
+}
+
+impl OutputPin for Pin<Configured> {
+    type Error = Infallible;
+
+    #[inline(always)]
+    fn set_high(&mut self) -> Result<(), Self::Error> {
+        unsafe { (*self.port).set_high(self.i) };
+        Ok(())
+    }
+
+    #[inline(always)]
+    fn set_low(&mut self) -> Result<(), Self::Error> {
+        unsafe { (*self.port).set_low(self.i) }
+        Ok(())
+    }
+}
+
+impl toggleable::Default for Pin<Configured> {}
+
+impl InputPin for Pin<Configured> {
+    type Error = Infallible;
+
+    #[inline(always)]
+    fn is_high(&self) -> Result<bool, Self::Error> {
+        self.is_low().map(|v| !v)
+    }
+
+    #[inline(always)]
+    fn is_low(&self) -> Result<bool, Self::Error> {
+        Ok(unsafe { (*self.port).is_low(self.i) })
+    }
+}
+
+#[cfg(feature = "embedded-hal-1")]
+impl<MODE> embedded_hal_1::digital::ErrorType for Pin<MODE> {
+    type Error = Infallible;
+}
+
+#[cfg(feature = "embedded-hal-1")]
+impl<MODE> embedded_hal_1::digital::OutputPin for Pin<Output<MODE>> {
+    #[inline(always)]
+    fn set_high(&mut self) -> Result<(), Self::Error> {
+        unsafe { (*self.port).set_high(self.i) };
+        Ok(())
+    }
+
+    #[inline(always)]
+    fn set_low(&mut self) -> Result<(), Self::Error> {
+        unsafe { (*self.port).set_low(self.i) };
+        Ok(())
+    }
+}
+
+// `toggle` is provided by `StatefulOutputPin` in embedded-hal 1.0
+#[cfg(feature = "embedded-hal-1")]
+impl<MODE> embedded_hal_1::digital::StatefulOutputPin for Pin<Output<MODE>> {
+    #[inline(always)]
+    fn is_set_high(&mut self) -> Result<bool, Self::Error> {
+        Ok(!unsafe { (*self.port).is_set_low(self.i) })
+    }
+
+    #[inline(always)]
+    fn is_set_low(&mut self) -> Result<bool, Self::Error> {
+        Ok(unsafe { (*self.port).is_set_low(self.i) })
+    }
+}
+
+#[cfg(feature = "embedded-hal-1")]
+impl embedded_hal_1::digital::InputPin for Pin<Output<OpenDrain>> {
+    #[inline(always)]
+    fn is_high(&mut self) -> Result<bool, Self::Error> {
+        Ok(!unsafe { (*self.port).is_low(self.i) })
+    }
+
+    #[inline(always)]
+    fn is_low(&mut self) -> Result<bool, Self::Error> {
+        Ok(unsafe { (*self.port).is_low(self.i) })
+    }
+}
+
+#[cfg(feature = "embedded-hal-1")]
+impl<MODE> embedded_hal_1::digital::InputPin for Pin<Input<MODE>> {
+    #[inline(always)]
+    fn is_high(&mut self) -> Result<bool, Self::Error> {
+        Ok(!unsafe { (*self.port).is_low(self.i) })
+    }
+
+    #[inline(always)]
+    fn is_low(&mut self) -> Result<bool, Self::Error> {
+        Ok(unsafe { (*self.port).is_low(self.i) })
+    }
+}
+
+/// Level change seen by [`EdgeDetector::poll`]
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub enum Edge {
+    /// Low to high
+    Rising,
+    /// High to low
+    Falling,
+}
+
+/// Software edge detection for polled inputs, e.g. buttons
+///
+/// Each [`poll`](Self::poll) compares the pin level against the previous one and counts the
+/// edges. Pulses shorter than the polling interval are missed, and a bouncing contact is counted
+/// several times; use EXTI for the former and debounce for the latter.
+pub struct EdgeDetector<PIN> {
+    pin: PIN,
+    level: bool,
+    rising: u32,
+    falling: u32,
+}
+
+impl<PIN: InputPin> EdgeDetector<PIN> {
+    /// Starts from the current pin level, without counting an edge
+    pub fn new(pin: PIN) -> Result<Self, PIN::Error> {
+        let level = pin.is_high()?;
+        Ok(EdgeDetector {
+            pin,
+            level,
+            rising: 0,
+            falling: 0,
+        })
+    }
+
+    /// Samples the pin and returns the edge since the last poll, if any
+    pub fn poll(&mut self) -> Result<Option<Edge>, PIN::Error> {
+        let level = self.pin.is_high()?;
+        let edge = match (self.level, level) {
+            (false, true) => {
+                self.rising = self.rising.wrapping_add(1);
+                Some(Edge::Rising)
+            }
+            (true, false) => {
+                self.falling = self.falling.wrapping_add(1);
+                Some(Edge::Falling)
+            }
+            _ => None,
+        };
+        self.level = level;
+        Ok(edge)
+    }
+
+    /// Returns the number of rising edges seen since creation or the last [`clear`](Self::clear)
+    pub fn rising_edges(&self) -> u32 {
+        self.rising
+    }
+
+    /// Returns the number of falling edges seen since creation or the last [`clear`](Self::clear)
+    pub fn falling_edges(&self) -> u32 {
+        self.falling
+    }
+
+    /// Returns the level sampled by the last poll, true if high
+    pub fn level(&self) -> bool {
+        self.level
+    }
+
+    /// Resets the edge counters
+    pub fn clear(&mut self) {
+        self.rising = 0;
+        self.falling = 0;
+    }
+
+    /// Releases the pin
+    pub fn release(self) -> PIN {
+        self.pin
+    }
+}
+
 macro_rules! gpio_trait {
     ($gpiox:ident) => {
         impl GpioRegExt for crate::pac::$gpiox::RegisterBlock {
@@ -179,6 +609,34 @@ macro_rules! gpio_trait {
                 // NOTE(unsafe) atomic write to a stateless register
                 unsafe { self.bsrr.write(|w| w.bits(1 << (pos + 16))) }
             }
+
+            fn mode(&self, pos: u8) -> PinMode {
+                let offset = 2 * pos;
+                let pull = match (self.pupdr.read().bits() >> offset) & 0b11 {
+                    0b01 => Pull::Up,
+                    0b10 => Pull::Down,
+                    _ => Pull::Floating,
+                };
+                let open_drain = self.otyper.read().bits() & (1 << pos) != 0;
+
+                match (self.moder.read().bits() >> offset) & 0b11 {
+                    0b00 => PinMode::Input(pull),
+                    0b01 => PinMode::Output { open_drain, pull },
+                    0b10 => {
+                        let af = if pos < 8 {
+                            self.afrl.read().bits() >> (4 * pos)
+                        } else {
+                            self.afrh.read().bits() >> (4 * (pos - 8))
+                        };
+                        PinMode::Alternate {
+                            af: (af & 0b1111) as u8,
+                            open_drain,
+                            pull,
+                        }
+                    }
+                    _ => PinMode::Analog,
+                }
+            }
         }
     };
 }
@@ -187,7 +645,7 @@ gpio_trait!(gpioa);
 gpio_trait!(gpiob);
 
 macro_rules! gpio {
-    ([$($GPIOX:ident, $gpiox:ident, $iopxenr:ident, $PXx:ident, [
+    ([$($GPIOX:ident, $gpiox:ident, $PER:ident, $PXx:ident, $port_id:expr, [
         $($PXi:ident: ($pxi:ident, $i:expr, $MODE:ty),)+
     ]),+]) => {
         $(
@@ -196,20 +654,23 @@ macro_rules! gpio {
                 use core::marker::PhantomData;
                 use core::convert::Infallible;
 
-                use embedded_hal::digital::v2::{InputPin, OutputPin, StatefulOutputPin, toggleable};
+                use embedded_hal::digital::v2::{
+                    InputPin, IoPin, OutputPin, PinState, StatefulOutputPin, toggleable,
+                };
                 use crate::{
-                    rcc::Rcc,
-                    pac::$GPIOX
+                    exti::ExtiLine,
+                    rcc::{Peripheral, Rcc},
+                    pac::{$GPIOX, EXTI, Interrupt},
                 };
 
                 use cortex_m::interrupt::CriticalSection;
 
                 use super::{
-                    Alternate, Analog, Floating, GpioExt, Input, OpenDrain, Output,
-                    PullDown, PullUp, PushPull,
+                    Alternate, Analog, Configured, Floating, GpioExt, Input, OpenDrain, Output,
+                    PortConfig, PullDown, PullUp, PushPull,
                     AF0, AF1, AF2, AF3, AF4, AF5, AF6, AF7,
                     AF8, AF9, AF10, AF11, AF12, AF13, AF14, AF15,
-                    Pin, GpioRegExt,
+                    Pin, PinMode, PinModeName, GpioRegExt,
                 };
 
                 /// GPIO parts
@@ -218,22 +679,130 @@ macro_rules! gpio {
                         /// Pin
                         pub $pxi: $PXi<$MODE>,
                     )+
+                    /// Whole port output and input data access
+                    pub port: Port,
+                }
+
+                /// Raw access to the data registers of the whole port
+                ///
+                /// Each method maps to a single register access, which makes it suitable for
+                /// bit-banged parallel buses. Pin modes are not checked: bits of pins that are not
+                /// configured as outputs have no effect on the pin level. BSRR writes are atomic,
+                /// so this does not race with the typed pins of the same port.
+                pub struct Port {
+                    _private: (),
+                }
+
+                impl Port {
+                    /// Drives the pins in `mask` high
+                    #[inline(always)]
+                    pub fn set_bits(&mut self, mask: u16) {
+                        //NOTE(unsafe) atomic write to a stateless register
+                        unsafe { (*$GPIOX::ptr()).bsrr.write(|w| w.bits(u32::from(mask))) };
+                    }
+
+                    /// Drives the pins in `mask` low
+                    #[inline(always)]
+                    pub fn reset_bits(&mut self, mask: u16) {
+                        //NOTE(unsafe) atomic write to a stateless register
+                        unsafe { (*$GPIOX::ptr()).bsrr.write(|w| w.bits(u32::from(mask) << 16)) };
+                    }
+
+                    /// Drives the pins in `mask` to the corresponding bits of `value`
+                    ///
+                    /// Pins outside of `mask` are left untouched, all others change at once.
+                    #[inline(always)]
+                    pub fn write(&mut self, mask: u16, value: u16) {
+                        let bits = u32::from(mask & value) | (u32::from(mask & !value) << 16);
+                        //NOTE(unsafe) atomic write to a stateless register
+                        unsafe { (*$GPIOX::ptr()).bsrr.write(|w| w.bits(bits)) };
+                    }
+
+                    /// Reads the input level of all pins
+                    #[inline(always)]
+                    pub fn read(&self) -> u16 {
+                        //NOTE(unsafe) atomic read with no side effects
+                        unsafe { (*$GPIOX::ptr()).idr.read().bits() as u16 }
+                    }
                 }
 
                 impl GpioExt for $GPIOX {
                     type Parts = Parts;
 
                     fn split(self, rcc: &mut Rcc) -> Parts {
-                        rcc.regs.iopenr.modify(|_, w| w.$iopxenr().set_bit());
+                        rcc.enable(Peripheral::$PER);
 
                         Parts {
                             $(
                                 $pxi: $PXi { _mode: PhantomData },
                             )+
+                            port: Port { _private: () },
                         }
                     }
                 }
 
+                impl Parts {
+                    /// Configures the whole port at once
+                    ///
+                    /// MODER, OTYPER, PUPDR, AFRL and AFRH are each written a single time, which
+                    /// is much faster than converting the pins one by one. The typed pins are
+                    /// consumed and returned erased, indexed by pin number.
+                    ///
+                    /// Every pin of the port is rewritten; on GPIOA keep PA13 and PA14 in
+                    /// alternate function 0 to retain the SWD connection.
+                    pub fn configure(
+                        self, _cs: &CriticalSection, config: &PortConfig
+                    ) -> [Pin<Configured>; 16] {
+                        unsafe {
+                            let reg = &(*$GPIOX::ptr());
+                            reg.afrl.write(|w| w.bits(config.af as u32));
+                            reg.afrh.write(|w| w.bits((config.af >> 32) as u32));
+                            reg.otyper.write(|w| w.bits(u32::from(config.open_drain)));
+                            reg.pupdr.write(|w| w.bits(config.pupdr()));
+                            reg.moder.write(|w| w.bits(config.moder()));
+                        }
+
+                        [
+                            $(
+                                Pin {
+                                    i: $i,
+                                    port: $GPIOX::ptr() as *const dyn GpioRegExt,
+                                    _mode: PhantomData,
+                                },
+                            )+
+                        ]
+                    }
+                }
+
+                impl Parts {
+                    /// Puts every pin of the port into analog mode without pull resistors
+                    ///
+                    /// Analog is the lowest-leakage pin state: the Schmitt trigger is disconnected,
+                    /// so a floating or slowly changing level does not draw shoot-through current.
+                    /// Before entering Stop mode, sweep every port this way and then reconfigure
+                    /// the few pins that have to keep a level or act as a wake-up source.
+                    ///
+                    /// On GPIOA this also disconnects SWD (PA13, PA14), a debugger can no longer
+                    /// attach until the pins are restored.
+                    pub fn all_analog(self, _cs: &CriticalSection) -> [Pin<Analog>; 16] {
+                        unsafe {
+                            let reg = &(*$GPIOX::ptr());
+                            reg.pupdr.write(|w| w.bits(0));
+                            reg.moder.write(|w| w.bits(0xFFFF_FFFF));
+                        }
+
+                        [
+                            $(
+                                Pin {
+                                    i: $i,
+                                    port: $GPIOX::ptr() as *const dyn GpioRegExt,
+                                    _mode: PhantomData,
+                                },
+                            )+
+                        ]
+                    }
+                }
+
                 fn _set_alternate_mode (index:usize, mode: u32)
                 {
                     let offset = 2 * index;
@@ -467,6 +1036,15 @@ macro_rules! gpio {
                             $PXi { _mode: PhantomData }
                         }
 
+                        /// Returns the pin to its lowest power state, analog mode without pulls
+                        ///
+                        /// Unused pins left as floating digital inputs are a common source of
+                        /// excess current in Stop mode. See [`Parts::all_analog`] for the whole
+                        /// port.
+                        pub fn deinit(self, cs: &CriticalSection) -> $PXi<Analog> {
+                            self.into_analog(cs)
+                        }
+
                         /// Configures the pin to operate as an open drain output pin
                         pub fn into_open_drain_output(
                             self, _cs: &CriticalSection
@@ -532,6 +1110,31 @@ macro_rules! gpio {
                         }
                     }
 
+                    impl<MODE> super::Deinit for $PXi<MODE> {
+                        type Analog = $PXi<Analog>;
+
+                        fn deinit(self, cs: &CriticalSection) -> $PXi<Analog> {
+                            self.into_analog(cs)
+                        }
+                    }
+
+                    impl<MODE> $PXi<MODE> {
+                        /// Reads back the current configuration of the pin from the port registers
+                        pub fn mode(&self) -> PinMode {
+                            unsafe { (*$GPIOX::ptr()).mode($i) }
+                        }
+
+                        /// Returns true if the pin is configured as a general purpose output
+                        pub fn is_output(&self) -> bool {
+                            matches!(self.mode(), PinMode::Output { .. })
+                        }
+
+                        /// Returns true if the pin is configured as a digital input
+                        pub fn is_input(&self) -> bool {
+                            matches!(self.mode(), PinMode::Input(_))
+                        }
+                    }
+
                     impl $PXi<Output<OpenDrain>> {
                         /// Enables / disables the internal pull up
                         pub fn internal_pull_up(&mut self, _cs: &CriticalSection, on: bool) {
@@ -625,6 +1228,76 @@ macro_rules! gpio {
                         }
                     }
 
+                    // The output level is latched into ODR before the mode switch, so the pin
+                    // never drives the previous level for a cycle.
+                    impl IoPin<Self, $PXi<Output<OpenDrain>>> for $PXi<Input<Floating>> {
+                        type Error = Infallible;
+
+                        fn into_input_pin(self) -> Result<Self, Self::Error> {
+                            Ok(self)
+                        }
+
+                        fn into_output_pin(
+                            self, state: PinState
+                        ) -> Result<$PXi<Output<OpenDrain>>, Self::Error> {
+                            // NOTE(unsafe) atomic write to a stateless register
+                            unsafe {
+                                match state {
+                                    PinState::High => (*$GPIOX::ptr()).set_high($i),
+                                    PinState::Low => (*$GPIOX::ptr()).set_low($i),
+                                }
+                            }
+                            Ok(cortex_m::interrupt::free(|cs| self.into_open_drain_output(cs)))
+                        }
+                    }
+
+                    impl IoPin<$PXi<Input<Floating>>, Self> for $PXi<Output<OpenDrain>> {
+                        type Error = Infallible;
+
+                        fn into_input_pin(self) -> Result<$PXi<Input<Floating>>, Self::Error> {
+                            Ok(cortex_m::interrupt::free(|cs| self.into_floating_input(cs)))
+                        }
+
+                        fn into_output_pin(mut self, state: PinState) -> Result<Self, Self::Error> {
+                            self.set_state(state)?;
+                            Ok(self)
+                        }
+                    }
+
+                    impl IoPin<Self, $PXi<Output<PushPull>>> for $PXi<Input<Floating>> {
+                        type Error = Infallible;
+
+                        fn into_input_pin(self) -> Result<Self, Self::Error> {
+                            Ok(self)
+                        }
+
+                        fn into_output_pin(
+                            self, state: PinState
+                        ) -> Result<$PXi<Output<PushPull>>, Self::Error> {
+                            // NOTE(unsafe) atomic write to a stateless register
+                            unsafe {
+                                match state {
+                                    PinState::High => (*$GPIOX::ptr()).set_high($i),
+                                    PinState::Low => (*$GPIOX::ptr()).set_low($i),
+                                }
+                            }
+                            Ok(cortex_m::interrupt::free(|cs| self.into_push_pull_output(cs)))
+                        }
+                    }
+
+                    impl IoPin<$PXi<Input<Floating>>, Self> for $PXi<Output<PushPull>> {
+                        type Error = Infallible;
+
+                        fn into_input_pin(self) -> Result<$PXi<Input<Floating>>, Self::Error> {
+                            Ok(cortex_m::interrupt::free(|cs| self.into_floating_input(cs)))
+                        }
+
+                        fn into_output_pin(mut self, state: PinState) -> Result<Self, Self::Error> {
+                            self.set_state(state)?;
+                            Ok(self)
+                        }
+                    }
+
                     impl<MODE> $PXi<Input<MODE>> {
                         /// Erases the pin number from the type
                         ///
@@ -639,6 +1312,66 @@ macro_rules! gpio {
                         }
                     }
 
+                    impl<MODE: PinModeName> core::fmt::Debug for $PXi<MODE> {
+                        fn fmt(&self, f: &mut core::fmt::Formatter<'_>) -> core::fmt::Result {
+                            write!(f, "{}<{}>", stringify!($PXi), MODE::NAME)
+                        }
+                    }
+
+                    #[cfg(feature = "defmt")]
+                    impl<MODE: PinModeName> defmt::Format for $PXi<MODE> {
+                        fn format(&self, f: defmt::Formatter) {
+                            defmt::write!(f, "{=str}<{=str}>", stringify!($PXi), MODE::NAME)
+                        }
+                    }
+
+                    impl<MODE> $PXi<Input<MODE>> {
+                        /// Connects the pin to its EXTI line
+                        ///
+                        /// Only one port can be connected to a line at a time. Lines 5 to 8 can
+                        /// only select GPIOA or GPIOB and lines 9 to 15 are fixed to GPIOA, so
+                        /// this panics for the pins of the other ports on those lines.
+                        pub fn make_interrupt_source(&mut self, exti: &mut EXTI) {
+                            let index = $i;
+                            let port: u32 = $port_id;
+                            match index {
+                                0..=3 => unsafe {
+                                    exti.exticr1.modify(|r, w| {
+                                        w.bits((r.bits() & !(0b11 << (8 * index))) | (port << (8 * index)))
+                                    })
+                                },
+                                4 => unsafe {
+                                    exti.exticr2.modify(|r, w| w.bits((r.bits() & !0b11) | port))
+                                },
+                                // EXTI5 to EXTI7 are single bit selectors: GPIOA or GPIOB only
+                                5..=7 => {
+                                    assert!(port < 2);
+                                    let offset = 8 * (index - 4);
+                                    unsafe {
+                                        exti.exticr2.modify(|r, w| {
+                                            w.bits((r.bits() & !(0b1 << offset)) | (port << offset))
+                                        })
+                                    }
+                                },
+                                8 => {
+                                    assert!(port < 2);
+                                    unsafe { exti.exticr3.write(|w| w.bits(port)) }
+                                },
+                                _ => assert!(port == 0),
+                            }
+                        }
+
+                        /// Returns the EXTI line of the pin
+                        pub fn exti_line(&self) -> ExtiLine {
+                            ExtiLine::gpio($i)
+                        }
+
+                        /// Returns the interrupt vector the pin's EXTI line is routed to
+                        pub fn interrupt(&self) -> Interrupt {
+                            self.exti_line().interrupt()
+                        }
+                    }
+
                     impl<MODE> InputPin for $PXi<Input<MODE>> {
                         type Error = Infallible;
 
@@ -650,6 +1383,57 @@ macro_rules! gpio {
                             Ok(unsafe { (*$GPIOX::ptr()).is_low($i) })
                         }
                     }
+
+                    #[cfg(feature = "embedded-hal-1")]
+                    impl<MODE> embedded_hal_1::digital::ErrorType for $PXi<MODE> {
+                        type Error = Infallible;
+                    }
+
+                    #[cfg(feature = "embedded-hal-1")]
+                    impl<MODE> embedded_hal_1::digital::OutputPin for $PXi<Output<MODE>> {
+                        fn set_high(&mut self) -> Result<(), Self::Error> {
+                            unsafe { (*$GPIOX::ptr()).set_high($i) };
+                            Ok(())
+                        }
+
+                        fn set_low(&mut self) -> Result<(), Self::Error> {
+                            unsafe { (*$GPIOX::ptr()).set_low($i) };
+                            Ok(())
+                        }
+                    }
+
+                    #[cfg(feature = "embedded-hal-1")]
+                    impl<MODE> embedded_hal_1::digital::StatefulOutputPin for $PXi<Output<MODE>> {
+                        fn is_set_high(&mut self) -> Result<bool, Self::Error> {
+                            Ok(!unsafe { (*$GPIOX::ptr()).is_set_low($i) })
+                        }
+
+                        fn is_set_low(&mut self) -> Result<bool, Self::Error> {
+                            Ok(unsafe { (*$GPIOX::ptr()).is_set_low($i) })
+                        }
+                    }
+
+                    #[cfg(feature = "embedded-hal-1")]
+                    impl embedded_hal_1::digital::InputPin for $PXi<Output<OpenDrain>> {
+                        fn is_high(&mut self) -> Result<bool, Self::Error> {
+                            Ok(!unsafe { (*$GPIOX::ptr()).is_low($i) })
+                        }
+
+                        fn is_low(&mut self) -> Result<bool, Self::Error> {
+                            Ok(unsafe { (*$GPIOX::ptr()).is_low($i) })
+                        }
+                    }
+
+                    #[cfg(feature = "embedded-hal-1")]
+                    impl<MODE> embedded_hal_1::digital::InputPin for $PXi<Input<MODE>> {
+                        fn is_high(&mut self) -> Result<bool, Self::Error> {
+                            Ok(!unsafe { (*$GPIOX::ptr()).is_low($i) })
+                        }
+
+                        fn is_low(&mut self) -> Result<bool, Self::Error> {
+                            Ok(unsafe { (*$GPIOX::ptr()).is_low($i) })
+                        }
+                    }
                 )+
             }
         )+
@@ -657,7 +1441,7 @@ macro_rules! gpio {
 }
 
 gpio!([
-    GPIOA, gpioa, gpioaen, PA, [
+    GPIOA, gpioa, GpioA, PA, 0, [
         PA0: (pa0, 0, Input<Floating>),
         PA1: (pa1, 1, Input<Floating>),
         PA2: (pa2, 2, Input<Floating>),
@@ -675,7 +1459,7 @@ gpio!([
         PA14: (pa14, 14, Input<Floating>),
         PA15: (pa15, 15, Input<Floating>),
     ],
-    GPIOB, gpiob, gpioben, PB, [
+    GPIOB, gpiob, GpioB, PB, 1, [
         PB0: (pb0, 0, Input<Floating>),
         PB1: (pb1, 1, Input<Floating>),
         PB2: (pb2, 2, Input<Floating>),
@@ -693,7 +1477,7 @@ gpio!([
         PB14: (pb14, 14, Input<Floating>),
         PB15: (pb15, 15, Input<Floating>),
     ],
-    GPIOF, gpiof, gpiofen, PF, [
+    GPIOF, gpiof, GpioF, PF, 2, [
         PF0: (pf0, 0, Input<Floating>),
         PF1: (pf1, 1, Input<Floating>),
         PF2: (pf2, 2, Input<Floating>),
@@ -712,3 +1496,28 @@ gpio!([
         PF15: (pf15, 15, Input<Floating>),
     ]
 ]);
+
+#[cfg(test)]
+mod tests {
+    use super::gpioa::PA0;
+    use super::*;
+    use embedded_hal::digital::v2::{IoPin, PinState};
+
+    /// Pulls the line low, releases it and samples the answer, as a 1-Wire reset does
+    fn presence<I, O>(pin: I) -> (bool, I)
+    where
+        I: IoPin<I, O, Error = Infallible> + InputPin<Error = Infallible>,
+        O: IoPin<I, O, Error = Infallible> + OutputPin,
+    {
+        let pin = pin.into_output_pin(PinState::Low).unwrap();
+        let pin = pin.into_input_pin().unwrap();
+        (pin.is_low().unwrap(), pin)
+    }
+
+    // Type checked only, the GPIO registers don't exist on the host
+    #[allow(dead_code)]
+    fn io_pin_consumer(pin: PA0<Input<Floating>>) {
+        let (_, pin) = presence::<_, PA0<Output<OpenDrain>>>(pin);
+        let _ = presence::<_, PA0<Output<PushPull>>>(pin);
+    }
+}
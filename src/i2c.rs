@@ -0,0 +1,97 @@
+//! # Inter-Integrated Circuit
+//!
+//! Pin mapping of the I2C peripheral. SCL and SDA have to be configured as open drain, e.g. with
+//! `set_open_drain` on the alternate function pin.
+//!
+//! [`recover_bus`] frees a bus that is stuck because a slave holds SDA low.
+
+use embedded_hal::blocking::delay::DelayUs;
+use embedded_hal::digital::v2::{InputPin, OutputPin};
+
+use crate::gpio::*;
+
+pub trait SclPin<I2C> {}
+pub trait SdaPin<I2C> {}
+
+macro_rules! i2c_pins {
+    ($($I2C:ident => {
+        scl => [$($scl:ty),+ $(,)*],
+        sda => [$($sda:ty),+ $(,)*],
+    })+) => {
+        $(
+            $(
+                impl SclPin<crate::pac::$I2C> for $scl {}
+            )+
+            $(
+                impl SdaPin<crate::pac::$I2C> for $sda {}
+            )+
+        )+
+    }
+}
+
+i2c_pins! {
+    I2C => {
+        scl => [
+            gpioa::PA3<Alternate<AF12>>,
+            gpioa::PA8<Alternate<AF12>>,
+            gpioa::PA9<Alternate<AF6>>,
+            gpioa::PA11<Alternate<AF6>>,
+            gpiob::PB6<Alternate<AF6>>,
+            gpiob::PB8<Alternate<AF6>>,
+            gpiof::PF1<Alternate<AF12>>,
+        ],
+        sda => [
+            gpioa::PA2<Alternate<AF12>>,
+            gpioa::PA7<Alternate<AF12>>,
+            gpioa::PA10<Alternate<AF6>>,
+            gpioa::PA12<Alternate<AF6>>,
+            gpiob::PB7<Alternate<AF6>>,
+            gpiof::PF0<Alternate<AF12>>,
+        ],
+    }
+}
+
+/// Clocks a stuck slave free and generates a STOP condition
+///
+/// A slave that was reset by the master in the middle of a read may keep SDA low while it waits
+/// for the rest of its byte, which shows as a bus that stays `BUSY`. Call this when the bus is
+/// still busy after a timeout, or at start-up if SDA is low: convert the pins to open drain
+/// outputs (e.g. `into_open_drain_output`), recover, then return them to their alternate
+/// function and reset the I2C peripheral (`Rcc::reset(Peripheral::I2c)`) before using it again.
+///
+/// Up to 9 SCL pulses are clocked at roughly 100 kHz until the slave releases SDA. Returns true
+/// if SDA is high (the bus is free) afterwards.
+pub fn recover_bus<SCL, SDA, D>(scl: &mut SCL, sda: &mut SDA, delay: &mut D) -> bool
+where
+    SCL: OutputPin,
+    SDA: OutputPin + InputPin,
+    D: DelayUs<u16>,
+{
+    const HALF_PERIOD_US: u16 = 5;
+
+    let _ = sda.set_high();
+    let _ = scl.set_high();
+    delay.delay_us(HALF_PERIOD_US);
+
+    for _ in 0..9 {
+        if sda.is_high().unwrap_or(false) {
+            break;
+        }
+        let _ = scl.set_low();
+        delay.delay_us(HALF_PERIOD_US);
+        let _ = scl.set_high();
+        delay.delay_us(HALF_PERIOD_US);
+    }
+
+    // STOP: SDA rising while SCL is high
+    let _ = scl.set_low();
+    delay.delay_us(HALF_PERIOD_US);
+    let _ = sda.set_low();
+    delay.delay_us(HALF_PERIOD_US);
+    let _ = scl.set_high();
+    delay.delay_us(HALF_PERIOD_US);
+    let _ = sda.set_high();
+    delay.delay_us(HALF_PERIOD_US);
+
+    sda.is_high().unwrap_or(false)
+}
@@ -5,13 +5,158 @@
 
 pub use air001_pac as pac;
 
+pub mod comparator;
+pub mod crc;
 pub mod delay;
+pub mod dma;
+pub mod exti;
+pub mod flash;
 pub mod gpio;
+pub mod i2c;
 pub mod prelude;
 pub mod pwm;
 pub mod rcc;
 pub mod serial;
+#[cfg(feature = "critical-section")]
+pub mod shared;
 pub mod spi;
 pub mod time;
 pub mod timers;
 pub mod watchdog;
+
+#[cfg(feature = "async")]
+mod waker;
+
+use crate::gpio::GpioExt;
+use crate::rcc::{Rcc, RccExt};
+use crate::time::Hertz;
+
+/// Clock configuration for [`init`]
+///
+/// Unset clocks take the same defaults as [`rcc::CFGR`].
+#[derive(Default, Clone, Copy)]
+pub struct Config {
+    hclk: Option<Hertz>,
+    pclk: Option<Hertz>,
+    sysclk: Option<Hertz>,
+}
+
+impl Config {
+    pub fn hclk<F>(mut self, freq: F) -> Self
+    where
+        F: Into<Hertz>,
+    {
+        self.hclk = Some(freq.into());
+        self
+    }
+
+    pub fn pclk<F>(mut self, freq: F) -> Self
+    where
+        F: Into<Hertz>,
+    {
+        self.pclk = Some(freq.into());
+        self
+    }
+
+    pub fn sysclk<F>(mut self, freq: F) -> Self
+    where
+        F: Into<Hertz>,
+    {
+        self.sysclk = Some(freq.into());
+        self
+    }
+}
+
+/// Peripherals returned by [`init`]
+///
+/// The clock tree is frozen and the GPIO ports are split, every other peripheral is handed back
+/// untouched.
+#[allow(non_snake_case)]
+pub struct Parts {
+    /// Constrained RCC with the frozen clocks
+    pub rcc: Rcc,
+    /// GPIO port A pins
+    pub gpioa: gpio::gpioa::Parts,
+    /// GPIO port B pins
+    pub gpiob: gpio::gpiob::Parts,
+    /// GPIO port F pins
+    pub gpiof: gpio::gpiof::Parts,
+    pub ADC: pac::ADC,
+    pub COMP1: pac::COMP1,
+    pub COMP2: pac::COMP2,
+    pub PWR: pac::PWR,
+    pub EXTI: pac::EXTI,
+    pub LPTIM: pac::LPTIM,
+    pub USART1: pac::USART1,
+    pub USART2: pac::USART2,
+    pub RTC: pac::RTC,
+    pub IWDG: pac::IWDG,
+    pub WWDG: pac::WWDG,
+    pub TIM1: pac::TIM1,
+    pub TIM3: pac::TIM3,
+    pub TIM14: pac::TIM14,
+    pub TIM16: pac::TIM16,
+    pub TIM17: pac::TIM17,
+    pub SYSCFG: pac::SYSCFG,
+    pub DMA: pac::DMA,
+    pub FLASH: pac::FLASH,
+    pub CRC: pac::CRC,
+    pub SPI1: pac::SPI1,
+    pub SPI2: pac::SPI2,
+    pub I2C: pac::I2C,
+    pub LED: pac::LED,
+    pub DBGMCU: pac::DBGMCU,
+}
+
+/// Freezes the clocks according to `config` and splits the GPIO ports
+///
+/// This is a shorthand for the usual `dp.RCC.configure()...freeze(&mut dp.FLASH)` followed by
+/// `split` on each GPIO port. Nothing else is configured, so doing the same by hand is still
+/// possible.
+pub fn init(dp: pac::Peripherals, config: Config) -> Parts {
+    let mut flash = dp.FLASH;
+
+    let mut cfgr = dp.RCC.configure();
+    if let Some(sysclk) = config.sysclk {
+        cfgr = cfgr.sysclk(sysclk);
+    }
+    if let Some(hclk) = config.hclk {
+        cfgr = cfgr.hclk(hclk);
+    }
+    if let Some(pclk) = config.pclk {
+        cfgr = cfgr.pclk(pclk);
+    }
+    let mut rcc = cfgr.freeze(&mut flash);
+
+    Parts {
+        gpioa: dp.GPIOA.split(&mut rcc),
+        gpiob: dp.GPIOB.split(&mut rcc),
+        gpiof: dp.GPIOF.split(&mut rcc),
+        rcc,
+        ADC: dp.ADC,
+        COMP1: dp.COMP1,
+        COMP2: dp.COMP2,
+        PWR: dp.PWR,
+        EXTI: dp.EXTI,
+        LPTIM: dp.LPTIM,
+        USART1: dp.USART1,
+        USART2: dp.USART2,
+        RTC: dp.RTC,
+        IWDG: dp.IWDG,
+        WWDG: dp.WWDG,
+        TIM1: dp.TIM1,
+        TIM3: dp.TIM3,
+        TIM14: dp.TIM14,
+        TIM16: dp.TIM16,
+        TIM17: dp.TIM17,
+        SYSCFG: dp.SYSCFG,
+        DMA: dp.DMA,
+        FLASH: flash,
+        CRC: dp.CRC,
+        SPI1: dp.SPI1,
+        SPI2: dp.SPI2,
+        I2C: dp.I2C,
+        LED: dp.LED,
+        DBGMCU: dp.DBGMCU,
+    }
+}
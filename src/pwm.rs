@@ -1,7 +1,7 @@
-use cast::{u16, u32};
+use cast::u32;
 use core::{marker::PhantomData, mem::MaybeUninit};
 
-use crate::rcc::Rcc;
+use crate::rcc::{Peripheral, Rcc};
 
 use crate::time::Hertz;
 use embedded_hal as hal;
@@ -23,6 +23,7 @@ use crate::timers::PinC2N;
 use crate::timers::PinC3;
 use crate::timers::PinC3N;
 use crate::timers::PinC4;
+use crate::timers::TimerPeriod;
 
 pub struct C1;
 pub struct C1N;
@@ -37,6 +38,179 @@ pub struct PwmChannels<TIM, CHANNELS> {
     _tim: PhantomData<TIM>,
 }
 
+/// Timer running PWM, owns the timer and the channel pins
+///
+/// The outputs are driven through the [`PwmChannels`] handles returned next to it by the
+/// constructor.
+pub struct Pwm<TIM, PINS, P> {
+    tim: TIM,
+    pins: PINS,
+    _p: PhantomData<P>,
+}
+
+/// Output level of a channel while the timer outputs are idle (`OISx`)
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub enum IdleState {
+    /// Output driven low
+    Low,
+    /// Output driven high
+    High,
+}
+
+/// Level of the break input (`BKIN`) that disables the outputs, see [`Pwm::enable_break`]
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub enum BreakPolarity {
+    /// Break while the input is low
+    ActiveLow,
+    /// Break while the input is high
+    ActiveHigh,
+}
+
+/// Level a channel is forced to by [`PwmChannels::disable_to`]
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub enum ForcedState {
+    /// Reference forced high, the output is at its active level
+    Active,
+    /// Reference forced low, the output is at its inactive level
+    Inactive,
+}
+
+/// Moves the duty cycle of a PWM channel towards a target by a fixed step per [`tick`]
+///
+/// Call `tick` at a fixed interval, e.g. from a timer interrupt, to fade an LED smoothly.
+///
+/// [`tick`]: PwmRamp::tick
+pub struct PwmRamp<P> {
+    pwm: P,
+    current: u16,
+    target: u16,
+    step: u16,
+}
+
+impl<P> PwmRamp<P>
+where
+    P: hal::PwmPin<Duty = u16>,
+{
+    /// Creates a ramp starting at the channel's current duty cycle, moving by `step` per tick
+    pub fn new(pwm: P, step: u16) -> Self {
+        let current = pwm.get_duty();
+        PwmRamp {
+            pwm,
+            current,
+            target: current,
+            step: step.max(1),
+        }
+    }
+
+    /// Sets the duty cycle to ramp towards, clamped to the channel's maximum duty
+    pub fn set_target(&mut self, duty: u16) {
+        self.target = duty.min(self.pwm.get_max_duty());
+    }
+
+    /// Returns the duty cycle the ramp is moving towards
+    pub fn target(&self) -> u16 {
+        self.target
+    }
+
+    /// Sets the duty change per tick
+    pub fn set_step(&mut self, step: u16) {
+        self.step = step.max(1);
+    }
+
+    /// Returns the duty cycle currently applied
+    pub fn current(&self) -> u16 {
+        self.current
+    }
+
+    /// Returns true once the target has been reached
+    pub fn is_done(&self) -> bool {
+        self.current == self.target
+    }
+
+    /// Moves the duty cycle one step towards the target
+    ///
+    /// Returns true once the target has been reached.
+    pub fn tick(&mut self) -> bool {
+        let next = if self.current < self.target {
+            self.current.saturating_add(self.step).min(self.target)
+        } else {
+            self.current.saturating_sub(self.step).max(self.target)
+        };
+        if next != self.current {
+            self.current = next;
+            self.pwm.set_duty(next);
+        }
+        self.is_done()
+    }
+
+    /// Releases the PWM channel
+    pub fn release(self) -> P {
+        self.pwm
+    }
+}
+
+/// Number of fractional duty bits handled by [`PwmDither`]
+pub const DITHER_BITS: u32 = 8;
+
+/// Dithers the duty cycle of a PWM channel to reach a resolution finer than one timer tick
+///
+/// The target is given in duty units with [`DITHER_BITS`] fractional bits. Every [`tick`]
+/// applies either the integer part or the integer part plus one, so that the average over
+/// consecutive periods matches the fractional target. Call `tick` once per PWM period, e.g.
+/// from the update interrupt; with the duty preload enabled the new value takes effect in the
+/// following period.
+///
+/// [`tick`]: PwmDither::tick
+pub struct PwmDither<P> {
+    pwm: P,
+    target: u32,
+    error: u32,
+}
+
+impl<P> PwmDither<P>
+where
+    P: hal::PwmPin<Duty = u16>,
+{
+    /// Creates a dither starting at the channel's current duty cycle
+    pub fn new(pwm: P) -> Self {
+        let target = u32::from(pwm.get_duty()) << DITHER_BITS;
+        PwmDither {
+            pwm,
+            target,
+            error: 0,
+        }
+    }
+
+    /// Sets the fractional duty cycle, clamped to the channel's maximum duty
+    ///
+    /// `target` is the duty cycle multiplied by `1 << DITHER_BITS`.
+    pub fn set_target(&mut self, target: u32) {
+        self.target = target.min(u32::from(self.pwm.get_max_duty()) << DITHER_BITS);
+    }
+
+    /// Returns the fractional duty cycle
+    pub fn target(&self) -> u32 {
+        self.target
+    }
+
+    /// Applies the duty cycle for the next period
+    pub fn tick(&mut self) {
+        let mask = (1 << DITHER_BITS) - 1;
+        let mut duty = (self.target >> DITHER_BITS) as u16;
+        self.error += self.target & mask;
+        if self.error > mask {
+            self.error -= 1 << DITHER_BITS;
+            duty += 1;
+        }
+        self.pwm.set_duty(duty);
+    }
+
+    /// Releases the PWM channel
+    pub fn release(self) -> P {
+        self.pwm
+    }
+}
+
 macro_rules! pins_impl {
     ( $( ( $($PINX:ident),+ ), ( $($TRAIT:ident),+ ), ( $($ENCHX:ident),* ); )+ ) => {
         $(
@@ -120,17 +294,29 @@ macro_rules! brk {
 
 // Timer with four output channels 16 Bit Timer
 macro_rules! pwm_4_channels {
-    ($($TIMX:ident: ($timX:ident, $timXen:ident, $timXrst:ident, $apbenr:ident, $apbrstr:ident),)+) => {
+    ($($TIMX:ident: ($timX:ident, $PER:ident),)+) => {
         $(
-            pub fn $timX<P, PINS, T>(tim: $TIMX, _pins: PINS, rcc: &mut Rcc, freq: T) -> PINS::Channels
+            /// Configures the timer for PWM output at `freq` on the given channel pins
+            ///
+            /// `pins` is either a single channel pin or a tuple of channel pins, e.g.
+            /// `(c1, c2, c3)` to drive an RGB LED. A matching tuple of independent
+            /// [`PwmChannels`] handles is returned, each with its own duty cycle and enable. All
+            /// channels share the timer period, so `get_max_duty` is the same for each of them.
+            /// The [`Pwm`] returned alongside keeps the timer and the pins until
+            /// [`Pwm::release`].
+            pub fn $timX<P, PINS, T>(
+                tim: $TIMX,
+                pins: PINS,
+                rcc: &mut Rcc,
+                freq: T,
+            ) -> (Pwm<$TIMX, PINS, P>, PINS::Channels)
             where
                 PINS: Pins<$TIMX, P>,
                 T: Into<Hertz>,
             {
                 // enable and reset peripheral to a clean slate state
-                rcc.regs.$apbenr.modify(|_, w| w.$timXen().set_bit());
-                rcc.regs.$apbrstr.modify(|_, w| w.$timXrst().set_bit());
-                rcc.regs.$apbrstr.modify(|_, w| w.$timXrst().clear_bit());
+                rcc.enable(Peripheral::$PER);
+                rcc.reset(Peripheral::$PER);
 
                 let mode1 = 0b110;
 
@@ -153,18 +339,10 @@ macro_rules! pwm_4_channels {
                     }
                 }
 
-                // If pclk is prescaled from hclk, the frequency fed into the timers is doubled
-                let tclk = if rcc.clocks.hclk().0 == rcc.clocks.pclk().0 {
-                    rcc.clocks.pclk().0
-                } else {
-                    rcc.clocks.pclk().0 * 2
-                };
-                let ticks = tclk / freq.into().0;
-
-                let psc = u16((ticks - 1) / (1 << 16)).unwrap();
-                tim.psc.write(|w| unsafe { w.psc().bits(psc) });
-                let arr = u16(ticks / u32(psc + 1)).unwrap();
-                tim.arr.write(|w| unsafe { w.bits(u32(arr)) });
+                let tclk = rcc.clocks.peripheral_clock(Peripheral::$PER);
+                let period = TimerPeriod::new(tclk, freq.into());
+                tim.psc.write(|w| unsafe { w.psc().bits(period.psc) });
+                tim.arr.write(|w| unsafe { w.bits(u32(period.arr)) });
 
                 // enable auto-reload preload
                 tim.cr1.modify(|_, w| w.arpe().set_bit());
@@ -186,8 +364,13 @@ macro_rules! pwm_4_channels {
                         .set_bit()
                     }
                 );
+                let pwm = Pwm {
+                    tim,
+                    pins,
+                    _p: PhantomData,
+                };
                 //NOTE(unsafe) `PINS::Channels` is a ZST
-                unsafe { MaybeUninit::uninit().assume_init() }
+                (pwm, unsafe { MaybeUninit::uninit().assume_init() })
             }
 
             impl hal::PwmPin for PwmChannels<$TIMX, C1> {
@@ -311,19 +494,31 @@ macro_rules! pwm_4_channels {
 
 // Timer with four output channels three with complements 16 Bit Timer
 macro_rules! pwm_4_channels_with_3_complementary_outputs {
-    ($($TIMX:ident: ($timX:ident, $timXen:ident, $timXrst:ident, $apbenr:ident, $apbrstr:ident),)+) => {
+    ($($TIMX:ident: ($timX:ident, $PER:ident),)+) => {
         $(
-            pub fn $timX<P, PINS, T>(tim: $TIMX, _pins: PINS, rcc: &mut Rcc, freq: T) -> PINS::Channels
+            /// Configures the timer for PWM output at `freq` on the given channel pins
+            ///
+            /// `pins` is either a single channel pin or a tuple of channel pins, e.g.
+            /// `(c1, c2, c3)` to drive an RGB LED or `(c1, c1n, c2, c2n)` for complementary
+            /// outputs. A matching tuple of independent [`PwmChannels`] handles is returned, each
+            /// with its own duty cycle and enable. All channels share the timer period, so
+            /// `get_max_duty` is the same for each of them. The [`Pwm`] returned alongside keeps
+            /// the timer and the pins until [`Pwm::release`].
+            pub fn $timX<P, PINS, T>(
+                tim: $TIMX,
+                pins: PINS,
+                rcc: &mut Rcc,
+                freq: T,
+            ) -> (Pwm<$TIMX, PINS, P>, PINS::Channels)
             where
                 PINS: Pins<$TIMX, P>,
                 T: Into<Hertz>,
             {
                 // enable and reset peripheral to a clean slate state
-                rcc.regs.$apbenr.modify(|_, w| w.$timXen().set_bit());
-                rcc.regs.$apbrstr.modify(|_, w| w.$timXrst().set_bit());
-                rcc.regs.$apbrstr.modify(|_, w| w.$timXrst().clear_bit());
+                rcc.enable(Peripheral::$PER);
+                rcc.reset(Peripheral::$PER);
 
-                if PINS::C1N | PINS::C1N | PINS::C1N {
+                if PINS::C1N | PINS::C2N | PINS::C3N {
                     tim.bdtr.modify(|_, w| w.ossr().set_bit());
                 }
 
@@ -348,18 +543,10 @@ macro_rules! pwm_4_channels_with_3_complementary_outputs {
                 }
                 }
 
-                // If pclk is prescaled from hclk, the frequency fed into the timers is doubled
-                let tclk = if rcc.clocks.hclk().0 == rcc.clocks.pclk().0 {
-                    rcc.clocks.pclk().0
-                } else {
-                    rcc.clocks.pclk().0 * 2
-                };
-                let ticks = tclk / freq.into().0;
-
-                let psc = u16((ticks - 1) / (1 << 16)).unwrap();
-                tim.psc.write(|w| unsafe {w.psc().bits(psc) });
-                let arr = u16(ticks / u32(psc + 1)).unwrap();
-                tim.arr.write(|w| unsafe { w.bits(u32(arr)) });
+                let tclk = rcc.clocks.peripheral_clock(Peripheral::$PER);
+                let period = TimerPeriod::new(tclk, freq.into());
+                tim.psc.write(|w| unsafe { w.psc().bits(period.psc) });
+                tim.arr.write(|w| unsafe { w.bits(u32(period.arr)) });
 
                 // enable auto-reload preload
                 tim.cr1.modify(|_, w| w.arpe().set_bit());
@@ -381,8 +568,13 @@ macro_rules! pwm_4_channels_with_3_complementary_outputs {
                         .set_bit()
                    }
                 );
+                let pwm = Pwm {
+                    tim,
+                    pins,
+                    _p: PhantomData,
+                };
                 //NOTE(unsafe) `PINS::Channels` is a ZST
-                unsafe { MaybeUninit::uninit().assume_init() }
+                (pwm, unsafe { MaybeUninit::uninit().assume_init() })
             }
 
             impl hal::PwmPin for PwmChannels<$TIMX, C1> {
@@ -593,17 +785,25 @@ macro_rules! pwm_4_channels_with_3_complementary_outputs {
 
 // General purpose timer with one output channel (TIM14)
 macro_rules! pwm_1_channel {
-    ($($TIMX:ident: ($timX:ident, $timXen:ident, $timXrst:ident, $apbenr:ident, $apbrstr:ident),)+) => {
+    ($($TIMX:ident: ($timX:ident, $PER:ident),)+) => {
         $(
-            pub fn $timX<P, PINS, T>(tim: $TIMX, _pins: PINS, rcc: &mut Rcc, freq: T) -> PINS::Channels
+            /// Configures the timer for PWM output at `freq` on its channel 1 pin
+            ///
+            /// The timer only has channel 1, a single [`PwmChannels`] handle is returned. The
+            /// [`Pwm`] returned alongside keeps the timer and the pin until [`Pwm::release`].
+            pub fn $timX<P, PINS, T>(
+                tim: $TIMX,
+                pins: PINS,
+                rcc: &mut Rcc,
+                freq: T,
+            ) -> (Pwm<$TIMX, PINS, P>, PINS::Channels)
             where
                 PINS: Pins<$TIMX, P>,
                 T: Into<Hertz>,
             {
                 // enable and reset peripheral to a clean slate state
-                rcc.regs.$apbenr.modify(|_, w| w.$timXen().set_bit());
-                rcc.regs.$apbrstr.modify(|_, w| w.$timXrst().set_bit());
-                rcc.regs.$apbrstr.modify(|_, w| w.$timXrst().clear_bit());
+                rcc.enable(Peripheral::$PER);
+                rcc.reset(Peripheral::$PER);
 
                 if PINS::C1 {
                     unsafe {
@@ -611,18 +811,10 @@ macro_rules! pwm_1_channel {
                     }
                 }
 
-                // If pclk is prescaled from hclk, the frequency fed into the timers is doubled
-                let tclk = if rcc.clocks.hclk().0 == rcc.clocks.pclk().0 {
-                    rcc.clocks.pclk().0
-                } else {
-                    rcc.clocks.pclk().0 * 2
-                };
-                let ticks = tclk / freq.into().0;
-
-                let psc = u16((ticks - 1) / (1 << 16)).unwrap();
-                tim.psc.write(|w| unsafe {w.psc().bits(psc) });
-                let arr = u16(ticks / u32(psc + 1)).unwrap();
-                tim.arr.write(|w| unsafe { w.bits(u32(arr)) });
+                let tclk = rcc.clocks.peripheral_clock(Peripheral::$PER);
+                let period = TimerPeriod::new(tclk, freq.into());
+                tim.psc.write(|w| unsafe { w.psc().bits(period.psc) });
+                tim.arr.write(|w| unsafe { w.bits(u32(period.arr)) });
 
                 // enable auto-reload preload
                 tim.cr1.modify(|_, w| w.arpe().set_bit());
@@ -637,8 +829,13 @@ macro_rules! pwm_1_channel {
                     w.cen()
                         .set_bit()
                 );
+                let pwm = Pwm {
+                    tim,
+                    pins,
+                    _p: PhantomData,
+                };
                 //NOTE(unsafe) `PINS::Channels` is a ZST
-                unsafe { MaybeUninit::uninit().assume_init() }
+                (pwm, unsafe { MaybeUninit::uninit().assume_init() })
             }
 
             impl hal::PwmPin for PwmChannels<$TIMX, C1> {
@@ -675,34 +872,36 @@ macro_rules! pwm_1_channel {
 
 // General purpose timer with one output channel (TIM16/TIM17)
 macro_rules! pwm_1_channel_with_complementary_outputs {
-    ($($TIMX:ident: ($timX:ident, $timXen:ident, $timXrst:ident, $apbenr:ident, $apbrstr:ident),)+) => {
+    ($($TIMX:ident: ($timX:ident, $PER:ident),)+) => {
         $(
-            pub fn $timX<P, PINS, T>(tim: $TIMX, _pins: PINS, rcc: &mut Rcc, freq: T) -> PINS::Channels
+            /// Configures the timer for PWM output at `freq` on its channel 1 pin
+            ///
+            /// The timer only has channel 1 (plus its complementary output), a single
+            /// [`PwmChannels`] handle is returned. Like TIM1 it has a main output switch, see
+            /// `set_idle_state` for the level while it is off. The [`Pwm`] returned alongside
+            /// keeps the timer and the pin until [`Pwm::release`].
+            pub fn $timX<P, PINS, T>(
+                tim: $TIMX,
+                pins: PINS,
+                rcc: &mut Rcc,
+                freq: T,
+            ) -> (Pwm<$TIMX, PINS, P>, PINS::Channels)
             where
                 PINS: Pins<$TIMX, P>,
                 T: Into<Hertz>,
             {
                 // enable and reset peripheral to a clean slate state
-                rcc.regs.$apbenr.modify(|_, w| w.$timXen().set_bit());
-                rcc.regs.$apbrstr.modify(|_, w| w.$timXrst().set_bit());
-                rcc.regs.$apbrstr.modify(|_, w| w.$timXrst().clear_bit());
+                rcc.enable(Peripheral::$PER);
+                rcc.reset(Peripheral::$PER);
 
                 if PINS::C1 {
                     tim.ccmr1_output().modify(|_, w| unsafe {w.oc1pe().set_bit().oc1m().bits(6) });
                 }
 
-                // If pclk is prescaled from hclk, the frequency fed into the timers is doubled
-                let tclk = if rcc.clocks.hclk().0 == rcc.clocks.pclk().0 {
-                    rcc.clocks.pclk().0
-                } else {
-                    rcc.clocks.pclk().0 * 2
-                };
-                let ticks = tclk / freq.into().0;
-
-                let psc = u16((ticks - 1) / (1 << 16)).unwrap();
-                tim.psc.write(|w| unsafe { w.psc().bits(psc) });
-                let arr = u16(ticks / u32(psc + 1)).unwrap();
-                tim.arr.write(|w| unsafe { w.bits(u32(arr)) });
+                let tclk = rcc.clocks.peripheral_clock(Peripheral::$PER);
+                let period = TimerPeriod::new(tclk, freq.into());
+                tim.psc.write(|w| unsafe { w.psc().bits(period.psc) });
+                tim.arr.write(|w| unsafe { w.bits(u32(period.arr)) });
 
                 // enable auto-reload preload
                 tim.cr1.modify(|_, w| w.arpe().set_bit());
@@ -720,8 +919,13 @@ macro_rules! pwm_1_channel_with_complementary_outputs {
                         .set_bit()
                 );
 
+                let pwm = Pwm {
+                    tim,
+                    pins,
+                    _p: PhantomData,
+                };
                 //NOTE(unsafe) `PINS::Channels` is a ZST
-                unsafe { MaybeUninit::uninit().assume_init() }
+                (pwm, unsafe { MaybeUninit::uninit().assume_init() })
             }
 
             impl hal::PwmPin for PwmChannels<$TIMX, C1> {
@@ -758,10 +962,381 @@ macro_rules! pwm_1_channel_with_complementary_outputs {
 
 use crate::pac::*;
 
-pwm_4_channels_with_3_complementary_outputs!(TIM1: (tim1, tim1en, tim1rst, apbenr2, apbrstr2),);
-pwm_4_channels!(TIM3: (tim3, tim3en, tim3rst, apbenr1, apbrstr1),);
-pwm_1_channel!(TIM14: (tim14, tim14en, tim14rst, apbenr2, apbrstr2),);
+pwm_4_channels_with_3_complementary_outputs!(TIM1: (tim1, Tim1),);
+pwm_4_channels!(TIM3: (tim3, Tim3),);
+pwm_1_channel!(TIM14: (tim14, Tim14),);
 pwm_1_channel_with_complementary_outputs!(
-    TIM16: (tim16, tim16en, tim16rst, apbenr2, apbrstr2),
-    TIM17: (tim17, tim17en, tim17rst, apbenr2, apbrstr2),
+    TIM16: (tim16, Tim16),
+    TIM17: (tim17, Tim17),
 );
+
+// only the timers with a break function (TIM1, TIM16, TIM17) have output idle states
+macro_rules! idle_state {
+    ($($TIMX:ident: [$($CX:ident: $oisx:ident,)+],)+) => {
+        $(
+            $(
+                impl PwmChannels<$TIMX, $CX> {
+                    /// Sets the level the output is driven to while the main output is disabled
+                    ///
+                    /// The idle state applies whenever MOE is cleared, in particular after a
+                    /// break event. For a half-bridge, set both the channel and its
+                    /// complementary output to `IdleState::Low` so that neither switch conducts.
+                    /// This also sets OSSI, so the outputs keep driving their idle level instead
+                    /// of being released.
+                    pub fn set_idle_state(&mut self, state: IdleState) {
+                        //NOTE(unsafe) atomic writes, OISx is only accessed through this channel
+                        let tim = unsafe { &*$TIMX::ptr() };
+                        tim.cr2.modify(|_, w| w.$oisx().bit(state == IdleState::High));
+                        tim.bdtr.modify(|_, w| w.ossi().set_bit());
+                    }
+                }
+            )+
+        )+
+    };
+}
+
+idle_state! {
+    TIM1: [
+        C1: ois1,
+        C1N: ois1n,
+        C2: ois2,
+        C2N: ois2n,
+        C3: ois3,
+        C3N: ois3n,
+        C4: ois4,
+    ],
+    TIM16: [
+        C1: ois1,
+        C1N: ois1n,
+    ],
+    TIM17: [
+        C1: ois1,
+        C1N: ois1n,
+    ],
+}
+
+macro_rules! break_input {
+    ($($TIMX:ident,)+) => {
+        $(
+            impl<P, PINS: Pins<$TIMX, P>> Pwm<$TIMX, PINS, P> {
+                /// Enables the break input (`BKE`) with the given polarity (`BKP`)
+                ///
+                /// An active break input clears MOE asynchronously, so all outputs of the timer
+                /// switch to their idle state (see `set_idle_state`) without any software
+                /// involvement. Since automatic output enable is set, the outputs resume at the
+                /// next update event once the input is inactive again. The `BKIN` pin has to be
+                /// put into its alternate function by the caller.
+                pub fn enable_break(&mut self, polarity: BreakPolarity) {
+                    self.tim.bdtr.modify(|_, w| {
+                        w.bkp()
+                            .bit(polarity == BreakPolarity::ActiveHigh)
+                            .bke()
+                            .set_bit()
+                    });
+                }
+
+                /// Disables the break input
+                pub fn disable_break(&mut self) {
+                    self.tim.bdtr.modify(|_, w| w.bke().clear_bit());
+                }
+            }
+        )+
+    };
+}
+
+break_input! {
+    TIM1,
+    TIM16,
+    TIM17,
+}
+
+macro_rules! forced_output {
+    ($($TIMX:ident: [$($CX:ident: ($ccmrx:ident, $ocxm:ident, $ccxe:ident),)+],)+) => {
+        $(
+            $(
+                impl PwmChannels<$TIMX, $CX> {
+                    /// Stops the PWM and forces the output to `state`
+                    ///
+                    /// Unlike [`PwmPin::disable`](hal::PwmPin::disable), which releases the pin
+                    /// (or drives the idle state on timers with a break function), the output
+                    /// keeps being driven, using the forced output compare modes (`OCxM`). The
+                    /// change takes effect immediately, without waiting for the end of the period.
+                    ///
+                    /// `state` refers to the reference signal: the output polarity (`CCxP`) still
+                    /// applies, so with an active low output `ForcedState::Active` drives the pin
+                    /// low. A complementary output, if enabled, is driven to the opposite level
+                    /// with dead time inserted as usual.
+                    ///
+                    /// Call [`PwmChannels::resume`] to return to PWM, `PwmPin::enable` alone
+                    /// keeps the forced level.
+                    pub fn disable_to(&mut self, state: ForcedState) {
+                        let mode = match state {
+                            ForcedState::Active => 0b101,
+                            ForcedState::Inactive => 0b100,
+                        };
+                        //NOTE(unsafe) atomic writes, OCxM and CCxE are only accessed through
+                        // this channel
+                        unsafe {
+                            let tim = &*$TIMX::ptr();
+                            tim.$ccmrx().modify(|_, w| w.$ocxm().bits(mode));
+                            tim.ccer.modify(|_, w| w.$ccxe().set_bit());
+                        }
+                    }
+
+                    /// Returns from a forced level to PWM mode 1 and enables the output
+                    pub fn resume(&mut self) {
+                        //NOTE(unsafe) see `disable_to`
+                        unsafe {
+                            let tim = &*$TIMX::ptr();
+                            tim.$ccmrx().modify(|_, w| w.$ocxm().bits(0b110));
+                            tim.ccer.modify(|_, w| w.$ccxe().set_bit());
+                        }
+                    }
+                }
+            )+
+        )+
+    };
+}
+
+forced_output! {
+    TIM1: [
+        C1: (ccmr1_output, oc1m, cc1e),
+        C2: (ccmr1_output, oc2m, cc2e),
+        C3: (ccmr2_output, oc3m, cc3e),
+        C4: (ccmr2_output, oc4m, cc4e),
+    ],
+    TIM3: [
+        C1: (ccmr1_output, oc1m, cc1e),
+        C2: (ccmr1_output, oc2m, cc2e),
+        C3: (ccmr2_output, oc3m, cc3e),
+        C4: (ccmr2_output, oc4m, cc4e),
+    ],
+    TIM14: [
+        C1: (ccmr1_output, oc1m, cc1e),
+    ],
+    TIM16: [
+        C1: (ccmr1_output, oc1m, cc1e),
+    ],
+    TIM17: [
+        C1: (ccmr1_output, oc1m, cc1e),
+    ],
+}
+
+/// Marker for PWM channel handles of timer `TIM`, single or in a tuple
+pub trait Channels<TIM> {}
+
+impl<TIM, CHANNEL> Channels<TIM> for PwmChannels<TIM, CHANNEL> {}
+impl<TIM, A: Channels<TIM>, B: Channels<TIM>> Channels<TIM> for (A, B) {}
+impl<TIM, A: Channels<TIM>, B: Channels<TIM>, C: Channels<TIM>> Channels<TIM> for (A, B, C) {}
+impl<TIM, A: Channels<TIM>, B: Channels<TIM>, C: Channels<TIM>, D: Channels<TIM>> Channels<TIM>
+    for (A, B, C, D)
+{
+}
+impl<TIM, A, B, C, D, E, F> Channels<TIM> for (A, B, C, D, E, F)
+where
+    A: Channels<TIM>,
+    B: Channels<TIM>,
+    C: Channels<TIM>,
+    D: Channels<TIM>,
+    E: Channels<TIM>,
+    F: Channels<TIM>,
+{
+}
+
+macro_rules! release {
+    ($($TIMX:ident: $PER:ident,)+) => {
+        $(
+            impl<P, PINS: Pins<$TIMX, P>> Pwm<$TIMX, PINS, P> {
+                /// Stops the PWM and hands back the timer, reset to its power-on state, and the
+                /// pins
+                ///
+                /// `channels` has to be the complete set of handles returned by the constructor,
+                /// so none of them can keep driving the timer once it is used for something else.
+                pub fn release(self, _channels: PINS::Channels) -> ($TIMX, PINS) {
+                    crate::rcc::reset(Peripheral::$PER);
+                    crate::rcc::disable(Peripheral::$PER);
+                    (self.tim, self.pins)
+                }
+            }
+        )+
+    };
+}
+
+release! {
+    TIM1: Tim1,
+    TIM3: Tim3,
+    TIM14: Tim14,
+    TIM16: Tim16,
+    TIM17: Tim17,
+}
+
+/// PWM outputs of TIM1 and TIM3 started on the same clock edge
+///
+/// TIM1 is the master: with `MMS = enable` its counter enable is output as TRGO, which starts
+/// TIM3 in trigger mode through ITR0. Both counters are reset before, so their periods stay
+/// aligned as long as both run at the same frequency. TIM14, TIM16 and TIM17 have no slave
+/// mode controller and can't be started in hardware lockstep.
+pub struct PwmGroup<M, S> {
+    master: M,
+    slave: S,
+}
+
+impl<M, S> PwmGroup<M, S>
+where
+    M: Channels<TIM1>,
+    S: Channels<TIM3>,
+{
+    /// Groups the channels of TIM1 and TIM3, both timers are stopped until [`enable_all`]
+    ///
+    /// [`enable_all`]: PwmGroup::enable_all
+    pub fn tim1_tim3(master: M, slave: S) -> Self {
+        let mut group = PwmGroup { master, slave };
+        group.disable_all();
+        group
+    }
+
+    /// Resets both counters and starts them on the same edge
+    pub fn enable_all(&mut self) {
+        //NOTE(unsafe) the group owns the channels of both timers
+        let (tim1, tim3) = unsafe { (&*TIM1::ptr(), &*TIM3::ptr()) };
+        self.disable_all();
+
+        tim1.cnt.reset();
+        tim3.cnt.reset();
+        // TIM3: trigger mode on ITR0 (TIM1 TRGO)
+        tim3.smcr
+            .modify(|_, w| unsafe { w.ts().bits(0b000).sms().bits(0b110) });
+        // TIM1: TRGO on counter enable, delayed to match the slave
+        tim1.cr2.modify(|_, w| unsafe { w.mms().bits(0b001) });
+        tim1.smcr.modify(|_, w| w.msm().set_bit());
+
+        tim1.cr1.modify(|_, w| w.cen().set_bit());
+    }
+
+    /// Stops both counters, the outputs keep their current level
+    pub fn disable_all(&mut self) {
+        //NOTE(unsafe) the group owns the channels of both timers
+        let (tim1, tim3) = unsafe { (&*TIM1::ptr(), &*TIM3::ptr()) };
+        tim1.cr1.modify(|_, w| w.cen().clear_bit());
+        tim3.cr1.modify(|_, w| w.cen().clear_bit());
+    }
+
+    /// Releases the channel handles, the timers keep running
+    pub fn release(self) -> (M, S) {
+        (self.master, self.slave)
+    }
+}
+
+/// Level of the gate input that lets the counter run
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub enum GatePolarity {
+    /// The counter runs while the input is high
+    ActiveHigh,
+    /// The counter runs while the input is low
+    ActiveLow,
+}
+
+/// Gates the counter of a PWM timer with an external input (gated slave mode)
+///
+/// The counter, and with it every PWM output of the timer, only advances while the gate input is
+/// active and freezes otherwise, so the outputs hold their level while gated off. The gate pin
+/// is a channel 1 or 2 input of the timer, that channel can't be used for PWM output at the same
+/// time. Only TIM1 and TIM3 have a slave mode controller.
+///
+/// Created from the [`Pwm`] of the timer with `gate_ch1` or `gate_ch2`.
+pub struct PwmGate<TIM, PINS, P, CHANNEL, PIN> {
+    pwm: Pwm<TIM, PINS, P>,
+    pin: PIN,
+    _channel: PhantomData<CHANNEL>,
+}
+
+macro_rules! pwm_gate {
+    ($($TIMX:ident: [$($CX:ident: ($gate:ident, $PINCX:ident, $CXN:ident, $ccxs:ident, $ccxp:ident, $ccxnp:ident, $ts:expr, $tix:literal),)+],)+) => {
+        $(
+            $(
+                impl<P, PINS: Pins<$TIMX, P>> Pwm<$TIMX, PINS, P> {
+                    #[doc = concat!("Gates the counter with the channel ", $tix, " input (`TI", $tix, "FP", $tix, "`)")]
+                    ///
+                    /// # Panics
+                    ///
+                    /// Panics if the channel (or its complementary output) is one of the PWM
+                    /// outputs, it is turned into an input.
+                    pub fn $gate<PIN>(
+                        self,
+                        pin: PIN,
+                        polarity: GatePolarity,
+                    ) -> PwmGate<$TIMX, PINS, P, $CX, PIN>
+                    where
+                        PIN: $PINCX<$TIMX>,
+                    {
+                        assert!(!PINS::$CX && !PINS::$CXN);
+                        let tim = &self.tim;
+                        tim.ccmr1_input().modify(|_, w| unsafe { w.$ccxs().bits(0b01) });
+                        tim.ccer.modify(|_, w| {
+                            w.$ccxp()
+                                .bit(polarity == GatePolarity::ActiveLow)
+                                .$ccxnp()
+                                .clear_bit()
+                        });
+                        tim.smcr.modify(|_, w| unsafe { w.ts().bits($ts).sms().bits(0b101) });
+                        PwmGate {
+                            pwm: self,
+                            pin,
+                            _channel: PhantomData,
+                        }
+                    }
+                }
+
+                impl<P, PINS, PIN> PwmGate<$TIMX, PINS, P, $CX, PIN> {
+                    /// Disables the gating and hands back the PWM and the gate pin
+                    ///
+                    /// The counter runs freely again and the channel is no longer mapped on its
+                    /// input.
+                    pub fn release(self) -> (Pwm<$TIMX, PINS, P>, PIN) {
+                        let tim = &self.pwm.tim;
+                        tim.smcr.modify(|_, w| unsafe { w.sms().bits(0b000).ts().bits(0b000) });
+                        tim.ccer.modify(|_, w| w.$ccxp().clear_bit());
+                        tim.ccmr1_input().modify(|_, w| unsafe { w.$ccxs().bits(0b00) });
+                        (self.pwm, self.pin)
+                    }
+                }
+            )+
+        )+
+    };
+}
+
+pwm_gate! {
+    TIM1: [
+        C1: (gate_ch1, PinC1, C1N, cc1s, cc1p, cc1np, 0b101, "1"),
+        C2: (gate_ch2, PinC2, C2N, cc2s, cc2p, cc2np, 0b110, "2"),
+    ],
+    TIM3: [
+        C1: (gate_ch1, PinC1, C1N, cc1s, cc1p, cc1np, 0b101, "1"),
+        C2: (gate_ch2, PinC2, C2N, cc2s, cc2p, cc2np, 0b110, "2"),
+    ],
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::gpio::{gpioa::PA6, Alternate, AF1};
+    use crate::time::U32Ext;
+    use crate::timers::{IcConfig, Timer};
+
+    // Type checked only, the timer registers don't exist on the host
+    #[allow(dead_code)]
+    fn pwm_input_capture_round_trip(tim: TIM3, pin: PA6<Alternate<AF1>>, rcc: &mut Rcc) {
+        let (pwm, mut c1) = tim3(tim, pin, rcc, 1000.hz());
+        hal::PwmPin::enable(&mut c1);
+        let (tim, pin) = pwm.release(c1);
+
+        let mut timer = Timer::tim3(tim, 1.hz(), rcc);
+        let mut capture = timer.input_capture::<C1, _>(pin, IcConfig::default());
+        let _ = capture.capture();
+        let pin = capture.release();
+        let tim = timer.release();
+
+        let (pwm, c1) = tim3(tim, pin, rcc, 1000.hz());
+        let _: (TIM3, PA6<Alternate<AF1>>) = pwm.release(c1);
+    }
+}
@@ -17,14 +17,395 @@ impl RccExt for RCC {
             sysclk: None,
             clock_src: SystClkSource::HSI,
             rcc: self,
+            generation: 0,
         }
     }
 }
 
 /// Constrained RCC peripheral
+///
+/// Only obtainable from [`CFGR::freeze`], so holding an `Rcc` guarantees that `clocks` describes
+/// the configured clock tree.
 pub struct Rcc {
     pub clocks: Clocks,
     pub regs: RCC, // TODO: should be pub(crate)
+    // CFGR and clocks to restore when leaving low power run
+    normal_run: Option<(u32, Clocks)>,
+}
+
+macro_rules! peripherals {
+    ($($PER:ident: ($enr:ident, $perXen:ident, $rstr:ident, $perXrst:ident),)+) => {
+        /// Peripherals whose bus clock is gated by the RCC
+        #[derive(Clone, Copy, PartialEq, Eq)]
+        pub enum Peripheral {
+            $(
+                $PER,
+            )+
+            /// Window watchdog (has no reset line)
+            Wwdg,
+        }
+
+        fn set_clock(rcc: &crate::pac::rcc::RegisterBlock, peripheral: Peripheral, on: bool) {
+            match peripheral {
+                $(
+                    Peripheral::$PER => rcc.$enr.modify(|_, w| w.$perXen().bit(on)),
+                )+
+                Peripheral::Wwdg => rcc.apbenr1.modify(|_, w| w.wwdgen().bit(on)),
+            }
+        }
+
+        fn pulse_reset(rcc: &crate::pac::rcc::RegisterBlock, peripheral: Peripheral) {
+            match peripheral {
+                $(
+                    Peripheral::$PER => {
+                        rcc.$rstr.modify(|_, w| w.$perXrst().set_bit());
+                        rcc.$rstr.modify(|_, w| w.$perXrst().clear_bit());
+                    }
+                )+
+                Peripheral::Wwdg => {}
+            }
+        }
+    };
+}
+
+peripherals! {
+    Dma: (ahbenr, dmaen, ahbrstr, dmarst),
+    Crc: (ahbenr, crcen, ahbrstr, crcrst),
+    GpioA: (iopenr, gpioaen, ioprstr, gpioarst),
+    GpioB: (iopenr, gpioben, ioprstr, gpiobrst),
+    GpioF: (iopenr, gpiofen, ioprstr, gpiofrst),
+    Tim3: (apbenr1, tim3en, apbrstr1, tim3rst),
+    Spi2: (apbenr1, spi2en, apbrstr1, spi2rst),
+    Usart2: (apbenr1, usart2en, apbrstr1, usart2rst),
+    I2c: (apbenr1, i2cen, apbrstr1, i2crst),
+    Dbg: (apbenr1, dbgen, apbrstr1, dbgrst),
+    Pwr: (apbenr1, pwren, apbrstr1, pwrrst),
+    Lptim: (apbenr1, lptimen, apbrstr1, lptimrst),
+    Syscfg: (apbenr2, syscfgen, apbrstr2, syscfgrst),
+    Tim1: (apbenr2, tim1en, apbrstr2, tim1rst),
+    Spi1: (apbenr2, spi1en, apbrstr2, spi1rst),
+    Usart1: (apbenr2, usart1en, apbrstr2, usart1rst),
+    Tim14: (apbenr2, tim14en, apbrstr2, tim14rst),
+    Tim16: (apbenr2, tim16en, apbrstr2, tim16rst),
+    Tim17: (apbenr2, tim17en, apbrstr2, tim17rst),
+    Adc: (apbenr2, adcen, apbrstr2, adcrst),
+    Comp1: (apbenr2, comp1en, apbrstr2, comp1rst),
+    Comp2: (apbenr2, comp2en, apbrstr2, comp2rst),
+    Led: (apbenr2, leden, apbrstr2, ledrst),
+}
+
+impl Rcc {
+    /// Enables the bus clock of `peripheral`
+    pub fn enable(&mut self, peripheral: Peripheral) {
+        enable(peripheral);
+    }
+
+    /// Disables the bus clock of `peripheral` to save power
+    pub fn disable(&mut self, peripheral: Peripheral) {
+        disable(peripheral);
+    }
+
+    /// Resets `peripheral` to its power-on register state
+    ///
+    /// The window watchdog has no reset line and is left untouched.
+    pub fn reset(&mut self, peripheral: Peripheral) {
+        reset(peripheral);
+    }
+}
+
+/// Factory HSI calibration words, one per `HSI_FS` frequency selection (4, 8, 16, 22.12 and 24 MHz)
+const HSI_CALIBRATION: u32 = 0x1FFF_0F00;
+
+impl Rcc {
+    /// Returns the current HSI trimming value (`ICSCR.HSI_TRIM`)
+    pub fn hsi_trim(&self) -> u16 {
+        self.regs.icscr.read().hsi_trim().bits()
+    }
+
+    /// Fine-tunes the HSI frequency
+    ///
+    /// `value` replaces the 13-bit `HSI_TRIM` field, larger values make the HSI faster. The step
+    /// size is not characterised in the datasheet and depends on the selected HSI frequency, so
+    /// applications compensating for temperature drift should measure the effect of a trim
+    /// change against their reference clock (e.g. a timer input capture of an external signal)
+    /// rather than assume a fixed ppm per step.
+    ///
+    /// The frozen [`Clocks`] keep reporting the nominal frequency.
+    pub fn trim_hsi(&mut self, value: u16) {
+        self.regs
+            .icscr
+            .modify(|_, w| unsafe { w.hsi_trim().bits(value & 0x1FFF) });
+    }
+
+    /// Returns the factory calibrated trimming value for the currently selected HSI frequency
+    ///
+    /// Passing this to [`Rcc::trim_hsi`] undoes any runtime trimming.
+    pub fn hsi_factory_trim(&self) -> u16 {
+        let fs = self.regs.icscr.read().hsi_fs().bits() as u32;
+        // NOTE(unsafe) read-only access to the factory calibration area
+        let cal = unsafe { core::ptr::read_volatile((HSI_CALIBRATION + 4 * fs) as *const u32) };
+        (cal & 0x1FFF) as u16
+    }
+
+    /// Trims the HSI for `temperature` (in °C) from a calibration curve, returns the applied
+    /// trimming value
+    ///
+    /// `curve` holds `(temperature, offset)` points sorted by temperature, where `offset` is
+    /// added to the factory trim. Between two points the offset is interpolated linearly, outside
+    /// of the curve the nearest point is used. An empty curve restores the factory trim.
+    ///
+    /// The HAL has no ADC driver yet, read the internal temperature sensor with the ADC and
+    /// convert it using the factory calibration values before calling this, then again whenever
+    /// the temperature changed noticeably. The curve has to be measured for the product, the
+    /// drift of the HSI over temperature is not characterised in the datasheet.
+    ///
+    /// This is a best-effort compensation: its accuracy is bounded by the temperature sensor
+    /// (a few °C), the trim step size and the part-to-part spread of the curve. It can keep a
+    /// UART within tolerance over a wide range, but is no substitute for a crystal.
+    pub fn compensate_hsi(&mut self, temperature: i16, curve: &[(i16, i16)]) -> u16 {
+        let offset = match curve {
+            [] => 0,
+            [(t0, o0), ..] if temperature <= *t0 => *o0,
+            [.., (tn, on)] if temperature >= *tn => *on,
+            _ => curve
+                .windows(2)
+                .find(|w| temperature <= w[1].0)
+                .map(|w| {
+                    let ((t0, o0), (t1, o1)) = (w[0], w[1]);
+                    let (t0, o0) = (i32::from(t0), i32::from(o0));
+                    let span = (i32::from(t1) - t0).max(1);
+                    (o0 + (i32::from(o1) - o0) * (i32::from(temperature) - t0) / span) as i16
+                })
+                .unwrap_or(0),
+        };
+
+        let trim = (i32::from(self.hsi_factory_trim()) + i32::from(offset)).clamp(0, 0x1FFF) as u16;
+        self.trim_hsi(trim);
+        trim
+    }
+}
+
+impl Rcc {
+    /// Switches the HSI off to save power, if it isn't needed by the system clock
+    ///
+    /// Returns false and leaves the HSI running when SYSCLK is the HSI, or the PLL fed from the
+    /// HSI, as confirmed by the `SWS` readback. With the clock sources currently supported by
+    /// [`CFGR`] this is always the case, so this only takes effect once SYSCLK runs from another
+    /// source.
+    pub fn disable_hsi(&mut self) -> bool {
+        let in_use = match self.regs.cfgr.read().sws().bits() {
+            0b000 => true,
+            0b010 => self.regs.pllcfgr.read().pllsrc().bit_is_clear(),
+            _ => false,
+        };
+
+        if !in_use {
+            self.regs.cr.modify(|_, w| w.hsion().clear_bit());
+        }
+        !in_use
+    }
+}
+
+/// Division factor applied to the HSI when it drives SYSCLK directly (`HSIDIV`)
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub enum HsiDiv {
+    Div1 = 0b000,
+    Div2 = 0b001,
+    Div4 = 0b010,
+    Div8 = 0b011,
+    Div16 = 0b100,
+    Div32 = 0b101,
+    Div64 = 0b110,
+    Div128 = 0b111,
+}
+
+impl Rcc {
+    /// Runs SYSCLK from the HSI divided by `div` to save power
+    ///
+    /// The PLL is switched off, the AHB and APB prescalers are set to 1 and the flash goes to
+    /// zero wait states. Unlike stop mode the peripherals keep running, only slower. Calling this
+    /// again while in low power run just changes the divider.
+    ///
+    /// Returns the new clocks, which are also stored in `self.clocks`. Drivers configured with
+    /// the previous `Clocks` (serial baud rates, timer periods, delays) keep their old dividers
+    /// and run at the wrong rate until they are configured again.
+    pub fn enter_low_power_run(&mut self, flash: &mut crate::pac::FLASH, div: HsiDiv) -> Clocks {
+        if self.normal_run.is_none() {
+            self.normal_run = Some((self.regs.cfgr.read().bits(), self.clocks));
+        }
+
+        // Switch to the undivided HSI first, so the PLL can be turned off
+        self.regs.cfgr.modify(|_, w| unsafe { w.sw().bits(0b000) });
+        while self.regs.cfgr.read().sws().bits() != 0b000 {}
+        self.regs.cr.modify(|_, w| w.pllon().clear_bit());
+
+        self.regs
+            .cr
+            .modify(|_, w| unsafe { w.hsidiv().bits(div as u8) });
+        self.regs
+            .cfgr
+            .modify(|_, w| unsafe { w.hpre().bits(0b0111).ppre().bits(0b011) });
+        flash.acr.write(|w| w.latency().clear_bit());
+
+        let sysclk = Hertz(self::inner::HSI >> (div as u8));
+        self.clocks = Clocks {
+            hclk: sysclk,
+            pclk: sysclk,
+            sysclk,
+            source: ClockSource::current(&self.regs),
+            generation: self.clocks.generation.wrapping_add(1),
+        };
+        self.clocks
+    }
+
+    /// Restores the clock configuration that was active before
+    /// [`enter_low_power_run`](Self::enter_low_power_run)
+    ///
+    /// Returns the restored clocks, which are also stored in `self.clocks`. The same caveat
+    /// about drivers holding the low power `Clocks` applies.
+    pub fn exit_low_power_run(&mut self, flash: &mut crate::pac::FLASH) -> Clocks {
+        if let Some((cfgr, clocks)) = self.normal_run.take() {
+            self.regs
+                .cr
+                .modify(|_, w| unsafe { w.hsidiv().bits(0b000) });
+            flash
+                .acr
+                .write(|w| w.latency().bit(clocks.sysclk.0 > 24_000_000));
+
+            let sw = (cfgr & 0b111) as u8;
+            if sw == 0b010 {
+                self.regs.cr.modify(|_, w| w.pllon().set_bit());
+                while self.regs.cr.read().pllrdy().bit_is_clear() {}
+            }
+            self.regs.cfgr.write(|w| unsafe { w.bits(cfgr) });
+            while self.regs.cfgr.read().sws().bits() != sw {}
+
+            self.clocks = Clocks {
+                generation: self.clocks.generation.wrapping_add(1),
+                ..clocks
+            };
+        }
+        self.clocks
+    }
+
+    /// Gives up the frozen clocks to configure them again
+    ///
+    /// SYSCLK is switched back to the undivided HSI and the PLL is turned off, then the returned
+    /// `CFGR` is set up and frozen like the one from [`RccExt::configure`]. The new
+    /// [`Clocks::generation`] is one higher than the current one.
+    ///
+    /// Peripheral drivers keep the dividers computed from the old `Clocks` and run at the wrong
+    /// rate afterwards. Release and create them again with the new `Rcc`, or reconfigure them
+    /// where the driver supports it (e.g. `Serial::set_baud_rate`).
+    pub fn reconfigure(self) -> CFGR {
+        self.regs
+            .cr
+            .modify(|_, w| unsafe { w.hsidiv().bits(0b000) });
+        self.regs.cfgr.modify(|_, w| unsafe { w.sw().bits(0b000) });
+        while self.regs.cfgr.read().sws().bits() != 0b000 {}
+        self.regs.cr.modify(|_, w| w.pllon().clear_bit());
+
+        CFGR {
+            hclk: None,
+            pclk: None,
+            sysclk: None,
+            clock_src: SystClkSource::HSI,
+            rcc: self.regs,
+            generation: self.clocks.generation.wrapping_add(1),
+        }
+    }
+}
+
+/// Clock routed to the low-speed clock output
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub enum LscoSource {
+    /// Internal low speed RC oscillator, it drifts by several percent
+    Lsi,
+    /// External 32.768 kHz crystal, where the package provides its pins
+    Lse,
+}
+
+/// Pin that can output the low-speed clock
+///
+/// Only PA2 has the LSCO function. It is not an alternate function: while LSCO is enabled the
+/// output overrides the GPIO configuration of the pin.
+pub trait LscoPin {}
+
+impl<MODE> LscoPin for crate::gpio::gpioa::PA2<MODE> {}
+
+/// Low-speed clock output, holds the pin while the clock is output
+pub struct Lsco<PIN> {
+    pin: PIN,
+}
+
+impl<PIN: LscoPin> Lsco<PIN> {
+    /// Stops the clock output and releases the pin
+    ///
+    /// The source oscillator is left running, it may be used by the RTC or IWDG.
+    pub fn release(self) -> PIN {
+        // NOTE(unsafe) the read-modify-write is done in a critical section, LSCOEN is owned by
+        // this handle
+        cortex_m::interrupt::free(|_| unsafe {
+            (*RCC::ptr()).bdcr.modify(|_, w| w.lscoen().clear_bit())
+        });
+        self.pin
+    }
+}
+
+impl Rcc {
+    /// Outputs the LSI or LSE on `pin`, e.g. to share a 32 kHz timebase with a companion chip
+    ///
+    /// The source oscillator is started if needed, which blocks until it is ready; for the
+    /// LSE this takes up to a few seconds and never finishes without a crystal. `BDCR` is in
+    /// the backup domain, so backup domain write access is enabled in `pwr` and left enabled.
+    pub fn enable_lsco<PIN: LscoPin>(
+        &mut self,
+        pin: PIN,
+        source: LscoSource,
+        pwr: &mut crate::pac::PWR,
+    ) -> Lsco<PIN> {
+        self.enable(Peripheral::Pwr);
+        pwr.cr1.modify(|_, w| w.dbp().set_bit());
+
+        match source {
+            LscoSource::Lsi => {
+                self.regs.csr.modify(|_, w| w.lsion().set_bit());
+                while self.regs.csr.read().lsirdy().bit_is_clear() {}
+            }
+            LscoSource::Lse => {
+                self.regs.bdcr.modify(|_, w| w.lseon().set_bit());
+                while self.regs.bdcr.read().lserdy().bit_is_clear() {}
+            }
+        }
+        cortex_m::interrupt::free(|_| {
+            self.regs.bdcr.modify(|_, w| {
+                w.lscosel()
+                    .bit(source == LscoSource::Lse)
+                    .lscoen()
+                    .set_bit()
+            })
+        });
+
+        Lsco { pin }
+    }
+}
+
+// All peripheral clock gating goes through these two functions, so that drivers releasing their
+// peripheral without a `&mut Rcc` at hand can't race with other read-modify-writes of the same
+// enable register.
+pub(crate) fn enable(peripheral: Peripheral) {
+    // NOTE(unsafe) the read-modify-write is done in a critical section
+    cortex_m::interrupt::free(|_| set_clock(unsafe { &*RCC::ptr() }, peripheral, true));
+}
+
+pub(crate) fn disable(peripheral: Peripheral) {
+    // NOTE(unsafe) the read-modify-write is done in a critical section
+    cortex_m::interrupt::free(|_| set_clock(unsafe { &*RCC::ptr() }, peripheral, false));
+}
+
+pub(crate) fn reset(peripheral: Peripheral) {
+    // NOTE(unsafe) the read-modify-write is done in a critical section
+    cortex_m::interrupt::free(|_| pulse_reset(unsafe { &*RCC::ptr() }, peripheral));
 }
 
 /// RCC for Air001.
@@ -33,6 +414,7 @@ mod inner {
 
     pub(super) const HSI: u32 = 24_000_000; // 24 MHz
 
+    #[allow(clippy::upper_case_acronyms)]
     pub(super) enum SystClkSource {
         HSI,
     }
@@ -43,18 +425,69 @@ mod inner {
         }
     }
 
-    pub(super) fn enable_clock(rcc: &mut RCC, c_src: &SystClkSource) {
+    /// AHB prescaler bits (`HPRE`) dividing `sysclk` down to the closest power of two to `hclk`
+    pub(super) fn hpre_bits(sysclk: u32, hclk: u32) -> u8 {
+        match sysclk / hclk.max(1) {
+            // a higher frequency than SYSCLK can't be reached, run undivided
+            0 | 1 => 0b0111,
+            2 => 0b1000,
+            3..=5 => 0b1001,
+            6..=11 => 0b1010,
+            12..=39 => 0b1011,
+            40..=95 => 0b1100,
+            96..=191 => 0b1101,
+            192..=383 => 0b1110,
+            _ => 0b1111,
+        }
+    }
+
+    /// APB prescaler bits (`PPRE`) dividing `hclk` down to the closest power of two to `pclk`
+    pub(super) fn ppre_bits(hclk: u32, pclk: u32) -> u8 {
+        match hclk / pclk.max(1) {
+            0 | 1 => 0b011,
+            2 => 0b100,
+            3..=5 => 0b101,
+            6..=11 => 0b110,
+            _ => 0b111,
+        }
+    }
+
+    /// Polls `ready` until it returns true, at most `polls` times if given
+    pub(super) fn wait(polls: Option<u32>, ready: impl Fn() -> bool) -> bool {
+        match polls {
+            None => {
+                while !ready() {}
+                true
+            }
+            Some(polls) => (0..polls).any(|_| ready()),
+        }
+    }
+
+    pub(super) fn enable_clock(
+        rcc: &mut RCC,
+        c_src: &SystClkSource,
+        polls: Option<u32>,
+    ) -> Result<(), super::ClockError> {
         match c_src {
             SystClkSource::HSI => {
                 // enable HSI
                 rcc.cr.write(|w| w.hsion().set_bit());
                 // wait until HSI is ready
-                while rcc.cr.read().hsirdy().bit_is_clear() {}
+                if !wait(polls, || rcc.cr.read().hsirdy().bit_is_set()) {
+                    return Err(super::ClockError::HsiNotReady);
+                }
             }
         }
+        Ok(())
     }
 
-    pub(super) fn enable_pll(rcc: &mut RCC, c_src: &SystClkSource, ppre_bits: u8, hpre_bits: u8) {
+    pub(super) fn enable_pll(
+        rcc: &mut RCC,
+        c_src: &SystClkSource,
+        ppre_bits: u8,
+        hpre_bits: u8,
+        polls: Option<u32>,
+    ) -> Result<(), super::ClockError> {
         // Set PLL source
         match c_src {
             SystClkSource::HSI => rcc.pllcfgr.modify(|_, w| w.pllsrc().clear_bit()),
@@ -62,7 +495,10 @@ mod inner {
 
         // Enable PLL and wait until PLL is ready
         rcc.cr.modify(|_, w| w.pllon().set_bit());
-        while rcc.cr.read().pllrdy().bit_is_clear() {}
+        if !wait(polls, || rcc.cr.read().pllrdy().bit_is_set()) {
+            rcc.cr.modify(|_, w| w.pllon().clear_bit());
+            return Err(super::ClockError::PllNotReady);
+        }
 
         // Use PLL CLK as SYSCLK, set APB and AHB prescaler
         rcc.cfgr.modify(|_, w| unsafe {
@@ -73,15 +509,37 @@ mod inner {
                 .sw()
                 .bits(0b010) // PLL CLK
         });
+        if !wait(polls, || rcc.cfgr.read().sws().bits() == 0b010) {
+            // Fall back to the HSI
+            rcc.cfgr.modify(|_, w| unsafe { w.sw().bits(0b000) });
+            // the PLL can only be stopped once it no longer drives SYSCLK
+            if wait(polls, || rcc.cfgr.read().sws().bits() == 0b000) {
+                rcc.cr.modify(|_, w| w.pllon().clear_bit());
+            }
+            return Err(super::ClockError::SwitchTimeout);
+        }
+        Ok(())
     }
 }
 
+/// Clock configuration errors of [`CFGR::try_freeze`]
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub enum ClockError {
+    /// The HSI did not become ready
+    HsiNotReady,
+    /// The PLL did not lock
+    PllNotReady,
+    /// The system clock switch was not confirmed by `SWS`
+    SwitchTimeout,
+}
+
 pub struct CFGR {
     hclk: Option<u32>,
     pclk: Option<u32>,
     sysclk: Option<u32>,
     clock_src: SystClkSource,
     rcc: RCC,
+    generation: u32,
 }
 
 impl CFGR {
@@ -109,7 +567,32 @@ impl CFGR {
         self
     }
 
-    pub fn freeze(mut self, flash: &mut crate::pac::FLASH) -> Rcc {
+    pub fn freeze(self, flash: &mut crate::pac::FLASH) -> Rcc {
+        match self.freeze_polling(flash, None) {
+            Ok(rcc) => rcc,
+            // Without a poll limit every wait loops until the clock is ready
+            Err(_) => unreachable!(),
+        }
+    }
+
+    /// Applies the clock configuration, giving up if a clock is not ready in time
+    ///
+    /// Every wait for a clock to become ready or for the system clock switch polls its flag at
+    /// most `max_polls` times. On failure the system keeps running from HSISYS and the `CFGR` is
+    /// handed back, so a fallback configuration (e.g. without PLL) can be frozen instead.
+    pub fn try_freeze(
+        self,
+        flash: &mut crate::pac::FLASH,
+        max_polls: u32,
+    ) -> Result<Rcc, (ClockError, CFGR)> {
+        self.freeze_polling(flash, Some(max_polls))
+    }
+
+    fn freeze_polling(
+        mut self,
+        flash: &mut crate::pac::FLASH,
+        polls: Option<u32>,
+    ) -> Result<Rcc, (ClockError, CFGR)> {
         // Default to HSI
         let sysclk = self.sysclk.unwrap_or(self::inner::HSI);
 
@@ -125,38 +608,20 @@ impl CFGR {
             r_sysclk = src_clk_freq;
             enable_pll = false;
         } else {
-            r_sysclk = src_clk_freq * 2;
+            r_sysclk = src_clk_freq.saturating_mul(2);
             enable_pll = true;
         }
 
         let hpre_bits = self
             .hclk
-            .map(|hclk| match r_sysclk / hclk {
-                0 => unreachable!(),
-                1 => 0b0111,
-                2 => 0b1000,
-                3..=5 => 0b1001,
-                6..=11 => 0b1010,
-                12..=39 => 0b1011,
-                40..=95 => 0b1100,
-                96..=191 => 0b1101,
-                192..=383 => 0b1110,
-                _ => 0b1111,
-            })
+            .map(|hclk| self::inner::hpre_bits(r_sysclk, hclk))
             .unwrap_or(0b0111);
 
         let hclk = r_sysclk / (1 << (hpre_bits - 0b0111));
 
         let ppre_bits = self
             .pclk
-            .map(|pclk| match hclk / pclk {
-                0 => unreachable!(),
-                1 => 0b011,
-                2 => 0b100,
-                3..=5 => 0b101,
-                6..=11 => 0b110,
-                _ => 0b111,
-            })
+            .map(|pclk| self::inner::ppre_bits(hclk, pclk))
             .unwrap_or(0b011);
 
         let ppre: u8 = 1 << (ppre_bits - 0b011);
@@ -166,11 +631,17 @@ impl CFGR {
         flash.acr.write(|w| w.latency().bit(r_sysclk > 24_000_000));
 
         // Enable the requested clock
-        self::inner::enable_clock(&mut self.rcc, &self.clock_src);
+        if let Err(e) = self::inner::enable_clock(&mut self.rcc, &self.clock_src, polls) {
+            return Err((e, self));
+        }
 
         // Enable PLL
         if enable_pll {
-            self::inner::enable_pll(&mut self.rcc, &mut self.clock_src, ppre_bits, hpre_bits)
+            if let Err(e) =
+                self::inner::enable_pll(&mut self.rcc, &self.clock_src, ppre_bits, hpre_bits, polls)
+            {
+                return Err((e, self));
+            }
         } else {
             // Use HSI as source
             self.rcc.cfgr.modify(|_, w| unsafe {
@@ -181,27 +652,63 @@ impl CFGR {
                     .sw()
                     .bits(0b000) // HSISYS
             });
+            if !self::inner::wait(polls, || self.rcc.cfgr.read().sws().bits() == 0b000) {
+                return Err((ClockError::SwitchTimeout, self));
+            }
         }
 
-        Rcc {
+        Ok(Rcc {
             clocks: Clocks {
                 hclk: Hertz(hclk),
                 pclk: Hertz(pclk),
                 sysclk: Hertz(sysclk),
+                source: ClockSource::current(&self.rcc),
+                generation: self.generation,
             },
             regs: self.rcc,
+            normal_run: None,
+        })
+    }
+}
+
+/// System clock source, as reported by `CFGR.SWS`
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub enum ClockSource {
+    /// HSISYS, the HSI after the `HSIDIV` divider
+    Hsi,
+    /// External high speed clock
+    Hse,
+    /// PLL output
+    Pll,
+    /// Internal low speed clock
+    Lsi,
+    /// External low speed clock
+    Lse,
+}
+
+impl ClockSource {
+    fn current(rcc: &RCC) -> Self {
+        match rcc.cfgr.read().sws().bits() {
+            0b001 => ClockSource::Hse,
+            0b010 => ClockSource::Pll,
+            0b011 => ClockSource::Lsi,
+            0b100 => ClockSource::Lse,
+            _ => ClockSource::Hsi,
         }
     }
 }
 
 /// Frozen clock frequency
 ///
-/// The existence of this value indicates that the clock configuration can no longer be changed.
+/// The existence of this value indicates that the clock configuration can no longer be changed
+/// without going through [`Rcc::reconfigure`] (or low power run), which bumps the generation.
 #[derive(Clone, Copy)]
 pub struct Clocks {
     hclk: Hertz,
     pclk: Hertz,
     sysclk: Hertz,
+    source: ClockSource,
+    generation: u32,
 }
 
 /// Frozen clock frequencies
@@ -220,4 +727,84 @@ impl Clocks {
     pub fn sysclk(&self) -> Hertz {
         self.sysclk
     }
+
+    /// Returns the source of the system clock, read back from the hardware when the clocks
+    /// were configured
+    pub fn source(&self) -> ClockSource {
+        self.source
+    }
+
+    /// Returns the number of times the clocks were changed since the first `freeze`
+    ///
+    /// A driver holding a copy of `Clocks` can compare it against `rcc.clocks` to detect that
+    /// its dividers are stale.
+    pub fn generation(&self) -> u32 {
+        self.generation
+    }
+
+    /// Returns the kernel clock frequency of `peripheral`
+    ///
+    /// DMA, CRC and the GPIO ports run from the AHB clock, everything else from the APB clock.
+    /// If the APB is prescaled from the AHB, the frequency fed into the timers is doubled.
+    pub fn peripheral_clock(&self, peripheral: Peripheral) -> Hertz {
+        match peripheral {
+            Peripheral::Dma
+            | Peripheral::Crc
+            | Peripheral::GpioA
+            | Peripheral::GpioB
+            | Peripheral::GpioF => self.hclk,
+            Peripheral::Tim1
+            | Peripheral::Tim3
+            | Peripheral::Tim14
+            | Peripheral::Tim16
+            | Peripheral::Tim17 => {
+                if self.hclk.0 == self.pclk.0 {
+                    self.pclk
+                } else {
+                    Hertz(self.pclk.0.saturating_mul(2))
+                }
+            }
+            _ => self.pclk,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::inner::{hpre_bits, ppre_bits, wait};
+
+    #[test]
+    fn hpre_covers_all_dividers() {
+        let sysclk = 48_000_000;
+        assert_eq!(hpre_bits(sysclk, sysclk), 0b0111);
+        assert_eq!(hpre_bits(sysclk, sysclk / 2), 0b1000);
+        assert_eq!(hpre_bits(sysclk, sysclk / 4), 0b1001);
+        assert_eq!(hpre_bits(sysclk, sysclk / 512), 0b1111);
+    }
+
+    #[test]
+    fn hpre_edge_cases() {
+        // above SYSCLK runs undivided, zero asks for the slowest clock
+        assert_eq!(hpre_bits(24_000_000, u32::MAX), 0b0111);
+        assert_eq!(hpre_bits(24_000_000, 0), 0b1111);
+        assert_eq!(hpre_bits(24_000_000, 1), 0b1111);
+    }
+
+    #[test]
+    fn ppre_edge_cases() {
+        let hclk = 24_000_000;
+        assert_eq!(ppre_bits(hclk, hclk), 0b011);
+        assert_eq!(ppre_bits(hclk, u32::MAX), 0b011);
+        assert_eq!(ppre_bits(hclk, hclk / 2), 0b100);
+        assert_eq!(ppre_bits(hclk, hclk / 16), 0b111);
+        assert_eq!(ppre_bits(hclk, 0), 0b111);
+    }
+
+    #[test]
+    fn wait_gives_up_after_polls() {
+        assert!(!wait(Some(0), || true));
+        assert!(!wait(Some(u32::MAX >> 16), || false));
+        assert!(wait(Some(1), || true));
+        assert!(wait(None, || true));
+    }
 }
@@ -1,12 +1,78 @@
+//! # Universal Synchronous Asynchronous Receiver Transmitter
+//!
+//! The Air001 USART has no TX/RX FIFO: there are no `FIFOEN` or threshold bits in `CR1`/`CR3`
+//! and no `TXFT`/`RXFT` flags, only a single data register per direction. At high baud rates
+//! service the receiver from the `Rxne` interrupt or with DMA to avoid overruns.
+//!
+//! The sampling method is fixed as well: there is no `ONEBIT` bit in `CR3`, every bit is sampled
+//! three times with majority voting. This gives good noise immunity but little tolerance for
+//! baud rate deviation, so keep the clock error low (see [`closest_baud`]) on marginal links.
+//!
+//! The baud rate divider is derived from the `Rcc` passed to the constructors, which can only be
+//! obtained from `CFGR::freeze`. Configure and freeze the clocks first and create the serial
+//! afterwards; changing the clocks later requires configuring the serial again.
+//!
+//! ## Splitting
+//!
+//! [`Serial::split`] hands out independent [`Tx`] and [`Rx`] halves, e.g. to move the receiver
+//! into an interrupt handler. [`Serial::join`] is the inverse: it checks that both halves belong
+//! to the same USART and rebuilds the `Serial`, which can then be reconfigured or released.
+//!
+//! ## RS485
+//!
+//! There is no hardware driver enable output: `CR3` has no `DEM`/`DEP` bits and `CR1` no
+//! `DEAT`/`DEDT` timing fields. Drive the transceiver's DE input from a GPIO, asserted before the
+//! first byte is written and released only after `flush` reports the last frame complete (`TC`,
+//! not `TXE`), otherwise the final byte is truncated.
+//!
+//! ## LIN
+//!
+//! There is no LIN mode: `CR2` has no `LINEN`, `LBDL` or `LBDIE` bits and `SR` no `LBD` flag, so
+//! break fields are not detected in hardware. A break arrives as a zero frame with a framing
+//! error, reported by `read` as [`Error::Framing`]; a LIN slave can treat that as the start of a
+//! header and resynchronize on the following sync byte.
+//!
+//! ## Async
+//!
+//! With the `async` feature, `Serial` and the split `Rx`/`Tx` halves implement
+//! `embedded_io_async::{Read, Write}`. The futures wait on the `RXNE`, `TXE` and `TC`
+//! interrupts, so the application has to call the matching handler from the USART interrupt
+//! and unmask it in the NVIC:
+//!
+//! ```ignore
+//! #[interrupt]
+//! fn USART1() {
+//!     air001_hal::serial::on_usart1_interrupt();
+//! }
+//!
+//! unsafe { NVIC::unmask(Interrupt::USART1) };
+//! ```
+//!
+//! Reception is buffered: once a read has been started, the interrupt handler keeps moving
+//! received bytes into a ring buffer of `RX_BUFFER_SIZE` bytes per USART, so nothing is lost
+//! between two reads as long as the buffer does not fill up. Don't mix the async reads with the
+//! blocking `read` on the same USART, the handler takes the bytes out of the data register.
+//!
+//! For sustained high rates, [`RxDmaCircular`] implements `embedded_io_async::Read` too. It is
+//! woken by the idle line interrupt through the USART handler and by the half and full transfer
+//! interrupts of its channel, which need `dma::on_channel1_interrupt` or
+//! `dma::on_channel2_3_interrupt` in the DMA handlers.
+
 use core::{
     convert::Infallible,
     fmt::{Result, Write},
     ops::Deref,
+    sync::atomic::{compiler_fence, Ordering},
 };
 
 use embedded_hal::prelude::*;
 
-use crate::{gpio::*, rcc::Rcc, time::Bps};
+use crate::{
+    dma,
+    gpio::*,
+    rcc::{Clocks, Peripheral, Rcc},
+    time::{Bps, Hertz},
+};
 
 use core::marker::PhantomData;
 
@@ -25,6 +91,7 @@ pub enum Error {
 }
 
 /// Interrupt event
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
 pub enum Event {
     /// New data has been received
     Rxne,
@@ -34,8 +101,247 @@ pub enum Event {
     Idle,
 }
 
+bitflags::bitflags! {
+    /// Set of interrupt events
+    #[derive(Clone, Copy, PartialEq, Eq, Debug)]
+    pub struct Events: u8 {
+        /// New data has been received
+        const RXNE = 1 << 0;
+        /// New data can be sent
+        const TXE = 1 << 1;
+        /// Idle line state detected
+        const IDLE = 1 << 2;
+    }
+}
+
+bitflags::bitflags! {
+    /// Status flags, with the same bit positions as in `SR`
+    #[derive(Clone, Copy, PartialEq, Eq, Debug)]
+    pub struct Status: u16 {
+        /// Parity error
+        const PE = 1 << 0;
+        /// Framing error
+        const FE = 1 << 1;
+        /// Noise detected
+        const NE = 1 << 2;
+        /// Overrun error
+        const ORE = 1 << 3;
+        /// Idle line detected
+        const IDLE = 1 << 4;
+        /// Received data ready to be read
+        const RXNE = 1 << 5;
+        /// Transmission complete
+        const TC = 1 << 6;
+        /// Transmit data register empty
+        const TXE = 1 << 7;
+        /// CTS input changed
+        const CTS = 1 << 9;
+        /// Auto baud rate detection finished
+        const ABRF = 1 << 10;
+        /// Auto baud rate detection failed
+        const ABRE = 1 << 11;
+    }
+}
+
+impl From<Event> for Events {
+    fn from(event: Event) -> Self {
+        match event {
+            Event::Rxne => Events::RXNE,
+            Event::Txe => Events::TXE,
+            Event::Idle => Events::IDLE,
+        }
+    }
+}
+
+/// Returns the baud rate closest to `desired` that the USART can generate from `clock`, together
+/// with its deviation from `desired` in ppm
+///
+/// Assumes the default 16x oversampling, where the divider is `clock / baud` with a 4 bit
+/// fraction and must lie between 16 and 0xFFFF.
+pub fn closest_baud(clock: Hertz, desired: Bps) -> (Bps, i32) {
+    let clock = u64::from(clock.0);
+    let desired = u64::from(desired.0.max(1));
+
+    let brr = ((clock + desired / 2) / desired).clamp(16, 0xFFFF);
+    let actual = (clock + brr / 2) / brr;
+    let error = (actual as i64 - desired as i64) * 1_000_000 / desired as i64;
+
+    (Bps(actual as u32), error as i32)
+}
+
+/// Number of bits per frame, excluding start and stop bits
+///
+/// When parity is enabled the most significant bit of the frame is the parity bit, so `Bits8`
+/// then carries 7 data bits and `Bits9` carries 8.
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub enum WordLength {
+    /// 8 bit frames
+    Bits8,
+    /// 9 bit frames, use the `read_u16` and `write_u16` methods of `Serial`, `Rx` and `Tx` to
+    /// transfer the 9th bit, the `u8` interfaces truncate it
+    Bits9,
+}
+
+/// Parity bit generation and checking
+///
+/// The parity bit takes the most significant bit of the frame instead of being appended, see
+/// [`WordLength`]: 8 data bits with parity (8E1, 8O1) need [`WordLength::Bits9`]. The bit time
+/// and thus the baud rate divider are not affected. Received parity errors are reported as
+/// [`Error::Parity`].
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub enum Parity {
+    /// No parity bit
+    None,
+    /// Even number of ones in the frame, including the parity bit
+    Even,
+    /// Odd number of ones in the frame, including the parity bit
+    Odd,
+}
+
+/// Number of stop bits
+///
+/// The half bit variants are meant for smartcard links. The Air001 USART has no smartcard mode
+/// (no `SCEN` or guard time), only the frame format is changed.
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub enum StopBits {
+    /// 1 stop bit
+    Stop1 = 0b00,
+    /// 0.5 stop bits
+    Stop0p5 = 0b01,
+    /// 2 stop bits
+    Stop2 = 0b10,
+    /// 1.5 stop bits
+    Stop1p5 = 0b11,
+}
+
+/// Receiver oversampling rate
+///
+/// [`Over8`](Oversampling::Over8) doubles the highest baud rate reachable from a given clock
+/// (PCLK / 8 instead of PCLK / 16), at the cost of a lower tolerance to clock deviation and
+/// noise, as every bit is sampled half as often.
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub enum Oversampling {
+    /// 16 samples per bit
+    Over16,
+    /// 8 samples per bit
+    Over8,
+}
+
+/// Returns the BRR value for `baud_rate` at `clock`
+///
+/// The divider is rounded to the nearest integer like in [`closest_baud`]. With 8x oversampling
+/// the fraction is 3 bits wide and BRR[3] has to stay cleared.
+fn brr(clock: Hertz, baud_rate: Bps, oversampling: Oversampling) -> u32 {
+    let baud_rate = u64::from(baud_rate.0.max(1));
+    // can't exceed `clock`, so it fits in a u32
+    let div = ((u64::from(clock.0) + baud_rate / 2) / baud_rate) as u32;
+    match oversampling {
+        // the 12.4 fixed point divider is simply clock / baud
+        Oversampling::Over16 => div.clamp(16, 0xFFFF),
+        Oversampling::Over8 => {
+            let div = div.clamp(8, 0x7FFF);
+            ((div & !0b111) << 1) | (div & 0b111)
+        }
+    }
+}
+
+/// Writes the frame format of `config` to CR1..CR3
+fn frame_format(usart: &SerialRegisterBlock, config: &SerialConfig) {
+    usart.cr1.modify(|_, w| {
+        w.m()
+            .bit(config.word_length == WordLength::Bits9)
+            .pce()
+            .bit(config.parity != Parity::None)
+            .ps()
+            .bit(config.parity == Parity::Odd)
+    });
+
+    // Reset other registers to disable advanced USART features
+    usart
+        .cr2
+        .write(|w| unsafe { w.stop().bits(config.stop_bits as u8) });
+    usart
+        .cr3
+        .write(|w| w.over8().bit(config.oversampling == Oversampling::Over8));
+}
+
+/// Serial configuration
+///
+/// Created from a baud rate (`115_200.bps().into()`) or with [`Default`], which is 115200 baud
+/// with 8 bit frames, no parity, 1 stop bit and 16x oversampling. The other settings are changed
+/// builder-style:
+///
+/// ```ignore
+/// let config = SerialConfig::default()
+///     .baud_rate(9_600.bps())
+///     .word_length(WordLength::Bits9)
+///     .parity(Parity::Even)
+///     .stop_bits(StopBits::Stop2);
+/// let serial = Serial::usart1(dp.USART1, (tx, rx), config, &mut rcc);
+/// ```
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub struct SerialConfig {
+    baud_rate: Bps,
+    word_length: WordLength,
+    parity: Parity,
+    stop_bits: StopBits,
+    oversampling: Oversampling,
+}
+
+impl SerialConfig {
+    pub fn baud_rate<B>(mut self, baud_rate: B) -> Self
+    where
+        B: Into<Bps>,
+    {
+        self.baud_rate = baud_rate.into();
+        self
+    }
+
+    pub fn word_length(mut self, word_length: WordLength) -> Self {
+        self.word_length = word_length;
+        self
+    }
+
+    pub fn parity(mut self, parity: Parity) -> Self {
+        self.parity = parity;
+        self
+    }
+
+    pub fn stop_bits(mut self, stop_bits: StopBits) -> Self {
+        self.stop_bits = stop_bits;
+        self
+    }
+
+    pub fn oversampling(mut self, oversampling: Oversampling) -> Self {
+        self.oversampling = oversampling;
+        self
+    }
+}
+
+impl Default for SerialConfig {
+    fn default() -> Self {
+        SerialConfig {
+            baud_rate: Bps(115_200),
+            word_length: WordLength::Bits8,
+            parity: Parity::None,
+            stop_bits: StopBits::Stop1,
+            oversampling: Oversampling::Over16,
+        }
+    }
+}
+
+impl From<Bps> for SerialConfig {
+    fn from(baud_rate: Bps) -> Self {
+        SerialConfig::default().baud_rate(baud_rate)
+    }
+}
+
 pub trait TxPin<USART> {}
 pub trait RxPin<USART> {}
+/// Request to send output, driven low while the receiver can take data
+pub trait RtsPin<USART> {}
+/// Clear to send input, the transmitter only starts a frame while it is low
+pub trait CtsPin<USART> {}
 
 macro_rules! usart_pins {
     ($($USART:ident => {
@@ -94,51 +400,167 @@ usart_pins! {
     }
 }
 
+macro_rules! usart_flow_control_pins {
+    ($($USART:ident => {
+        rts => [$($rts:ty),+ $(,)*],
+        cts => [$($cts:ty),+ $(,)*],
+    })+) => {
+        $(
+            $(
+                impl RtsPin<crate::pac::$USART> for $rts {}
+            )+
+            $(
+                impl CtsPin<crate::pac::$USART> for $cts {}
+            )+
+        )+
+    }
+}
+
+usart_flow_control_pins! {
+    USART1 => {
+        rts => [
+            gpioa::PA1<Alternate<AF1>>,
+            gpioa::PA12<Alternate<AF1>>,
+        ],
+        cts => [
+            gpioa::PA0<Alternate<AF1>>,
+            gpioa::PA11<Alternate<AF1>>,
+        ],
+    }
+    USART2 => {
+        rts => [
+            gpioa::PA1<Alternate<AF4>>,
+        ],
+        cts => [
+            gpioa::PA0<Alternate<AF4>>,
+        ],
+    }
+}
+
 /// Serial abstraction
-pub struct Serial<USART, TXPIN, RXPIN> {
+///
+/// `FLOW` holds the RTS and CTS pins once hardware flow control is enabled with
+/// `with_flow_control`, `()` otherwise.
+pub struct Serial<USART, TXPIN, RXPIN, FLOW = ()> {
     usart: USART,
     pins: (TXPIN, RXPIN),
+    flow_control: FLOW,
 }
 
 // Common register
 type SerialRegisterBlock = crate::pac::usart1::RegisterBlock;
 
+/// USART peripheral, maps the instance to its clock for [`Serial::release`]
+pub trait Instance: Deref<Target = SerialRegisterBlock> {
+    #[doc(hidden)]
+    const PERIPHERAL: Peripheral;
+}
+
 /// Serial receiver
-pub struct Rx<USART> {
+///
+/// `PIN` is the RX pin when created by [`Serial::split_with_pins`], `()` otherwise.
+pub struct Rx<USART, PIN = ()> {
     usart: *const SerialRegisterBlock,
+    pin: PIN,
     _instance: PhantomData<USART>,
 }
 
 // NOTE(unsafe) Required to allow protected shared access in handlers
-unsafe impl<USART> Send for Rx<USART> {}
+unsafe impl<USART, PIN: Send> Send for Rx<USART, PIN> {}
 
 /// Serial transmitter
-pub struct Tx<USART> {
+///
+/// Holds the USART peripheral, so that [`Serial::join`] can hand it back. `PIN` is the TX pin
+/// when created by [`Serial::split_with_pins`], `()` otherwise.
+pub struct Tx<USART, PIN = ()> {
     usart: *const SerialRegisterBlock,
-    _instance: PhantomData<USART>,
+    pin: PIN,
+    instance: USART,
 }
 
 // NOTE(unsafe) Required to allow protected shared access in handlers
-unsafe impl<USART> Send for Tx<USART> {}
+unsafe impl<USART, PIN: Send> Send for Tx<USART, PIN> {}
+
+/// Serial receiver borrowed from a [`Serial`], see [`Serial::split_ref`]
+pub struct RxRef<'a, USART> {
+    usart: &'a SerialRegisterBlock,
+    _instance: PhantomData<&'a mut USART>,
+}
+
+/// Serial transmitter borrowed from a [`Serial`], see [`Serial::split_ref`]
+pub struct TxRef<'a, USART> {
+    usart: &'a SerialRegisterBlock,
+    _instance: PhantomData<&'a mut USART>,
+}
+
+/// Single-wire half-duplex serial, created by e.g. `HalfDuplex::usart1`
+///
+/// TX and RX share the TX pin. The receiver is switched off while transmitting, so the own
+/// frames are not read back, and switched on again once the last written frame is complete
+/// (`TC`), by `flush` or by the next `read`. Bytes the other side sends before that are lost,
+/// so protocols have to leave a turnaround gap. When receiving by interrupt, `flush` after
+/// writing so that reception resumes.
+pub struct HalfDuplex<USART, PIN> {
+    usart: USART,
+    pin: PIN,
+}
 
 macro_rules! usart {
-    ($($USART:ident: ($usart:ident, $usarttx:ident, $usartrx:ident, $usartXen:ident, $apbenr:ident),)+) => {
+    ($($USART:ident: ($usart:ident, $usarttx:ident, $usartrx:ident, $PER:ident),)+) => {
         $(
             use crate::pac::$USART;
+
+            impl Instance for $USART {
+                const PERIPHERAL: Peripheral = Peripheral::$PER;
+            }
+
             impl<TXPIN, RXPIN> Serial<$USART, TXPIN, RXPIN>
             where
                 TXPIN: TxPin<$USART>,
                 RXPIN: RxPin<$USART>,
             {
                 /// Creates a new serial instance
-                pub fn $usart(usart: $USART, pins: (TXPIN, RXPIN), baud_rate: Bps, rcc: &mut Rcc) -> Self
+                pub fn $usart<C>(usart: $USART, pins: (TXPIN, RXPIN), config: C, rcc: &mut Rcc) -> Self
+                where
+                    C: Into<SerialConfig>,
                 {
-                    let mut serial = Serial { usart, pins };
-                    serial.configure(baud_rate, rcc);
+                    let mut serial = Serial {
+                        usart,
+                        pins,
+                        flow_control: (),
+                    };
+                    serial.configure(config.into(), rcc);
                     // Enable transmission and receiving
                     serial.usart.cr1.modify(|_, w| w.te().set_bit().re().set_bit().ue().set_bit());
                     serial
                 }
+
+                /// Enables RTS/CTS hardware flow control
+                ///
+                /// The receiver deasserts RTS while a received byte is waiting in the data
+                /// register, and the transmitter holds back the next frame while CTS is high.
+                /// The returned serial keeps the flow control pins, its `release` returns them
+                /// next to TX and RX.
+                ///
+                /// Chain it to the constructor, e.g.
+                /// `Serial::usart1(..).with_flow_control((rts, cts))`.
+                pub fn with_flow_control<RTSPIN, CTSPIN>(
+                    self,
+                    flow_control: (RTSPIN, CTSPIN),
+                ) -> Serial<$USART, TXPIN, RXPIN, (RTSPIN, CTSPIN)>
+                where
+                    RTSPIN: RtsPin<$USART>,
+                    CTSPIN: CtsPin<$USART>,
+                {
+                    self.usart.cr1.modify(|_, w| w.ue().clear_bit());
+                    self.usart.cr3.modify(|_, w| w.rtse().set_bit().ctse().set_bit());
+                    self.usart.cr1.modify(|_, w| w.ue().set_bit());
+                    Serial {
+                        usart: self.usart,
+                        pins: self.pins,
+                        flow_control,
+                    }
+                }
             }
 
             impl<TXPIN> Serial<$USART, TXPIN, ()>
@@ -146,15 +568,51 @@ macro_rules! usart {
                 TXPIN: TxPin<$USART>,
             {
                 /// Creates a new tx-only serial instance
-                pub fn $usarttx(usart: $USART, txpin: TXPIN, baud_rate: Bps, rcc: &mut Rcc) -> Self
+                pub fn $usarttx<C>(usart: $USART, txpin: TXPIN, config: C, rcc: &mut Rcc) -> Self
+                where
+                    C: Into<SerialConfig>,
                 {
                     let rxpin = ();
-                    let mut serial = Serial { usart, pins: (txpin, rxpin) };
-                    serial.configure(baud_rate, rcc);
+                    let mut serial = Serial {
+                        usart,
+                        pins: (txpin, rxpin),
+                        flow_control: (),
+                    };
+                    serial.configure(config.into(), rcc);
                     // Enable transmission
                     serial.usart.cr1.modify(|_, w| w.te().set_bit().ue().set_bit());
                     serial
                 }
+
+            }
+
+            impl<PIN> HalfDuplex<$USART, PIN>
+            where
+                PIN: TxPin<$USART>,
+            {
+                /// Creates a new single-wire half-duplex serial instance (`HDSEL`) on the TX pin
+                ///
+                /// The pin is driven only while transmitting and receives otherwise. Use it in
+                /// open-drain alternate mode with a pull-up when more than two devices share the
+                /// wire.
+                pub fn $usart<C>(usart: $USART, pin: PIN, config: C, rcc: &mut Rcc) -> Self
+                where
+                    C: Into<SerialConfig>,
+                {
+                    let mut serial = Serial {
+                        usart,
+                        pins: (pin, ()),
+                        flow_control: (),
+                    };
+                    serial.configure(config.into(), rcc);
+                    // HDSEL can only be written while the USART is disabled
+                    serial.usart.cr3.modify(|_, w| w.hdsel().set_bit());
+                    serial.usart.cr1.modify(|_, w| w.te().set_bit().re().set_bit().ue().set_bit());
+                    HalfDuplex {
+                        usart: serial.usart,
+                        pin: serial.pins.0,
+                    }
+                }
             }
 
             impl<RXPIN> Serial<$USART, (), RXPIN>
@@ -162,31 +620,37 @@ macro_rules! usart {
                 RXPIN: RxPin<$USART>,
             {
                 /// Creates a new rx-only serial instance
-                pub fn $usartrx(usart: $USART, rxpin: RXPIN, baud_rate: Bps, rcc: &mut Rcc) -> Self
+                pub fn $usartrx<C>(usart: $USART, rxpin: RXPIN, config: C, rcc: &mut Rcc) -> Self
+                where
+                    C: Into<SerialConfig>,
                 {
                     let txpin = ();
-                    let mut serial = Serial { usart, pins: (txpin, rxpin) };
-                    serial.configure(baud_rate, rcc);
+                    let mut serial = Serial {
+                        usart,
+                        pins: (txpin, rxpin),
+                        flow_control: (),
+                    };
+                    serial.configure(config.into(), rcc);
                     // Enable receiving
                     serial.usart.cr1.modify(|_, w| w.re().set_bit().ue().set_bit());
                     serial
                 }
             }
 
-            impl<TXPIN, RXPIN> Serial<$USART, TXPIN, RXPIN> {
-                fn configure(&mut self, baud_rate: Bps, rcc: &mut Rcc) {
+            impl<TXPIN, RXPIN, FLOW> Serial<$USART, TXPIN, RXPIN, FLOW> {
+                fn configure(&mut self, config: SerialConfig, rcc: &mut Rcc) {
+                    // `Rcc` only exists after `CFGR::freeze`, but a bogus clock tree must not
+                    // silently produce a wrong baud rate
+                    assert!(rcc.clocks.pclk().0 != 0);
+
                     // Enable clock for USART
-                    rcc.regs.$apbenr.modify(|_, w| w.$usartXen().set_bit());
+                    rcc.enable(Peripheral::$PER);
 
-                    // Calculate correct baudrate divisor on the fly
-                    // FIXME: correct rcc setup
-                    // let brr = rcc.clocks.pclk().0 / baud_rate.0;
-                    let brr = 8000000 / baud_rate.0;
+                    let pclk = rcc.clocks.peripheral_clock(Peripheral::$PER);
+                    let brr = brr(pclk, config.baud_rate, config.oversampling);
                     self.usart.brr.write(|w| unsafe { w.bits(brr) });
 
-                    // Reset other registers to disable advanced USART features
-                    self.usart.cr2.reset();
-                    self.usart.cr3.reset();
+                    frame_format(&self.usart, &config);
                 }
 
                 /// Starts listening for an interrupt event
@@ -219,24 +683,194 @@ macro_rules! usart {
                     }
                 }
 
+                /// Enables the internal loopback used for self-tests
+                ///
+                /// The USART has no dedicated loopback bit, so this selects single-wire half-duplex
+                /// mode (`HDSEL`), where TX and RX are connected internally: every byte written is
+                /// received back without any external wiring. The TX pin keeps driving the line
+                /// while loopback is enabled and the RX pin is ignored.
+                pub fn enable_loopback(&mut self) {
+                    self.usart.cr1.modify(|_, w| w.ue().clear_bit());
+                    self.usart.cr3.modify(|_, w| w.hdsel().set_bit());
+                    self.usart.cr1.modify(|_, w| w.ue().set_bit());
+                }
+
+                /// Disables the internal loopback and returns to full-duplex operation
+                pub fn disable_loopback(&mut self) {
+                    self.usart.cr1.modify(|_, w| w.ue().clear_bit());
+                    self.usart.cr3.modify(|_, w| w.hdsel().clear_bit());
+                    self.usart.cr1.modify(|_, w| w.ue().set_bit());
+                }
+
+                /// Enables the IrDA SIR encoder/decoder
+                ///
+                /// TX and RX then carry 3/16 bit period pulses for an IR transceiver, which has
+                /// to be connected to pins in the USART alternate function. `low_power` selects
+                /// the IrDA low-power mode (`IRLP`). The Air001 USART has no `GTPR` register, so
+                /// neither the low-power pulse prescaler nor a smartcard guard time can be set.
+                pub fn enable_irda(&mut self, low_power: bool) {
+                    self.usart.cr1.modify(|_, w| w.ue().clear_bit());
+                    self.usart
+                        .cr3
+                        .modify(|_, w| w.iren().set_bit().irlp().bit(low_power));
+                    self.usart.cr1.modify(|_, w| w.ue().set_bit());
+                }
+
+                /// Disables the IrDA SIR encoder/decoder
+                pub fn disable_irda(&mut self) {
+                    self.usart.cr1.modify(|_, w| w.ue().clear_bit());
+                    self.usart
+                        .cr3
+                        .modify(|_, w| w.iren().clear_bit().irlp().clear_bit());
+                    self.usart.cr1.modify(|_, w| w.ue().set_bit());
+                }
+
+                /// Enables or disables the transmitter (`TE`)
+                ///
+                /// Disabling the transmitter in the middle of a frame lets the frame finish,
+                /// afterwards TX is released to its idle level.
+                pub fn set_tx_enabled(&mut self, enabled: bool) {
+                    self.usart.cr1.modify(|_, w| w.te().bit(enabled));
+                }
+
+                /// Enables or disables the receiver (`RE`)
+                ///
+                /// A reception in progress is aborted when disabling, and any received but unread
+                /// data is discarded together with pending error flags, so nothing stale is read
+                /// once the receiver is enabled again.
+                pub fn set_rx_enabled(&mut self, enabled: bool) {
+                    self.usart.cr1.modify(|_, w| w.re().bit(enabled));
+                    if !enabled {
+                        // Reading SR followed by DR clears RXNE, ORE, NE, FE and PE
+                        let _ = self.usart.sr.read();
+                        let _ = self.usart.dr.read();
+                    }
+                }
+
+                /// Changes the baud rate of a running link
+                ///
+                /// Blocks until the last frame has been sent (`TC`), then disables the USART,
+                /// writes the new divider and re-enables it. The TX line stays idle (high) in
+                /// between, so the switch is glitch-free as long as the other side does not send
+                /// while it happens; a frame received during the switch is lost.
+                pub fn set_baud_rate<B>(&mut self, baud_rate: B, clocks: &Clocks)
+                where
+                    B: Into<Bps>,
+                {
+                    let baud_rate = baud_rate.into();
+                    if self.usart.cr1.read().te().bit_is_set() {
+                        while self.usart.sr.read().tc().bit_is_clear() {}
+                    }
+                    let pclk = clocks.peripheral_clock(Peripheral::$PER);
+                    let oversampling = if self.usart.cr3.read().over8().bit_is_set() {
+                        Oversampling::Over8
+                    } else {
+                        Oversampling::Over16
+                    };
+                    self.set_raw_brr(brr(pclk, baud_rate, oversampling));
+                }
+
+                /// Changes the baud rate using the clocks frozen in `rcc`, see
+                /// [`set_baud_rate`](Self::set_baud_rate)
+                pub fn reconfigure<B>(&mut self, baud_rate: B, rcc: &Rcc)
+                where
+                    B: Into<Bps>,
+                {
+                    self.set_baud_rate(baud_rate, &rcc.clocks);
+                }
+
+                /// Writes `brr` directly to the baud rate register
+                ///
+                /// This bypasses the baud rate calculation for nonstandard rates or devices that
+                /// need a specific divider. The caller is responsible for a valid value: the
+                /// mantissa is in bits 4..16 and the fraction in bits 0..4 (0..3 with 8x
+                /// oversampling), values below 16 are not allowed. The USART is disabled while
+                /// BRR is written, a frame in progress is cut off.
+                pub fn set_raw_brr(&mut self, brr: u32) {
+                    self.usart.cr1.modify(|_, w| w.ue().clear_bit());
+                    self.usart.brr.write(|w| unsafe { w.bits(brr & 0xFFFF) });
+                    self.usart.cr1.modify(|_, w| w.ue().set_bit());
+                }
+
+                /// Returns the events that are both flagged and listened to
+                ///
+                /// SR and CR1 are read once, so an interrupt handler can dispatch on every cause
+                /// from a consistent snapshot.
+                pub fn pending_events(&self) -> Events {
+                    let sr = self.usart.sr.read();
+                    let cr1 = self.usart.cr1.read();
+
+                    let mut events = Events::empty();
+                    events.set(Events::RXNE, sr.rxne().bit_is_set() && cr1.rxneie().bit_is_set());
+                    events.set(Events::TXE, sr.txe().bit_is_set() && cr1.txeie().bit_is_set());
+                    events.set(Events::IDLE, sr.idle().bit_is_set() && cr1.idleie().bit_is_set());
+                    events
+                }
+
+                /// Returns true if `event` is flagged and listened to
+                pub fn is_event_pending(&self, event: Event) -> bool {
+                    self.pending_events().contains(event.into())
+                }
+
+                /// Returns all status flags from a single read of SR
+                pub fn status(&self) -> Status {
+                    Status::from_bits_truncate(self.usart.sr.read().bits() as u16)
+                }
+
+                /// Clears `flags`
+                ///
+                /// RXNE, TC and CTS are cleared by writing SR. PE, FE, NE, ORE and IDLE are only
+                /// cleared by reading SR followed by DR, so clearing any of them drops a received
+                /// byte, as does clearing RXNE. TXE, ABRF and ABRE are not cleared here.
+                pub fn clear(&mut self, flags: Status) {
+                    let writable = Status::RXNE | Status::TC | Status::CTS;
+                    if flags.intersects(writable) {
+                        // rc_w0 bits, writing 1 leaves the others (and ABRRQ at 0) untouched
+                        let keep = writable.difference(flags).bits();
+                        self.usart.sr.write(|w| unsafe { w.bits(u32::from(keep)) });
+                    }
+                    let sequence = Status::PE | Status::FE | Status::NE | Status::ORE | Status::IDLE;
+                    if flags.intersects(sequence) {
+                        let _ = self.usart.sr.read();
+                        let _ = self.usart.dr.read();
+                    }
+                }
+
                 /// Returns true if the line idle status is set
                 pub fn is_idle(&self) -> bool {
-                    self.usart.sr.read().idle().bit_is_set()
+                    self.status().contains(Status::IDLE)
+                }
+
+                /// Clears the IDLE flag, so idle line detection can trigger again
+                ///
+                /// The flag is cleared by reading SR followed by DR. This also clears the error
+                /// flags and drops a received byte that has not been read yet, read pending data
+                /// first.
+                pub fn clear_idle(&mut self) {
+                    self.clear(Status::IDLE);
+                }
+
+                /// Clears the ORE flag after an overrun
+                ///
+                /// Uses the same SR-then-DR sequence as [`clear_idle`](Self::clear_idle), with
+                /// the same side effects.
+                pub fn clear_overrun(&mut self) {
+                    self.clear(Status::ORE);
                 }
 
                 /// Returns true if the tx register is empty
                 pub fn is_txe(&self) -> bool {
-                    self.usart.sr.read().txe().bit_is_set()
+                    self.status().contains(Status::TXE)
                 }
 
                 /// Returns true if the rx register is not empty (and can be read)
                 pub fn is_rx_not_empty(&self) -> bool {
-                    self.usart.sr.read().rxne().bit_is_set()
+                    self.status().contains(Status::RXNE)
                 }
 
                 /// Returns true if transmission is complete
                 pub fn is_tx_complete(&self) -> bool {
-                    self.usart.sr.read().tc().bit_is_set()
+                    self.status().contains(Status::TC)
                 }
             }
         )+
@@ -244,132 +878,937 @@ macro_rules! usart {
 }
 
 usart! {
-    USART1: (usart1, usart1tx, usart1rx, usart1en, apbenr2),
+    USART1: (usart1, usart1tx, usart1rx, Usart1),
 }
 
 usart! {
-    USART2: (usart2, usart2tx, usart2rx,usart2en, apbenr1),
+    USART2: (usart2, usart2tx, usart2rx, Usart2),
 }
 
-impl<USART> embedded_hal::serial::Read<u8> for Rx<USART>
-where
-    USART: Deref<Target = SerialRegisterBlock>,
-{
-    type Error = Error;
-
-    /// Tries to read a byte from the uart
-    fn read(&mut self) -> nb::Result<u8, Error> {
-        read(self.usart)
+impl<USART, PIN> Rx<USART, PIN> {
+    /// Makes the USART request a DMA transfer for every received byte (`DMAR`)
+    ///
+    /// The caller owns the DMA setup: configure a channel reading from [`Rx::dr_address`] and
+    /// map it to this USART's RX request in SYSCFG before enabling.
+    pub fn enable_dma_request(&mut self) {
+        // NOTE(unsafe) CR3 is shared with `Tx`, the read-modify-write is done in a critical
+        // section
+        cortex_m::interrupt::free(|_| unsafe {
+            (*self.usart).cr3.modify(|_, w| w.dmar().set_bit())
+        });
     }
-}
 
-impl<USART, TXPIN, RXPIN> embedded_hal::serial::Read<u8> for Serial<USART, TXPIN, RXPIN>
-where
-    USART: Deref<Target = SerialRegisterBlock>,
-    RXPIN: RxPin<USART>,
-{
-    type Error = Error;
+    /// Stops requesting DMA transfers on reception
+    pub fn disable_dma_request(&mut self) {
+        // NOTE(unsafe) see `enable_dma_request`
+        cortex_m::interrupt::free(|_| unsafe {
+            (*self.usart).cr3.modify(|_, w| w.dmar().clear_bit())
+        });
+    }
 
-    /// Tries to read a byte from the uart
-    fn read(&mut self) -> nb::Result<u8, Error> {
-        read(&*self.usart)
+    /// Returns the address of the data register, the DMA peripheral address
+    pub fn dr_address(&self) -> u32 {
+        // NOTE(unsafe) only the address is taken
+        unsafe { &(*self.usart).dr as *const _ as u32 }
     }
-}
 
-impl<USART> embedded_hal::serial::Write<u8> for Tx<USART>
-where
-    USART: Deref<Target = SerialRegisterBlock>,
-{
-    type Error = Infallible;
+    /// Clears the IDLE flag, see [`Serial::clear_idle`]
+    pub fn clear_idle(&mut self) {
+        clear_sequence(self.usart);
+    }
 
-    /// Ensures that none of the previously written words are still buffered
-    fn flush(&mut self) -> nb::Result<(), Self::Error> {
-        flush(self.usart)
+    /// Clears the ORE flag, see [`Serial::clear_overrun`]
+    pub fn clear_overrun(&mut self) {
+        clear_sequence(self.usart);
     }
+}
 
-    /// Tries to write a byte to the uart
-    /// Fails if the transmit buffer is full
-    fn write(&mut self, byte: u8) -> nb::Result<(), Self::Error> {
-        write(self.usart, byte)
+/// Reads SR followed by DR, which clears PE, FE, NE, ORE and IDLE
+fn clear_sequence(usart: *const SerialRegisterBlock) {
+    // NOTE(unsafe) the reads only affect the receive side owned by the caller
+    unsafe {
+        let _ = (*usart).sr.read();
+        let _ = (*usart).dr.read();
     }
 }
 
-impl<USART, TXPIN, RXPIN> embedded_hal::serial::Write<u8> for Serial<USART, TXPIN, RXPIN>
-where
-    USART: Deref<Target = SerialRegisterBlock>,
-    TXPIN: TxPin<USART>,
-{
-    type Error = Infallible;
+impl<USART, PIN> Tx<USART, PIN> {
+    /// Makes the USART request a DMA transfer whenever the data register is empty (`DMAT`)
+    ///
+    /// The caller owns the DMA setup: configure a channel writing to [`Tx::dr_address`] and map
+    /// it to this USART's TX request in SYSCFG before enabling.
+    pub fn enable_dma_request(&mut self) {
+        // NOTE(unsafe) CR3 is shared with `Rx`, the read-modify-write is done in a critical
+        // section
+        cortex_m::interrupt::free(|_| unsafe {
+            (*self.usart).cr3.modify(|_, w| w.dmat().set_bit())
+        });
+    }
 
-    /// Ensures that none of the previously written words are still buffered
-    fn flush(&mut self) -> nb::Result<(), Self::Error> {
-        flush(&*self.usart)
+    /// Stops requesting DMA transfers for transmission
+    pub fn disable_dma_request(&mut self) {
+        // NOTE(unsafe) see `enable_dma_request`
+        cortex_m::interrupt::free(|_| unsafe {
+            (*self.usart).cr3.modify(|_, w| w.dmat().clear_bit())
+        });
     }
 
-    /// Tries to write a byte to the uart
-    /// Fails if the transmit buffer is full
-    fn write(&mut self, byte: u8) -> nb::Result<(), Self::Error> {
-        write(&*self.usart, byte)
+    /// Returns the address of the data register, the DMA peripheral address
+    pub fn dr_address(&self) -> u32 {
+        // NOTE(unsafe) only the address is taken
+        unsafe { &(*self.usart).dr as *const _ as u32 }
     }
 }
 
-impl<USART, TXPIN, RXPIN> Serial<USART, TXPIN, RXPIN>
+/// DMA transmission started by [`Tx::write_all_dma`]
+pub struct TxDma<USART, PIN, CH> {
+    tx: Tx<USART, PIN>,
+    channel: CH,
+    buffer: &'static [u8],
+}
+
+/// Continuous DMA reception started by [`Rx::read_dma_circular`]
+///
+/// The DMA fills the buffer as a ring and wraps around at the end. Combined with the idle line
+/// interrupt ([`listen_idle`](Self::listen_idle)) this receives variable length packets: when
+/// the line goes idle after a packet, [`read`](Self::read) takes out everything received since
+/// the previous call. Nothing stops the DMA from overwriting bytes that were not read yet, so
+/// read at least once per buffer length of received data.
+pub struct RxDmaCircular<USART, PIN, CH> {
+    rx: Rx<USART, PIN>,
+    channel: CH,
+    buffer: &'static mut [u8],
+    read_pos: usize,
+}
+
+macro_rules! usart_dma {
+    ($($USART:ident: ($tx_request:ident, $rx_request:ident),)+) => {
+        $(
+            impl<PIN> Rx<$USART, PIN> {
+                /// Starts receiving into `buffer` with DMA on `channel`, wrapping around at its end
+                ///
+                /// `buffer` must not be empty or longer than 65535 bytes.
+                pub fn read_dma_circular<CH>(
+                    mut self,
+                    mut channel: CH,
+                    buffer: &'static mut [u8],
+                ) -> RxDmaCircular<$USART, PIN, CH>
+                where
+                    CH: dma::Channel,
+                {
+                    assert!(!buffer.is_empty() && buffer.len() <= usize::from(u16::MAX));
+
+                    channel.stop();
+                    channel.clear_flags();
+                    channel.set_request(dma::Request::$rx_request);
+                    channel.configure(
+                        self.dr_address(),
+                        buffer.as_mut_ptr() as u32,
+                        buffer.len() as u16,
+                        dma::Direction::PeripheralToMemory,
+                        true,
+                    );
+                    compiler_fence(Ordering::Release);
+                    channel.start();
+                    self.enable_dma_request();
+
+                    RxDmaCircular {
+                        rx: self,
+                        channel,
+                        buffer,
+                        read_pos: 0,
+                    }
+                }
+            }
+
+            impl<PIN> Tx<$USART, PIN> {
+                /// Sends `buffer` with DMA on `channel`, without further CPU involvement
+                ///
+                /// The channel is mapped to the TX request of this USART. The transmitter, the
+                /// channel and the buffer are handed back by [`TxDma::wait`]. `buffer` must not be
+                /// empty, as a zero length transfer never completes, or longer than 65535 bytes.
+                pub fn write_all_dma<CH>(
+                    mut self,
+                    mut channel: CH,
+                    buffer: &'static [u8],
+                ) -> TxDma<$USART, PIN, CH>
+                where
+                    CH: dma::Channel,
+                {
+                    assert!(!buffer.is_empty() && buffer.len() <= usize::from(u16::MAX));
+
+                    channel.stop();
+                    channel.clear_flags();
+                    channel.set_request(dma::Request::$tx_request);
+                    channel.configure(
+                        self.dr_address(),
+                        buffer.as_ptr() as u32,
+                        buffer.len() as u16,
+                        dma::Direction::MemoryToPeripheral,
+                        false,
+                    );
+                    // The buffer must be completely written before the DMA reads it
+                    compiler_fence(Ordering::Release);
+                    channel.start();
+                    self.enable_dma_request();
+
+                    TxDma {
+                        tx: self,
+                        channel,
+                        buffer,
+                    }
+                }
+            }
+        )+
+    };
+}
+
+usart_dma! {
+    USART1: (Usart1Tx, Usart1Rx),
+    USART2: (Usart2Tx, Usart2Rx),
+}
+
+impl<USART, PIN, CH> TxDma<USART, PIN, CH>
 where
-    USART: Deref<Target = SerialRegisterBlock>,
+    CH: dma::Channel,
 {
-    /// Splits the UART Peripheral in a Tx and an Rx part
-    /// This is required for sending/receiving
-    pub fn split(self) -> (Tx<USART>, Rx<USART>)
-    where
-        TXPIN: TxPin<USART>,
-        RXPIN: RxPin<USART>,
-    {
-        (
-            Tx {
-                usart: &*self.usart,
-                _instance: PhantomData,
-            },
-            Rx {
-                usart: &*self.usart,
-                _instance: PhantomData,
-            },
-        )
+    /// Returns true once the DMA has handed the last byte to the USART, or aborted on an error
+    pub fn is_done(&self) -> bool {
+        self.channel.is_complete() || self.channel.has_error()
     }
 
-    pub fn release(self) -> (USART, (TXPIN, RXPIN)) {
-        (self.usart, self.pins)
+    /// Blocks until the transfer is done and releases the transmitter, channel and buffer
+    ///
+    /// The last byte may still be shifting out, [`flush`](embedded_hal::serial::Write::flush)
+    /// the transmitter before disabling the USART.
+    pub fn wait(mut self) -> (Tx<USART, PIN>, CH, &'static [u8]) {
+        while !self.is_done() {}
+        compiler_fence(Ordering::Acquire);
+
+        self.channel.stop();
+        self.channel.clear_flags();
+        self.tx.disable_dma_request();
+        (self.tx, self.channel, self.buffer)
     }
 }
 
-impl<USART> Write for Tx<USART>
+impl<USART, PIN, CH> RxDmaCircular<USART, PIN, CH>
 where
-    Tx<USART>: embedded_hal::serial::Write<u8>,
+    CH: dma::Channel,
 {
-    fn write_str(&mut self, s: &str) -> Result {
-        s.as_bytes()
-            .iter()
-            .try_for_each(|c| nb::block!(self.write(*c)))
-            .map_err(|_| core::fmt::Error)
+    /// Enables the idle line interrupt, it still has to be unmasked in the NVIC
+    pub fn listen_idle(&mut self) {
+        // NOTE(unsafe) CR1 is shared with `Tx`, the read-modify-write is done in a critical
+        // section
+        cortex_m::interrupt::free(|_| unsafe {
+            (*self.rx.usart).cr1.modify(|_, w| w.idleie().set_bit())
+        });
+    }
+
+    /// Disables the idle line interrupt
+    pub fn unlisten_idle(&mut self) {
+        // NOTE(unsafe) see `listen_idle`
+        cortex_m::interrupt::free(|_| unsafe {
+            (*self.rx.usart).cr1.modify(|_, w| w.idleie().clear_bit())
+        });
+    }
+
+    /// Returns true if the line went idle after a reception
+    pub fn is_idle(&self) -> bool {
+        // NOTE(unsafe) atomic read with no side effects
+        unsafe { (*self.rx.usart).sr.read().idle().bit_is_set() }
+    }
+
+    /// Clears the IDLE flag, call this from the idle line interrupt
+    ///
+    /// The SR-then-DR sequence does not lose data here: while the line is idle the DMA has
+    /// already taken the last byte out of DR.
+    pub fn clear_idle(&mut self) {
+        clear_sequence(self.rx.usart);
+    }
+
+    /// Returns the number of received bytes not taken out yet
+    pub fn available(&self) -> usize {
+        let len = self.buffer.len();
+        (self.write_pos() + len - self.read_pos) % len
+    }
+
+    /// Takes as many received bytes as available and fit into `out`, returns their number
+    pub fn read(&mut self, out: &mut [u8]) -> usize {
+        let count = self.available().min(out.len());
+        compiler_fence(Ordering::Acquire);
+        for byte in out[..count].iter_mut() {
+            // NOTE(unsafe) the DMA writes the buffer concurrently, read it volatile
+            *byte = unsafe { self.buffer.as_ptr().add(self.read_pos).read_volatile() };
+            self.read_pos = (self.read_pos + 1) % self.buffer.len();
+        }
+        count
+    }
+
+    /// Stops the reception and releases the receiver, channel and buffer
+    pub fn stop(mut self) -> (Rx<USART, PIN>, CH, &'static mut [u8]) {
+        self.unlisten_idle();
+        self.rx.disable_dma_request();
+        self.channel.stop();
+        self.channel.clear_flags();
+        compiler_fence(Ordering::Acquire);
+        (self.rx, self.channel, self.buffer)
+    }
+
+    /// Position the DMA writes the next byte to
+    fn write_pos(&self) -> usize {
+        let len = self.buffer.len();
+        (len - usize::from(self.channel.remaining())) % len
     }
 }
 
-impl<USART, TXPIN, RXPIN> Write for Serial<USART, TXPIN, RXPIN>
+/// Interrupt-driven receiver with a ring buffer in caller-provided storage
+///
+/// [`on_interrupt`](Self::on_interrupt) moves received bytes from the data register into the
+/// buffer and is called from the USART interrupt, the application takes them out with
+/// [`read`](Self::read). Both run in different contexts, so share the receiver through a
+/// critical section, e.g. `shared::Shared` (`critical-section` feature) or a `Mutex<RefCell<_>>`.
+///
+/// The storage is a `&'static mut [u8]`, usually from `cortex_m::singleton!` or a `static mut`
+/// taken once at startup, so no allocator or container crate is needed. Size it for the longest
+/// burst that can arrive between two reads; when it is full, bytes are dropped according to the
+/// [`OverrunPolicy`] and counted in [`dropped`](Self::dropped).
+pub struct BufferedRx<USART, PIN = ()> {
+    rx: Rx<USART, PIN>,
+    buf: &'static mut [u8],
+    head: usize,
+    len: usize,
+    error: Option<Error>,
+    policy: OverrunPolicy,
+    dropped: u32,
+}
+
+/// Byte discarded by [`BufferedRx`] when its buffer is full
+#[derive(Clone, Copy, PartialEq, Eq, Debug, Default)]
+pub enum OverrunPolicy {
+    /// Discard the received byte and keep the buffered data, suits command-response protocols
+    /// where the start of a message matters
+    #[default]
+    DropNewest,
+    /// Discard the oldest buffered byte to make room, suits streams where only recent data
+    /// matters
+    DropOldest,
+}
+
+impl<USART, PIN> BufferedRx<USART, PIN>
 where
     USART: Deref<Target = SerialRegisterBlock>,
-    TXPIN: TxPin<USART>,
 {
-    fn write_str(&mut self, s: &str) -> Result {
-        s.as_bytes()
-            .iter()
-            .try_for_each(|c| nb::block!(self.write(*c)))
-            .map_err(|_| core::fmt::Error)
+    /// Wraps `rx`, `buf` must not be empty
+    ///
+    /// Uses [`OverrunPolicy::DropNewest`], see [`with_policy`](Self::with_policy).
+    pub fn new(rx: Rx<USART, PIN>, buf: &'static mut [u8]) -> Self {
+        Self::with_policy(rx, buf, OverrunPolicy::default())
     }
-}
 
-/// Ensures that none of the previously written words are still buffered
-fn flush(usart: *const SerialRegisterBlock) -> nb::Result<(), Infallible> {
-    // NOTE(unsafe) atomic read with no side effects
-    let isr = unsafe { (*usart).sr.read() };
+    /// Wraps `rx` and selects which byte is discarded when `buf` is full, `buf` must not be empty
+    pub fn with_policy(rx: Rx<USART, PIN>, buf: &'static mut [u8], policy: OverrunPolicy) -> Self {
+        assert!(!buf.is_empty());
+        BufferedRx {
+            rx,
+            buf,
+            head: 0,
+            len: 0,
+            error: None,
+            policy,
+            dropped: 0,
+        }
+    }
+
+    /// Changes the overrun policy, the buffered data is kept
+    pub fn set_policy(&mut self, policy: OverrunPolicy) {
+        self.policy = policy;
+    }
+
+    /// Enables the `RXNE` interrupt, it still has to be unmasked in the NVIC
+    pub fn listen(&mut self) {
+        // NOTE(unsafe) CR1 is shared with `Tx`, the read-modify-write is done in a critical
+        // section
+        cortex_m::interrupt::free(|_| unsafe {
+            (*self.rx.usart).cr1.modify(|_, w| w.rxneie().set_bit())
+        });
+    }
+
+    /// Moves the received bytes into the buffer, call this from the USART interrupt
+    ///
+    /// A receive error is kept for [`take_error`](Self::take_error), the affected byte is lost.
+    pub fn on_interrupt(&mut self) {
+        loop {
+            match read(self.rx.usart) {
+                Ok(byte) => self.push(byte),
+                Err(nb::Error::Other(e)) => self.error = Some(e),
+                Err(nb::Error::WouldBlock) => break,
+            }
+        }
+    }
+
+    fn push(&mut self, byte: u8) {
+        if self.len == self.buf.len() {
+            self.dropped = self.dropped.wrapping_add(1);
+            match self.policy {
+                OverrunPolicy::DropNewest => return,
+                OverrunPolicy::DropOldest => {
+                    self.head = (self.head + 1) % self.buf.len();
+                    self.len -= 1;
+                }
+            }
+        }
+        let tail = (self.head + self.len) % self.buf.len();
+        self.buf[tail] = byte;
+        self.len += 1;
+    }
+
+    /// Takes the oldest byte out of the buffer
+    pub fn read(&mut self) -> Option<u8> {
+        if self.len == 0 {
+            return None;
+        }
+        let byte = self.buf[self.head];
+        self.head = (self.head + 1) % self.buf.len();
+        self.len -= 1;
+        Some(byte)
+    }
+
+    /// Takes as many bytes as available and fit into `out`, returns their number
+    pub fn read_into(&mut self, out: &mut [u8]) -> usize {
+        let mut count = 0;
+        while count < out.len() {
+            match self.read() {
+                Some(byte) => out[count] = byte,
+                None => break,
+            }
+            count += 1;
+        }
+        count
+    }
+
+    /// Returns the number of buffered bytes
+    pub fn len(&self) -> usize {
+        self.len
+    }
+
+    /// Returns true if no byte is buffered
+    pub fn is_empty(&self) -> bool {
+        self.len == 0
+    }
+
+    /// Returns the size of the buffer
+    pub fn capacity(&self) -> usize {
+        self.buf.len()
+    }
+
+    /// Returns and clears the last receive error
+    pub fn take_error(&mut self) -> Option<Error> {
+        self.error.take()
+    }
+
+    /// Returns the number of bytes discarded because the buffer was full, wrapping on overflow
+    pub fn dropped(&self) -> u32 {
+        self.dropped
+    }
+
+    /// Resets the dropped byte counter
+    pub fn clear_dropped(&mut self) {
+        self.dropped = 0;
+    }
+
+    /// Disables the `RXNE` interrupt and releases the receiver and the storage
+    pub fn release(self) -> (Rx<USART, PIN>, &'static mut [u8]) {
+        // NOTE(unsafe) see `listen`
+        cortex_m::interrupt::free(|_| unsafe {
+            (*self.rx.usart).cr1.modify(|_, w| w.rxneie().clear_bit())
+        });
+        (self.rx, self.buf)
+    }
+}
+
+/// Error of [`Tx::write_fmt_nb`] and [`Tx::try_write_str`]
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub enum WriteError {
+    /// The transmitter did not accept a byte in time, e.g. a stalled line. `written` bytes were
+    /// queued before.
+    Timeout { written: usize },
+    /// A formatting trait implementation returned an error
+    Format,
+}
+
+/// `fmt::Write` adapter that keeps the cause of a failed write
+struct BoundedWriter {
+    usart: *const SerialRegisterBlock,
+    max_polls: u32,
+    written: usize,
+    error: Option<WriteError>,
+}
+
+impl Write for BoundedWriter {
+    fn write_str(&mut self, s: &str) -> Result {
+        for byte in s.bytes() {
+            let mut polls = self.max_polls;
+            while write(self.usart, byte).is_err() {
+                if polls == 0 {
+                    self.error = Some(WriteError::Timeout {
+                        written: self.written,
+                    });
+                    return Err(core::fmt::Error);
+                }
+                polls -= 1;
+            }
+            self.written += 1;
+        }
+        Ok(())
+    }
+}
+
+impl<USART, PIN> Tx<USART, PIN> {
+    /// Writes `s`, waiting at most `max_polls` status checks for each byte
+    ///
+    /// Unlike the `fmt::Write` implementation, which blocks forever, this gives up on a line
+    /// that stopped accepting data and reports how much was queued.
+    pub fn try_write_str(
+        &mut self,
+        s: &str,
+        max_polls: u32,
+    ) -> core::result::Result<(), WriteError> {
+        self.write_fmt_nb(format_args!("{}", s), max_polls)
+    }
+
+    /// Writes formatted output, waiting at most `max_polls` status checks for each byte
+    ///
+    /// `write!` through `fmt::Write` only reports a bare `fmt::Error`; this returns whether
+    /// formatting failed or the transmitter timed out, so a logger can decide to drop or retry.
+    pub fn write_fmt_nb(
+        &mut self,
+        args: core::fmt::Arguments,
+        max_polls: u32,
+    ) -> core::result::Result<(), WriteError> {
+        let mut writer = BoundedWriter {
+            usart: self.usart,
+            max_polls,
+            written: 0,
+            error: None,
+        };
+        match writer.write_fmt(args) {
+            Ok(()) => Ok(()),
+            Err(_) => Err(writer.error.unwrap_or(WriteError::Format)),
+        }
+    }
+}
+
+impl<USART, PIN> embedded_hal::serial::Read<u8> for Rx<USART, PIN>
+where
+    USART: Deref<Target = SerialRegisterBlock>,
+{
+    type Error = Error;
+
+    /// Tries to read a byte from the uart
+    fn read(&mut self) -> nb::Result<u8, Error> {
+        read(self.usart)
+    }
+}
+
+impl<USART, TXPIN, RXPIN, FLOW> embedded_hal::serial::Read<u8> for Serial<USART, TXPIN, RXPIN, FLOW>
+where
+    USART: Deref<Target = SerialRegisterBlock>,
+    RXPIN: RxPin<USART>,
+{
+    type Error = Error;
+
+    /// Tries to read a byte from the uart
+    fn read(&mut self) -> nb::Result<u8, Error> {
+        read(&*self.usart)
+    }
+}
+
+impl<USART, PIN> embedded_hal::serial::Write<u8> for Tx<USART, PIN>
+where
+    USART: Deref<Target = SerialRegisterBlock>,
+{
+    type Error = Infallible;
+
+    /// Ensures that none of the previously written words are still buffered
+    fn flush(&mut self) -> nb::Result<(), Self::Error> {
+        flush(self.usart)
+    }
+
+    /// Tries to write a byte to the uart
+    /// Fails if the transmit buffer is full
+    fn write(&mut self, byte: u8) -> nb::Result<(), Self::Error> {
+        write(self.usart, byte)
+    }
+}
+
+impl<USART, TXPIN, RXPIN, FLOW> embedded_hal::serial::Write<u8>
+    for Serial<USART, TXPIN, RXPIN, FLOW>
+where
+    USART: Deref<Target = SerialRegisterBlock>,
+    TXPIN: TxPin<USART>,
+{
+    type Error = Infallible;
+
+    /// Ensures that none of the previously written words are still buffered
+    fn flush(&mut self) -> nb::Result<(), Self::Error> {
+        flush(&*self.usart)
+    }
+
+    /// Tries to write a byte to the uart
+    /// Fails if the transmit buffer is full
+    fn write(&mut self, byte: u8) -> nb::Result<(), Self::Error> {
+        write(&*self.usart, byte)
+    }
+}
+
+impl<USART, PIN> Rx<USART, PIN> {
+    /// Tries to read a 9 bit word, for [`WordLength::Bits9`]
+    pub fn read_u16(&mut self) -> nb::Result<u16, Error> {
+        read_word(self.usart)
+    }
+}
+
+impl<USART, PIN> Tx<USART, PIN> {
+    /// Tries to write a 9 bit word, for [`WordLength::Bits9`]
+    /// Fails if the transmit buffer is full
+    pub fn write_u16(&mut self, word: u16) -> nb::Result<(), Infallible> {
+        write_word(self.usart, word)
+    }
+}
+
+impl<USART, TXPIN, RXPIN, FLOW> Serial<USART, TXPIN, RXPIN, FLOW>
+where
+    USART: Deref<Target = SerialRegisterBlock>,
+{
+    /// Tries to read a 9 bit word, for [`WordLength::Bits9`]
+    pub fn read_u16(&mut self) -> nb::Result<u16, Error>
+    where
+        RXPIN: RxPin<USART>,
+    {
+        read_word(&*self.usart)
+    }
+
+    /// Tries to write a 9 bit word, for [`WordLength::Bits9`]
+    /// Fails if the transmit buffer is full
+    pub fn write_u16(&mut self, word: u16) -> nb::Result<(), Infallible>
+    where
+        TXPIN: TxPin<USART>,
+    {
+        write_word(&*self.usart, word)
+    }
+
+    /// Borrows the UART Peripheral as a Tx and an Rx part
+    ///
+    /// Unlike [`split`](Self::split), the halves only live as long as the borrow, after which the
+    /// `Serial` (and its pins) can be used again. Use `split` for halves that have to be moved
+    /// into interrupt handlers.
+    pub fn split_ref(&mut self) -> (TxRef<'_, USART>, RxRef<'_, USART>)
+    where
+        TXPIN: TxPin<USART>,
+        RXPIN: RxPin<USART>,
+    {
+        let usart: &SerialRegisterBlock = &self.usart;
+        (
+            TxRef {
+                usart,
+                _instance: PhantomData,
+            },
+            RxRef {
+                usart,
+                _instance: PhantomData,
+            },
+        )
+    }
+}
+
+impl<USART, TXPIN, RXPIN> Serial<USART, TXPIN, RXPIN>
+where
+    USART: Deref<Target = SerialRegisterBlock>,
+{
+    /// Splits the UART Peripheral in a Tx and an Rx part
+    /// This is required for sending/receiving
+    ///
+    /// The pins are dropped, use [`split_with_pins`](Self::split_with_pins) to get them back
+    /// with [`join`](Self::join).
+    pub fn split(self) -> (Tx<USART>, Rx<USART>)
+    where
+        TXPIN: TxPin<USART>,
+        RXPIN: RxPin<USART>,
+    {
+        let (tx, rx) = self.split_with_pins();
+        (
+            Tx {
+                usart: tx.usart,
+                pin: (),
+                instance: tx.instance,
+            },
+            Rx {
+                usart: rx.usart,
+                pin: (),
+                _instance: PhantomData,
+            },
+        )
+    }
+
+    /// Splits the UART Peripheral in a Tx and an Rx part that keep their pins
+    pub fn split_with_pins(self) -> (Tx<USART, TXPIN>, Rx<USART, RXPIN>)
+    where
+        TXPIN: TxPin<USART>,
+        RXPIN: RxPin<USART>,
+    {
+        let usart: *const SerialRegisterBlock = &*self.usart;
+        let (tx_pin, rx_pin) = self.pins;
+        (
+            Tx {
+                usart,
+                pin: tx_pin,
+                instance: self.usart,
+            },
+            Rx {
+                usart,
+                pin: rx_pin,
+                _instance: PhantomData,
+            },
+        )
+    }
+
+    /// Reassembles a `Serial` from the halves returned by [`split`](Self::split) or
+    /// [`split_with_pins`](Self::split_with_pins)
+    ///
+    /// The configuration is left as is, [`release`](Self::release) returns the peripheral and
+    /// the pins (`()` after a plain `split`).
+    ///
+    /// # Panics
+    ///
+    /// Panics if the halves belong to different USART instances, which the `USART` type
+    /// parameter normally rules out already.
+    pub fn join(tx: Tx<USART, TXPIN>, rx: Rx<USART, RXPIN>) -> Self {
+        assert!(core::ptr::eq(tx.usart, rx.usart));
+        Serial {
+            usart: tx.instance,
+            pins: (tx.pin, rx.pin),
+            flow_control: (),
+        }
+    }
+
+    /// Disables the USART and releases the peripheral and pins
+    ///
+    /// Blocks until the last frame has left the shift register (`TC`), so data written right
+    /// before releasing is not cut off. Use [`Serial::release_immediately`] to skip the wait.
+    ///
+    /// The USART is reset and its clock gated, the pins are returned in analog mode.
+    pub fn release(self) -> (USART, (TXPIN::Analog, RXPIN::Analog))
+    where
+        USART: Instance,
+        TXPIN: Deinit,
+        RXPIN: Deinit,
+    {
+        if self.usart.cr1.read().te().bit_is_set() {
+            while self.usart.sr.read().tc().bit_is_clear() {}
+        }
+        self.release_immediately()
+    }
+
+    /// Disables the USART and releases the peripheral and pins without waiting
+    ///
+    /// A frame still being transmitted is truncated.
+    pub fn release_immediately(self) -> (USART, (TXPIN::Analog, RXPIN::Analog))
+    where
+        USART: Instance,
+        TXPIN: Deinit,
+        RXPIN: Deinit,
+    {
+        self.usart
+            .cr1
+            .modify(|_, w| w.ue().clear_bit().te().clear_bit().re().clear_bit());
+        crate::rcc::reset(USART::PERIPHERAL);
+        crate::rcc::disable(USART::PERIPHERAL);
+        let (txpin, rxpin) = self.pins;
+        let pins = cortex_m::interrupt::free(|cs| (txpin.deinit(cs), rxpin.deinit(cs)));
+        (self.usart, pins)
+    }
+}
+
+impl<USART, TXPIN, RXPIN, RTSPIN, CTSPIN> Serial<USART, TXPIN, RXPIN, (RTSPIN, CTSPIN)>
+where
+    USART: Deref<Target = SerialRegisterBlock>,
+{
+    /// Disables the USART and releases the peripheral, the pins and the flow control pins
+    ///
+    /// Like [`Serial::release`] it blocks until the last frame has been sent, and returns all
+    /// pins in analog mode.
+    #[allow(clippy::type_complexity)]
+    pub fn release(
+        self,
+    ) -> (
+        USART,
+        (TXPIN::Analog, RXPIN::Analog),
+        (RTSPIN::Analog, CTSPIN::Analog),
+    )
+    where
+        USART: Instance,
+        TXPIN: Deinit,
+        RXPIN: Deinit,
+        RTSPIN: Deinit,
+        CTSPIN: Deinit,
+    {
+        let (rts, cts) = self.flow_control;
+        let serial = Serial {
+            usart: self.usart,
+            pins: self.pins,
+            flow_control: (),
+        };
+        let (usart, pins) = serial.release();
+        let flow_control = cortex_m::interrupt::free(|cs| (rts.deinit(cs), cts.deinit(cs)));
+        (usart, pins, flow_control)
+    }
+}
+
+impl<USART, PIN> embedded_hal::serial::Read<u8> for HalfDuplex<USART, PIN>
+where
+    USART: Deref<Target = SerialRegisterBlock>,
+{
+    type Error = Error;
+
+    /// Tries to read a byte, returns `WouldBlock` while a frame is being transmitted
+    fn read(&mut self) -> nb::Result<u8, Error> {
+        if self.usart.sr.read().tc().bit_is_clear() {
+            return Err(nb::Error::WouldBlock);
+        }
+        if self.usart.cr1.read().re().bit_is_clear() {
+            self.usart.cr1.modify(|_, w| w.re().set_bit());
+        }
+        read(&*self.usart)
+    }
+}
+
+impl<USART, PIN> embedded_hal::serial::Write<u8> for HalfDuplex<USART, PIN>
+where
+    USART: Deref<Target = SerialRegisterBlock>,
+{
+    type Error = Infallible;
+
+    /// Ensures that none of the previously written words are still buffered, then switches the
+    /// line back to receive
+    fn flush(&mut self) -> nb::Result<(), Self::Error> {
+        flush(&*self.usart)?;
+        if self.usart.cr1.read().re().bit_is_clear() {
+            self.usart.cr1.modify(|_, w| w.re().set_bit());
+        }
+        Ok(())
+    }
+
+    /// Tries to write a byte, switching the line to transmit first
+    fn write(&mut self, byte: u8) -> nb::Result<(), Self::Error> {
+        if self.usart.cr1.read().re().bit_is_set() {
+            self.usart.cr1.modify(|_, w| w.re().clear_bit());
+        }
+        write(&*self.usart, byte)
+    }
+}
+
+impl<USART, PIN> Write for HalfDuplex<USART, PIN>
+where
+    USART: Deref<Target = SerialRegisterBlock>,
+{
+    fn write_str(&mut self, s: &str) -> Result {
+        use embedded_hal::serial::Write;
+
+        s.as_bytes()
+            .iter()
+            .try_for_each(|c| nb::block!(Write::write(self, *c)))
+            .and_then(|()| nb::block!(Write::flush(self)))
+            .map_err(|_| core::fmt::Error)
+    }
+}
+
+impl<USART, PIN> HalfDuplex<USART, PIN>
+where
+    USART: Deref<Target = SerialRegisterBlock>,
+{
+    /// Disables the USART and releases the peripheral and pin
+    ///
+    /// Blocks until the last frame has been sent, then resets the USART and gates its clock like
+    /// [`Serial::release`]. The pin is returned in analog mode.
+    pub fn release(self) -> (USART, PIN::Analog)
+    where
+        USART: Instance,
+        PIN: Deinit,
+    {
+        while self.usart.sr.read().tc().bit_is_clear() {}
+        self.usart
+            .cr1
+            .modify(|_, w| w.ue().clear_bit().te().clear_bit().re().clear_bit());
+        crate::rcc::reset(USART::PERIPHERAL);
+        crate::rcc::disable(USART::PERIPHERAL);
+        let pin = cortex_m::interrupt::free(|cs| self.pin.deinit(cs));
+        (self.usart, pin)
+    }
+}
+
+impl<USART> embedded_hal::serial::Read<u8> for RxRef<'_, USART> {
+    type Error = Error;
+
+    /// Tries to read a byte from the uart
+    fn read(&mut self) -> nb::Result<u8, Error> {
+        read(self.usart)
+    }
+}
+
+impl<USART> embedded_hal::serial::Write<u8> for TxRef<'_, USART> {
+    type Error = Infallible;
+
+    /// Ensures that none of the previously written words are still buffered
+    fn flush(&mut self) -> nb::Result<(), Self::Error> {
+        flush(self.usart)
+    }
+
+    /// Tries to write a byte to the uart
+    /// Fails if the transmit buffer is full
+    fn write(&mut self, byte: u8) -> nb::Result<(), Self::Error> {
+        write(self.usart, byte)
+    }
+}
+
+impl<USART> Write for TxRef<'_, USART> {
+    fn write_str(&mut self, s: &str) -> Result {
+        s.as_bytes()
+            .iter()
+            .try_for_each(|c| nb::block!(self.write(*c)))
+            .map_err(|_| core::fmt::Error)
+    }
+}
+
+impl<USART, PIN> Write for Tx<USART, PIN>
+where
+    Tx<USART, PIN>: embedded_hal::serial::Write<u8>,
+{
+    fn write_str(&mut self, s: &str) -> Result {
+        s.as_bytes()
+            .iter()
+            .try_for_each(|c| nb::block!(self.write(*c)))
+            .map_err(|_| core::fmt::Error)
+    }
+}
+
+impl<USART, TXPIN, RXPIN, FLOW> Write for Serial<USART, TXPIN, RXPIN, FLOW>
+where
+    USART: Deref<Target = SerialRegisterBlock>,
+    TXPIN: TxPin<USART>,
+{
+    fn write_str(&mut self, s: &str) -> Result {
+        s.as_bytes()
+            .iter()
+            .try_for_each(|c| nb::block!(self.write(*c)))
+            .map_err(|_| core::fmt::Error)
+    }
+}
+
+/// Ensures that none of the previously written words are still buffered
+fn flush(usart: *const SerialRegisterBlock) -> nb::Result<(), Infallible> {
+    // NOTE(unsafe) atomic read with no side effects
+    let isr = unsafe { (*usart).sr.read() };
 
     if isr.tc().bit_is_set() {
         Ok(())
@@ -381,12 +1820,18 @@ fn flush(usart: *const SerialRegisterBlock) -> nb::Result<(), Infallible> {
 /// Tries to write a byte to the UART
 /// Returns `Err(WouldBlock)` if the transmit buffer is full
 fn write(usart: *const SerialRegisterBlock, byte: u8) -> nb::Result<(), Infallible> {
+    write_word(usart, u16::from(byte))
+}
+
+/// Tries to write a 9 bit word to the UART, bits above the frame length are ignored
+/// Returns `Err(WouldBlock)` if the transmit buffer is full
+fn write_word(usart: *const SerialRegisterBlock, word: u16) -> nb::Result<(), Infallible> {
     // NOTE(unsafe) atomic read with no side effects
     let isr = unsafe { (*usart).sr.read() };
 
     if isr.txe().bit_is_set() {
         // NOTE(unsafe) atomic write to stateless register
-        unsafe { (*usart).dr.write(|w| w.dr().bits(byte as u16)) }
+        unsafe { (*usart).dr.write(|w| w.dr().bits(word & 0x1FF)) }
         Ok(())
     } else {
         Err(nb::Error::WouldBlock)
@@ -394,24 +1839,739 @@ fn write(usart: *const SerialRegisterBlock, byte: u8) -> nb::Result<(), Infallib
 }
 
 /// Tries to read a byte from the UART
+///
+/// SR is checked before DR is touched, DR is only read when there is something to consume: the
+/// received byte, or the SR-then-DR sequence that clears a pending error. For PE, FE and NE the
+/// byte in DR is the corrupted one and is dropped with the error. ORE on its own leaves the last
+/// byte received before the overrun in DR, it is dropped as well since clearing ORE requires the
+/// DR read.
 fn read(usart: *const SerialRegisterBlock) -> nb::Result<u8, Error> {
+    read_word(usart).map(|word| word as u8)
+}
+
+/// Tries to read a 9 bit word from the UART, see [`read`]
+fn read_word(usart: *const SerialRegisterBlock) -> nb::Result<u16, Error> {
     // NOTE(unsafe) atomic read with no side effects
     let isr = unsafe { (*usart).sr.read() };
 
-    // NOTE(unsafe) read dr after sr clears pe,fe,ne,ore
-    let data = unsafe { (*usart).dr.read().dr().bits() as u8 };
-
-    if isr.pe().bit_is_set() {
-        Err(nb::Error::Other(Error::Parity))
+    let error = if isr.pe().bit_is_set() {
+        Some(Error::Parity)
     } else if isr.fe().bit_is_set() {
-        Err(nb::Error::Other(Error::Framing))
+        Some(Error::Framing)
     } else if isr.ne().bit_is_set() {
-        Err(nb::Error::Other(Error::Noise))
+        Some(Error::Noise)
     } else if isr.ore().bit_is_set() {
-        Err(nb::Error::Other(Error::Overrun))
+        Some(Error::Overrun)
+    } else {
+        None
+    };
+
+    if let Some(error) = error {
+        // NOTE(unsafe) read dr after sr clears pe,fe,ne,ore
+        unsafe { (*usart).dr.read() };
+        Err(nb::Error::Other(error))
     } else if isr.rxne().bit_is_set() {
-        Ok(data)
+        // NOTE(unsafe) atomic read, only consumes the byte flagged by rxne
+        Ok(unsafe { (*usart).dr.read().dr().bits() })
     } else {
         Err(nb::Error::WouldBlock)
     }
 }
+
+#[cfg(feature = "embedded-io")]
+mod io {
+    use core::convert::Infallible;
+    use core::ops::Deref;
+
+    use super::{Error, HalfDuplex, Rx, RxPin, Serial, SerialRegisterBlock, Tx, TxPin};
+
+    impl embedded_io::Error for Error {
+        fn kind(&self) -> embedded_io::ErrorKind {
+            match self {
+                Error::Framing | Error::Noise | Error::Parity => {
+                    embedded_io::ErrorKind::InvalidData
+                }
+                Error::Overrun => embedded_io::ErrorKind::Other,
+            }
+        }
+    }
+
+    /// Blocks until at least one byte is received, then takes whatever else is already available
+    fn read_buf(usart: *const SerialRegisterBlock, buf: &mut [u8]) -> Result<usize, Error> {
+        if buf.is_empty() {
+            return Ok(0);
+        }
+
+        buf[0] = nb::block!(super::read(usart))?;
+        let mut count = 1;
+        while count < buf.len() {
+            match super::read(usart) {
+                Ok(byte) => buf[count] = byte,
+                Err(nb::Error::WouldBlock) => break,
+                Err(nb::Error::Other(e)) => return Err(e),
+            }
+            count += 1;
+        }
+        Ok(count)
+    }
+
+    /// Blocks until the first byte is sent, then queues bytes as long as the data register is free
+    fn write_buf(usart: *const SerialRegisterBlock, buf: &[u8]) -> usize {
+        let mut bytes = buf.iter();
+        let Some(first) = bytes.next() else {
+            return 0;
+        };
+
+        nb::block!(super::write(usart, *first)).ok();
+        1 + bytes
+            .take_while(|byte| super::write(usart, **byte).is_ok())
+            .count()
+    }
+
+    fn read_ready(usart: *const SerialRegisterBlock) -> bool {
+        // NOTE(unsafe) atomic read with no side effects
+        unsafe { (*usart).sr.read().rxne().bit_is_set() }
+    }
+
+    fn write_ready(usart: *const SerialRegisterBlock) -> bool {
+        // NOTE(unsafe) atomic read with no side effects
+        unsafe { (*usart).sr.read().txe().bit_is_set() }
+    }
+
+    impl<USART, PIN> embedded_io::ErrorType for Rx<USART, PIN> {
+        type Error = Error;
+    }
+
+    impl<USART, PIN> embedded_io::Read for Rx<USART, PIN>
+    where
+        USART: Deref<Target = SerialRegisterBlock>,
+    {
+        fn read(&mut self, buf: &mut [u8]) -> Result<usize, Error> {
+            read_buf(self.usart, buf)
+        }
+    }
+
+    impl<USART, PIN> embedded_io::ReadReady for Rx<USART, PIN>
+    where
+        USART: Deref<Target = SerialRegisterBlock>,
+    {
+        fn read_ready(&mut self) -> Result<bool, Error> {
+            Ok(read_ready(self.usart))
+        }
+    }
+
+    impl<USART, PIN> embedded_io::ErrorType for Tx<USART, PIN> {
+        type Error = Infallible;
+    }
+
+    impl<USART, PIN> embedded_io::Write for Tx<USART, PIN>
+    where
+        USART: Deref<Target = SerialRegisterBlock>,
+    {
+        fn write(&mut self, buf: &[u8]) -> Result<usize, Infallible> {
+            Ok(write_buf(self.usart, buf))
+        }
+
+        fn flush(&mut self) -> Result<(), Infallible> {
+            nb::block!(super::flush(self.usart))
+        }
+    }
+
+    impl<USART, PIN> embedded_io::WriteReady for Tx<USART, PIN>
+    where
+        USART: Deref<Target = SerialRegisterBlock>,
+    {
+        fn write_ready(&mut self) -> Result<bool, Infallible> {
+            Ok(write_ready(self.usart))
+        }
+    }
+
+    impl<USART, TXPIN, RXPIN, FLOW> embedded_io::ErrorType for Serial<USART, TXPIN, RXPIN, FLOW> {
+        type Error = Error;
+    }
+
+    impl<USART, TXPIN, RXPIN, FLOW> embedded_io::Read for Serial<USART, TXPIN, RXPIN, FLOW>
+    where
+        USART: Deref<Target = SerialRegisterBlock>,
+        RXPIN: RxPin<USART>,
+    {
+        fn read(&mut self, buf: &mut [u8]) -> Result<usize, Error> {
+            read_buf(&*self.usart, buf)
+        }
+    }
+
+    impl<USART, TXPIN, RXPIN, FLOW> embedded_io::ReadReady for Serial<USART, TXPIN, RXPIN, FLOW>
+    where
+        USART: Deref<Target = SerialRegisterBlock>,
+        RXPIN: RxPin<USART>,
+    {
+        fn read_ready(&mut self) -> Result<bool, Error> {
+            Ok(read_ready(&*self.usart))
+        }
+    }
+
+    impl<USART, TXPIN, RXPIN, FLOW> embedded_io::Write for Serial<USART, TXPIN, RXPIN, FLOW>
+    where
+        USART: Deref<Target = SerialRegisterBlock>,
+        TXPIN: TxPin<USART>,
+    {
+        fn write(&mut self, buf: &[u8]) -> Result<usize, Error> {
+            Ok(write_buf(&*self.usart, buf))
+        }
+
+        fn flush(&mut self) -> Result<(), Error> {
+            nb::block!(super::flush(&*self.usart)).ok();
+            Ok(())
+        }
+    }
+
+    impl<USART, TXPIN, RXPIN, FLOW> embedded_io::WriteReady for Serial<USART, TXPIN, RXPIN, FLOW>
+    where
+        USART: Deref<Target = SerialRegisterBlock>,
+        TXPIN: TxPin<USART>,
+    {
+        fn write_ready(&mut self) -> Result<bool, Error> {
+            Ok(write_ready(&*self.usart))
+        }
+    }
+
+    impl<USART, PIN> embedded_io::ErrorType for HalfDuplex<USART, PIN> {
+        type Error = Error;
+    }
+
+    /// Goes through the `embedded_hal` implementation, which switches the line direction
+    impl<USART, PIN> embedded_io::Read for HalfDuplex<USART, PIN>
+    where
+        USART: Deref<Target = SerialRegisterBlock>,
+    {
+        fn read(&mut self, buf: &mut [u8]) -> Result<usize, Error> {
+            use embedded_hal::serial::Read;
+
+            if buf.is_empty() {
+                return Ok(0);
+            }
+
+            buf[0] = nb::block!(Read::read(self))?;
+            let mut count = 1;
+            while count < buf.len() {
+                match Read::read(self) {
+                    Ok(byte) => buf[count] = byte,
+                    Err(nb::Error::WouldBlock) => break,
+                    Err(nb::Error::Other(e)) => return Err(e),
+                }
+                count += 1;
+            }
+            Ok(count)
+        }
+    }
+
+    /// Goes through the `embedded_hal` implementation, which switches the line direction
+    impl<USART, PIN> embedded_io::Write for HalfDuplex<USART, PIN>
+    where
+        USART: Deref<Target = SerialRegisterBlock>,
+    {
+        fn write(&mut self, buf: &[u8]) -> Result<usize, Error> {
+            use embedded_hal::serial::Write;
+
+            let mut bytes = buf.iter();
+            let Some(first) = bytes.next() else {
+                return Ok(0);
+            };
+
+            nb::block!(Write::write(self, *first)).ok();
+            Ok(1 + bytes
+                .take_while(|byte| Write::write(self, **byte).is_ok())
+                .count())
+        }
+
+        fn flush(&mut self) -> Result<(), Error> {
+            use embedded_hal::serial::Write;
+
+            nb::block!(Write::flush(self)).ok();
+            Ok(())
+        }
+    }
+}
+
+#[cfg(feature = "async")]
+pub use asynch::{on_usart1_interrupt, on_usart2_interrupt, RX_BUFFER_SIZE};
+
+#[cfg(feature = "async")]
+mod asynch {
+    use core::cell::UnsafeCell;
+    use core::convert::Infallible;
+    use core::future::poll_fn;
+    use core::ops::Deref;
+    use core::ptr;
+    use core::sync::atomic::{AtomicU8, AtomicUsize, Ordering};
+    use core::task::Poll;
+
+    use super::{
+        Error, Rx, RxDmaCircular, RxPin, Serial, SerialRegisterBlock, Tx, TxPin, USART1, USART2,
+    };
+    use crate::dma;
+    use crate::waker::WakerCell;
+
+    /// Number of bytes each USART buffers for the async reads
+    pub const RX_BUFFER_SIZE: usize = 64;
+
+    static RX_WAKERS: [WakerCell; 2] = [WakerCell::new(), WakerCell::new()];
+    static TX_WAKERS: [WakerCell; 2] = [WakerCell::new(), WakerCell::new()];
+    static RX_BUFFERS: [RxBuffer; 2] = [RxBuffer::new(), RxBuffer::new()];
+
+    /// Single producer (interrupt handler), single consumer (reading future) ring buffer
+    ///
+    /// `head` and `tail` count the bytes taken out and put in, wrapping, so only loads and stores
+    /// are needed, which the Cortex-M0+ has for atomics.
+    struct RxBuffer {
+        buf: UnsafeCell<[u8; RX_BUFFER_SIZE]>,
+        head: AtomicUsize,
+        tail: AtomicUsize,
+        // receive error seen by the interrupt handler, see `error_code`
+        error: AtomicU8,
+    }
+
+    // NOTE(unsafe) each byte of `buf` is either owned by the producer or by the consumer,
+    // depending on `head` and `tail`
+    unsafe impl Sync for RxBuffer {}
+
+    impl RxBuffer {
+        const fn new() -> Self {
+            RxBuffer {
+                buf: UnsafeCell::new([0; RX_BUFFER_SIZE]),
+                head: AtomicUsize::new(0),
+                tail: AtomicUsize::new(0),
+                error: AtomicU8::new(0),
+            }
+        }
+
+        /// Appends `byte`, a full buffer drops it and reports an overrun
+        fn push(&self, byte: u8) {
+            let tail = self.tail.load(Ordering::Relaxed);
+            if tail.wrapping_sub(self.head.load(Ordering::Acquire)) == RX_BUFFER_SIZE {
+                self.set_error(Error::Overrun);
+                return;
+            }
+            // NOTE(unsafe) the slot at `tail` is not readable by the consumer yet
+            unsafe { (*self.buf.get())[tail % RX_BUFFER_SIZE] = byte };
+            self.tail.store(tail.wrapping_add(1), Ordering::Release);
+        }
+
+        /// Takes as many bytes as available and fit into `out`, returns their number
+        fn pop_into(&self, out: &mut [u8]) -> usize {
+            let mut head = self.head.load(Ordering::Relaxed);
+            let tail = self.tail.load(Ordering::Acquire);
+            let mut count = 0;
+            while head != tail && count < out.len() {
+                // NOTE(unsafe) the slot at `head` was released by the producer
+                out[count] = unsafe { (*self.buf.get())[head % RX_BUFFER_SIZE] };
+                head = head.wrapping_add(1);
+                count += 1;
+            }
+            self.head.store(head, Ordering::Release);
+            count
+        }
+
+        fn set_error(&self, error: Error) {
+            self.error.store(error_code(error), Ordering::Relaxed);
+        }
+
+        fn take_error(&self) -> Option<Error> {
+            // the handler can't run between the load and the store
+            let code = cortex_m::interrupt::free(|_| {
+                let code = self.error.load(Ordering::Relaxed);
+                self.error.store(0, Ordering::Relaxed);
+                code
+            });
+            match code {
+                1 => Some(Error::Framing),
+                2 => Some(Error::Noise),
+                3 => Some(Error::Overrun),
+                4 => Some(Error::Parity),
+                _ => None,
+            }
+        }
+    }
+
+    fn error_code(error: Error) -> u8 {
+        match error {
+            Error::Framing => 1,
+            Error::Noise => 2,
+            Error::Overrun => 3,
+            Error::Parity => 4,
+        }
+    }
+
+    macro_rules! on_interrupt {
+        ($($USART:ident: ($on_interrupt:ident, $index:expr),)+) => {
+            $(
+                /// Buffers received bytes and wakes the tasks waiting on the USART, call this
+                /// from its interrupt handler
+                pub fn $on_interrupt() {
+                    // NOTE(unsafe) the data register is only read by the handler while RXNEIE is
+                    // set, CR1 is shared with the futures and modified in a critical section
+                    let usart = unsafe { &*$USART::ptr() };
+
+                    // RXNEIE stays set, so bytes keep being buffered between two reads
+                    let mut rx = false;
+                    if usart.cr1.read().rxneie().bit_is_set() {
+                        loop {
+                            match super::read(usart) {
+                                Ok(byte) => RX_BUFFERS[$index].push(byte),
+                                Err(nb::Error::Other(e)) => RX_BUFFERS[$index].set_error(e),
+                                Err(nb::Error::WouldBlock) => break,
+                            }
+                            rx = true;
+                        }
+                    }
+
+                    let (idle, tx) = cortex_m::interrupt::free(|_| {
+                        let sr = usart.sr.read();
+                        let cr1 = usart.cr1.read();
+                        let idle = cr1.idleie().bit_is_set() && sr.idle().bit_is_set();
+                        let tx = (cr1.txeie().bit_is_set() && sr.txe().bit_is_set())
+                            || (cr1.tcie().bit_is_set() && sr.tc().bit_is_set());
+                        usart.cr1.modify(|_, w| {
+                            if idle {
+                                w.idleie().clear_bit();
+                            }
+                            if tx {
+                                w.txeie().clear_bit().tcie().clear_bit();
+                            }
+                            w
+                        });
+                        (idle, tx)
+                    });
+                    if rx || idle {
+                        RX_WAKERS[$index].wake();
+                    }
+                    if tx {
+                        TX_WAKERS[$index].wake();
+                    }
+                }
+            )+
+        };
+    }
+
+    on_interrupt! {
+        USART1: (on_usart1_interrupt, 0),
+        USART2: (on_usart2_interrupt, 1),
+    }
+
+    fn index(usart: *const SerialRegisterBlock) -> usize {
+        if ptr::eq(usart, USART1::ptr()) {
+            0
+        } else {
+            1
+        }
+    }
+
+    /// Waits until the interrupt handler buffered at least one byte, then takes all available
+    async fn read_buf(usart: *const SerialRegisterBlock, buf: &mut [u8]) -> Result<usize, Error> {
+        if buf.is_empty() {
+            return Ok(0);
+        }
+
+        let index = index(usart);
+        let rx = &RX_BUFFERS[index];
+        poll_fn(|cx| {
+            if let Some(e) = rx.take_error() {
+                return Poll::Ready(Err(e));
+            }
+            let count = rx.pop_into(buf);
+            if count > 0 {
+                return Poll::Ready(Ok(count));
+            }
+
+            RX_WAKERS[index].register(cx.waker());
+            // NOTE(unsafe) CR1 is shared with `Tx`, modified in a critical section. RXNE is
+            // level triggered, a byte received before the enable raises the interrupt right
+            // away.
+            cortex_m::interrupt::free(|_| unsafe {
+                (*usart).cr1.modify(|_, w| w.rxneie().set_bit())
+            });
+            // the handler may have run before the waker was registered
+            match rx.pop_into(buf) {
+                0 => Poll::Pending,
+                count => Poll::Ready(Ok(count)),
+            }
+        })
+        .await
+    }
+
+    /// Waits until the first byte is queued, then queues more as long as the data register is free
+    async fn write_buf(usart: *const SerialRegisterBlock, buf: &[u8]) -> usize {
+        let mut bytes = buf.iter();
+        let Some(first) = bytes.next() else {
+            return 0;
+        };
+
+        poll_fn(|cx| match super::write(usart, *first) {
+            Ok(()) => Poll::Ready(()),
+            Err(_) => {
+                TX_WAKERS[index(usart)].register(cx.waker());
+                // NOTE(unsafe) see `read_buf`
+                cortex_m::interrupt::free(|_| unsafe {
+                    (*usart).cr1.modify(|_, w| w.txeie().set_bit())
+                });
+                Poll::Pending
+            }
+        })
+        .await;
+
+        1 + bytes
+            .take_while(|byte| super::write(usart, **byte).is_ok())
+            .count()
+    }
+
+    /// Waits until the last frame left the shift register
+    async fn flush(usart: *const SerialRegisterBlock) {
+        poll_fn(|cx| match super::flush(usart) {
+            Ok(()) => Poll::Ready(()),
+            Err(_) => {
+                TX_WAKERS[index(usart)].register(cx.waker());
+                // NOTE(unsafe) see `read_buf`
+                cortex_m::interrupt::free(|_| unsafe {
+                    (*usart).cr1.modify(|_, w| w.tcie().set_bit())
+                });
+                Poll::Pending
+            }
+        })
+        .await
+    }
+
+    impl<USART, PIN, CH> embedded_io::ErrorType for RxDmaCircular<USART, PIN, CH> {
+        type Error = Infallible;
+    }
+
+    impl<USART, PIN, CH> embedded_io_async::Read for RxDmaCircular<USART, PIN, CH>
+    where
+        CH: dma::Channel,
+    {
+        /// Waits until the DMA received at least one byte, then takes all available
+        ///
+        /// The task is woken when the line goes idle after a burst and every time the DMA
+        /// fills half of the buffer, so a continuous stream is picked up before it wraps.
+        async fn read(&mut self, buf: &mut [u8]) -> Result<usize, Infallible> {
+            if buf.is_empty() {
+                return Ok(0);
+            }
+
+            let index = index(self.rx.usart);
+            poll_fn(|cx| {
+                let count = RxDmaCircular::read(self, buf);
+                if count > 0 {
+                    return Poll::Ready(Ok(count));
+                }
+
+                RX_WAKERS[index].register(cx.waker());
+                dma::WAKERS[CH::INDEX].register(cx.waker());
+                // a stale IDLE flag would fire the interrupt right away
+                if self.is_idle() {
+                    self.clear_idle();
+                }
+                self.listen_idle();
+                self.channel.listen();
+                // bytes received before the interrupts were enabled don't wake the task
+                match RxDmaCircular::read(self, buf) {
+                    0 => Poll::Pending,
+                    count => Poll::Ready(Ok(count)),
+                }
+            })
+            .await
+        }
+    }
+
+    impl<USART, PIN> embedded_io_async::Read for Rx<USART, PIN>
+    where
+        USART: Deref<Target = SerialRegisterBlock>,
+    {
+        async fn read(&mut self, buf: &mut [u8]) -> Result<usize, Error> {
+            read_buf(self.usart, buf).await
+        }
+    }
+
+    impl<USART, PIN> embedded_io_async::Write for Tx<USART, PIN>
+    where
+        USART: Deref<Target = SerialRegisterBlock>,
+    {
+        async fn write(&mut self, buf: &[u8]) -> Result<usize, core::convert::Infallible> {
+            Ok(write_buf(self.usart, buf).await)
+        }
+
+        async fn flush(&mut self) -> Result<(), core::convert::Infallible> {
+            flush(self.usart).await;
+            Ok(())
+        }
+    }
+
+    impl<USART, TXPIN, RXPIN, FLOW> embedded_io_async::Read for Serial<USART, TXPIN, RXPIN, FLOW>
+    where
+        USART: Deref<Target = SerialRegisterBlock>,
+        RXPIN: RxPin<USART>,
+    {
+        async fn read(&mut self, buf: &mut [u8]) -> Result<usize, Error> {
+            read_buf(&*self.usart, buf).await
+        }
+    }
+
+    impl<USART, TXPIN, RXPIN, FLOW> embedded_io_async::Write for Serial<USART, TXPIN, RXPIN, FLOW>
+    where
+        USART: Deref<Target = SerialRegisterBlock>,
+        TXPIN: TxPin<USART>,
+    {
+        async fn write(&mut self, buf: &[u8]) -> Result<usize, Error> {
+            Ok(write_buf(&*self.usart, buf).await)
+        }
+
+        async fn flush(&mut self) -> Result<(), Error> {
+            flush(&*self.usart).await;
+            Ok(())
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::time::U32Ext;
+
+    fn over16(clock: u32, baud: u32) -> u32 {
+        brr(clock.hz(), baud.bps(), Oversampling::Over16)
+    }
+
+    fn over8(clock: u32, baud: u32) -> u32 {
+        brr(clock.hz(), baud.bps(), Oversampling::Over8)
+    }
+
+    /// Checks the divider `closest_baud` settles on against `brr`, returns the ppm error
+    fn closest(clock: u32, desired: u32, div: u32) -> i32 {
+        let (actual, error) = closest_baud(clock.hz(), desired.bps());
+        assert_eq!(over16(clock, desired), div);
+        assert_eq!(actual.0, (clock + div / 2) / div);
+        error
+    }
+
+    #[test]
+    fn closest_baud_divider_and_error() {
+        assert_eq!(closest(8_000_000, 115_200, 69), 6_440);
+        assert_eq!(closest(24_000_000, 115_200, 208), 1_605);
+        assert_eq!(closest(48_000_000, 9_600, 5_000), 0);
+        assert_eq!(closest(48_000_000, 1_000_000, 48), 0);
+    }
+
+    #[test]
+    fn closest_baud_out_of_range() {
+        // the divider is clamped, the error shows how far off the baud rate is
+        assert_eq!(closest(8_000_000, 4_000_000, 16), -875_000);
+        assert_eq!(closest(48_000_000, 300, 0xFFFF), 1_440_000);
+        assert_eq!(closest(48_000_000, 0, 0xFFFF), 731_000_000);
+    }
+
+    /// Runs `read_word` against a register block in memory with the given SR flags
+    fn read_flags(sr: u32) -> nb::Result<u16, Error> {
+        // SAFETY: the register block is plain memory, all zero is its reset state
+        let usart: SerialRegisterBlock = unsafe { core::mem::zeroed() };
+        usart.sr.write(|w| unsafe { w.bits(sr) });
+        usart.dr.write(|w| unsafe { w.bits(0x1A5) });
+        read_word(&usart)
+    }
+
+    #[test]
+    fn read_word_checks_every_flag_combination() {
+        const PE: u32 = 1 << 0;
+        const FE: u32 = 1 << 1;
+        const NE: u32 = 1 << 2;
+        const ORE: u32 = 1 << 3;
+        const RXNE: u32 = 1 << 5;
+
+        for flags in 0..32 {
+            // spread the five flag bits over PE, FE, NE, ORE and RXNE
+            let sr = (flags & 0b1111) | ((flags & 0b1_0000) << 1);
+            let result = read_flags(sr);
+            // errors take precedence over data, in this order
+            let ok = if sr & PE != 0 {
+                matches!(result, Err(nb::Error::Other(Error::Parity)))
+            } else if sr & FE != 0 {
+                matches!(result, Err(nb::Error::Other(Error::Framing)))
+            } else if sr & NE != 0 {
+                matches!(result, Err(nb::Error::Other(Error::Noise)))
+            } else if sr & ORE != 0 {
+                matches!(result, Err(nb::Error::Other(Error::Overrun)))
+            } else if sr & RXNE != 0 {
+                matches!(result, Ok(0x1A5))
+            } else {
+                matches!(result, Err(nb::Error::WouldBlock))
+            };
+            assert!(ok, "SR {:#04x}: {:?}", sr, result);
+        }
+    }
+
+    /// Returns CR1 after writing the frame format of `config` to a register block in memory
+    fn cr1(config: SerialConfig) -> u32 {
+        // SAFETY: the register block is plain memory, all zero is its reset state
+        let usart: SerialRegisterBlock = unsafe { core::mem::zeroed() };
+        frame_format(&usart, &config);
+        usart.cr1.read().bits()
+    }
+
+    #[test]
+    fn frame_format_sets_parity_and_word_length() {
+        const PS: u32 = 1 << 9;
+        const PCE: u32 = 1 << 10;
+        const M: u32 = 1 << 12;
+
+        let config = SerialConfig::default();
+        assert_eq!(cr1(config), 0);
+        assert_eq!(cr1(config.parity(Parity::Even)), PCE);
+        assert_eq!(cr1(config.parity(Parity::Odd)), PCE | PS);
+
+        let config = config.word_length(WordLength::Bits9);
+        assert_eq!(cr1(config), M);
+        assert_eq!(cr1(config.parity(Parity::Even)), M | PCE);
+        assert_eq!(cr1(config.parity(Parity::Odd)), M | PCE | PS);
+    }
+
+    #[test]
+    fn brr_rounds_to_nearest() {
+        assert_eq!(over16(8_000_000, 230_400), 35);
+        assert_eq!(over16(8_000_000, 115_200), 69);
+        assert_eq!(over16(8_000_000, 9_600), 833);
+        assert_eq!(over16(24_000_000, 115_200), 208);
+        assert_eq!(over16(24_000_000, 1_000_000), 24);
+        assert_eq!(over16(48_000_000, 9_600), 5_000);
+        assert_eq!(over16(48_000_000, 921_600), 52);
+    }
+
+    #[test]
+    fn brr_over8_keeps_bit3_clear() {
+        // 34.72 -> 35 = 4 + 3/8
+        assert_eq!(over8(8_000_000, 230_400), 0x43);
+        // 52.08 -> 52 = 6 + 4/8
+        assert_eq!(over8(48_000_000, 921_600), 0x64);
+        // 8 = 1 + 0/8, the smallest divider
+        assert_eq!(over8(8_000_000, 1_000_000), 0x10);
+        assert_eq!(over8(24_000_000, 3_000_000), 0x10);
+    }
+
+    #[test]
+    fn brr_clamps() {
+        assert_eq!(over16(8_000_000, 4_000_000), 0x10);
+        assert_eq!(over8(8_000_000, 4_000_000), 0x10);
+        assert_eq!(over16(48_000_000, 300), 0xFFFF);
+        assert_eq!(over8(48_000_000, 300), 0xFFF7);
+        assert_eq!(over16(48_000_000, 0), 0xFFFF);
+        assert_eq!(over16(u32::MAX, 1), 0xFFFF);
+        assert_eq!(over16(0, 9_600), 0x10);
+    }
+
+    #[test]
+    fn brr_zero_and_max_inputs() {
+        assert_eq!(over8(48_000_000, 0), 0xFFF7);
+        assert_eq!(over8(u32::MAX, 1), 0xFFF7);
+        assert_eq!(over8(0, 9_600), 0x10);
+        assert_eq!(over16(48_000_000, u32::MAX), 0x10);
+        assert_eq!(over8(48_000_000, u32::MAX), 0x10);
+        assert_eq!(over16(u32::MAX, u32::MAX), 0x10);
+    }
+}
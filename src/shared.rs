@@ -0,0 +1,63 @@
+//! # Sharing drivers with interrupt handlers
+//!
+//! A driver that is used both from `main` and from an interrupt handler has to be moved into a
+//! `static` and every access has to exclude the other context. [`Shared`] bundles the usual
+//! `Mutex<RefCell<Option<T>>>` for this:
+//!
+//! ```ignore
+//! static SERIAL: Shared<Serial<USART1, TxPin, RxPin>> = Shared::new();
+//!
+//! // in main, after configuring the peripheral
+//! SERIAL.init(serial);
+//!
+//! // in main or in an interrupt handler
+//! SERIAL.with(|serial| serial.write(b'x'));
+//! ```
+//!
+//! Accesses run inside a critical section, so keep the closures short. The `cortex-m` crate has
+//! to provide the critical section implementation (feature `critical-section-single-core`).
+
+use core::cell::RefCell;
+
+use critical_section::Mutex;
+
+/// A driver shared between thread mode and interrupt handlers
+pub struct Shared<T> {
+    inner: Mutex<RefCell<Option<T>>>,
+}
+
+impl<T> Shared<T> {
+    /// Creates an empty slot, usable in a `static`
+    pub const fn new() -> Self {
+        Shared {
+            inner: Mutex::new(RefCell::new(None)),
+        }
+    }
+
+    /// Moves `value` into the slot, returning the previous value if there was one
+    pub fn init(&self, value: T) -> Option<T> {
+        critical_section::with(|cs| self.inner.borrow(cs).replace(Some(value)))
+    }
+
+    /// Takes the value out of the slot, leaving it empty
+    pub fn take(&self) -> Option<T> {
+        critical_section::with(|cs| self.inner.borrow(cs).take())
+    }
+
+    /// Runs `f` on the value with interrupts disabled
+    ///
+    /// Returns `None` without calling `f` if the slot is empty.
+    ///
+    /// # Panics
+    ///
+    /// Panics if called from within `f` on the same slot.
+    pub fn with<R>(&self, f: impl FnOnce(&mut T) -> R) -> Option<R> {
+        critical_section::with(|cs| self.inner.borrow(cs).borrow_mut().as_mut().map(f))
+    }
+}
+
+impl<T> Default for Shared<T> {
+    fn default() -> Self {
+        Self::new()
+    }
+}
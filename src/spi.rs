@@ -1,3 +1,51 @@
+//! # Serial Peripheral Interface
+//!
+//! Only the Motorola frame format is supported. Unlike some STM32 parts, the Air001 SPI has no
+//! `FRF` bit in `CR2`, so the TI frame format (where NSS pulses automatically between frames and
+//! CPOL/CPHA are fixed by the protocol) is not available. Devices using the TI protocol have to
+//! be driven in Motorola mode with NSS toggled in software.
+//!
+//! There is no `NSSP` bit either, so the hardware cannot pulse NSS between consecutive frames.
+//! NSS is managed in software (`SSM`) by this driver; toggle the chip select GPIO between frames
+//! if the device requires it.
+//!
+//! Both directions are buffered by a 32-bit FIFO. `RXNE` is raised when the RX FIFO level reaches
+//! the `FRXTH` threshold, which the width typestates set to match the frame size: a quarter
+//! (8 bits) for [`EightBit`] and half (16 bits) for [`SixteenBit`]. With a 16-bit threshold in
+//! 8-bit mode, a single received byte never raises `RXNE` and reads stall.
+//!
+//! ## Async
+//!
+//! With the `async` feature, 8-bit `Spi`s implement `embedded_hal_async::spi::SpiBus`. Frames
+//! are exchanged one at a time, waiting on the `RXNE`/`TXE` interrupts instead of spinning. The
+//! application has to call the matching handler from the SPI interrupt and unmask it in the
+//! NVIC, any executor (e.g. embassy) can then run the futures:
+//!
+//! ```ignore
+//! #[interrupt]
+//! fn SPI1() {
+//!     air001_hal::spi::on_spi1_interrupt();
+//! }
+//!
+//! unsafe { NVIC::unmask(Interrupt::SPI1) };
+//! ```
+//!
+//! The blocking inherent methods [`Spi::read`] and [`Spi::flush`] have the same names as their
+//! `SpiBus` counterparts and take precedence in method call syntax, so `spi.read(&mut buf).await`
+//! doesn't compile. The same goes for `write` and `transfer` while the blocking `embedded_hal`
+//! traits are in scope. Call the async methods through the trait instead, or from code generic
+//! over `SpiBus`:
+//!
+//! ```ignore
+//! use embedded_hal_async::spi::SpiBus;
+//!
+//! SpiBus::write(&mut spi, &[0x9F]).await?;
+//! SpiBus::read(&mut spi, &mut id).await?;
+//! SpiBus::flush(&mut spi).await?;
+//! ```
+//!
+//! There is no DMA support yet, each frame costs one interrupt.
+
 use core::marker::PhantomData;
 use core::{ops::Deref, ptr};
 
@@ -8,9 +56,11 @@ pub use embedded_hal::spi::{Mode, Phase, Polarity};
 use crate::pac::SPI1;
 use crate::pac::SPI2;
 
+use embedded_hal::blocking::delay::DelayUs;
+
 use crate::gpio::*;
 
-use crate::rcc::{Clocks, Rcc};
+use crate::rcc::{Clocks, Peripheral, Rcc};
 
 use crate::time::Hertz;
 
@@ -32,6 +82,64 @@ pub enum Error {
     Crc,
 }
 
+/// Order in which the bits of a frame are shifted out
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub enum BitOrder {
+    /// Most significant bit first (default)
+    MsbFirst,
+    /// Least significant bit first
+    LsbFirst,
+}
+
+/// RX FIFO level at which `RXNE` is raised
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub enum RxFifoThreshold {
+    /// 8 bits, required for byte-wise reads
+    Quarter,
+    /// 16 bits
+    Half,
+}
+
+/// Fill level of a data FIFO (`FRLVL` / `FTLVL`)
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub enum FifoLevel {
+    Empty,
+    /// 8 bits
+    Quarter,
+    /// 16 bits
+    Half,
+    /// 24 to 32 bits
+    Full,
+}
+
+impl FifoLevel {
+    fn from_bits(bits: u8) -> Self {
+        match bits {
+            0 => FifoLevel::Empty,
+            1 => FifoLevel::Quarter,
+            2 => FifoLevel::Half,
+            _ => FifoLevel::Full,
+        }
+    }
+}
+
+bitflags::bitflags! {
+    /// Status flags, with the same bit positions as in `SR`
+    #[derive(Clone, Copy, PartialEq, Eq, Debug)]
+    pub struct Status: u8 {
+        /// RX FIFO reached the `FRXTH` threshold
+        const RXNE = 1 << 0;
+        /// TX FIFO has room for a frame
+        const TXE = 1 << 1;
+        /// Mode fault, NSS was pulled low in master mode
+        const MODF = 1 << 5;
+        /// Overrun, a received frame was lost
+        const OVR = 1 << 6;
+        /// Transfer in progress
+        const BSY = 1 << 7;
+    }
+}
+
 /// SPI abstraction
 pub struct Spi<SPI, SCKPIN, MISOPIN, MOSIPIN, WIDTH> {
     spi: SPI,
@@ -42,12 +150,14 @@ pub struct Spi<SPI, SCKPIN, MISOPIN, MOSIPIN, WIDTH> {
 pub trait SckPin<SPI> {}
 pub trait MisoPin<SPI> {}
 pub trait MosiPin<SPI> {}
+pub trait NssPin<SPI> {}
 
 macro_rules! spi_pins {
     ($($SPI:ident => {
         sck => [$($sck:ty),+ $(,)*],
         miso => [$($miso:ty),+ $(,)*],
         mosi => [$($mosi:ty),+ $(,)*],
+        $(nss => [$($nss:ty),+ $(,)*],)?
     })+) => {
         $(
             $(
@@ -59,6 +169,9 @@ macro_rules! spi_pins {
             $(
                 impl MosiPin<crate::pac::$SPI> for $mosi {}
             )+
+            $($(
+                impl NssPin<crate::pac::$SPI> for $nss {}
+            )+)?
         )+
     }
 }
@@ -83,6 +196,11 @@ spi_pins! {
             gpioa::PA3<Alternate<AF10>>,
             gpioa::PA7<Alternate<AF0>>,
         ],
+        nss => [
+            gpioa::PA4<Alternate<AF0>>,
+            gpioa::PA15<Alternate<AF0>>,
+            gpiob::PB0<Alternate<AF0>>,
+        ],
     }
 }
 
@@ -106,8 +224,12 @@ spi_pins! {
 }
 
 macro_rules! spi {
-    ($($SPI:ident: ($spi:ident, $spiXen:ident, $spiXrst:ident, $apbenr:ident, $apbrstr:ident),)+) => {
+    ($($SPI:ident: ($spi:ident, $PER:ident),)+) => {
         $(
+            impl Instance for $SPI {
+                const PERIPHERAL: Peripheral = Peripheral::$PER;
+            }
+
             impl<SCKPIN, MISOPIN, MOSIPIN> Spi<$SPI, SCKPIN, MISOPIN, MOSIPIN, EightBit> {
                 /// Creates a new spi instance
                 pub fn $spi<F>(
@@ -124,11 +246,10 @@ macro_rules! spi {
                     F: Into<Hertz>,
                 {
                     /* Enable clock for SPI */
-                    rcc.regs.$apbenr.modify(|_, w| w.$spiXen().set_bit());
+                    rcc.enable(Peripheral::$PER);
 
                     /* Reset SPI */
-                    rcc.regs.$apbrstr.modify(|_, w| w.$spiXrst().set_bit());
-                    rcc.regs.$apbrstr.modify(|_, w| w.$spiXrst().clear_bit());
+                    rcc.reset(Peripheral::$PER);
 
                     Spi::<$SPI, SCKPIN, MISOPIN, MOSIPIN, EightBit> { spi, pins, _width: PhantomData }.spi_init(mode, speed, rcc.clocks).into_8bit_width()
                 }
@@ -138,17 +259,30 @@ macro_rules! spi {
 }
 
 spi! {
-    SPI1: (spi1, spi1en, spi1rst, apbenr2, apbrstr2),
+    SPI1: (spi1, Spi1),
 }
 
 spi! {
-    SPI2: (spi2, spi2en, spi2rst, apbenr1, apbrstr1),
+    SPI2: (spi2, Spi2),
 }
 
 // It's s needed for the impls, but rustc doesn't recognize that
 #[allow(dead_code)]
 type SpiRegisterBlock = crate::pac::spi1::RegisterBlock;
 
+/// SCK, MISO and MOSI pins in analog mode, as returned by [`Spi::release`]
+pub type ReleasedPins<SCKPIN, MISOPIN, MOSIPIN> = (
+    <SCKPIN as Deinit>::Analog,
+    <MISOPIN as Deinit>::Analog,
+    <MOSIPIN as Deinit>::Analog,
+);
+
+/// SPI peripheral, maps the instance to its clock for [`Spi::release`]
+pub trait Instance: Deref<Target = SpiRegisterBlock> {
+    #[doc(hidden)]
+    const PERIPHERAL: Peripheral;
+}
+
 impl<SPI, SCKPIN, MISOPIN, MOSIPIN, WIDTH> Spi<SPI, SCKPIN, MISOPIN, MOSIPIN, WIDTH>
 where
     SPI: Deref<Target = SpiRegisterBlock>,
@@ -160,9 +294,9 @@ where
         /* Make sure the SPI unit is disabled so we can configure it */
         self.spi.cr1.modify(|_, w| w.spe().clear_bit());
 
-        let br = match clocks.pclk().0 / speed.into().0 {
-            0 => unreachable!(),
-            1..=2 => 0b000,
+        // a speed above pclk / 2 is clamped to the fastest baud rate
+        let br = match clocks.pclk() / speed.into().max(Hertz(1)) {
+            0..=2 => 0b000,
             3..=5 => 0b001,
             6..=11 => 0b010,
             12..=23 => 0b011,
@@ -222,11 +356,11 @@ where
 
     pub fn into_16bit_width(self) -> Spi<SPI, SCKPIN, MISOPIN, MOSIPIN, SixteenBit> {
         // FRXTH: 16-bit threshold on RX FIFO
-        // DS: 8-bit data size
+        // DS: 16-bit data size
         // SSOE: cleared to disable SS output
         self.spi
             .cr2
-            .write(|w| w.frxth().set_bit().ds().set_bit().ssoe().clear_bit());
+            .write(|w| w.frxth().clear_bit().ds().set_bit().ssoe().clear_bit());
 
         Spi {
             spi: self.spi,
@@ -235,6 +369,115 @@ where
         }
     }
 
+    /// Selects whether frames are transmitted MSB or LSB first
+    ///
+    /// The SPI is briefly disabled while `LSBFIRST` is changed, so this must not be called in the
+    /// middle of a transfer.
+    pub fn set_bit_order(&mut self, order: BitOrder) {
+        self.spi.cr1.modify(|_, w| w.spe().clear_bit());
+        self.spi
+            .cr1
+            .modify(|_, w| w.lsbfirst().bit(order == BitOrder::LsbFirst));
+        self.spi.cr1.modify(|_, w| w.spe().set_bit());
+    }
+
+    /// Overrides the RX FIFO threshold chosen for the frame width
+    ///
+    /// A 16-bit threshold in 8-bit mode allows reading two bytes at once, but every read then has
+    /// to wait for two frames.
+    pub fn set_rx_fifo_threshold(&mut self, threshold: RxFifoThreshold) {
+        self.spi
+            .cr2
+            .modify(|_, w| w.frxth().bit(threshold == RxFifoThreshold::Quarter));
+    }
+
+    /// Returns the address of the data register, the DMA peripheral address
+    ///
+    /// In 8-bit mode configure the DMA channel for byte accesses: a half-word access packs two
+    /// frames and needs the `LDMA_TX` / `LDMA_RX` handling of odd lengths. In 16-bit mode use
+    /// half-word accesses with 2-byte aligned buffers.
+    pub fn dr_address(&self) -> u32 {
+        &self.spi.dr as *const _ as u32
+    }
+
+    /// Requests a DMA transfer whenever the TX FIFO has room (`TXDMAEN`)
+    ///
+    /// The caller owns the DMA setup. Enable the RX request first and the TX request last, so no
+    /// received frame is missed.
+    pub fn enable_tx_dma(&mut self) {
+        self.spi.cr2.modify(|_, w| w.txdmaen().set_bit());
+    }
+
+    /// Stops requesting DMA transfers for transmission
+    pub fn disable_tx_dma(&mut self) {
+        self.spi.cr2.modify(|_, w| w.txdmaen().clear_bit());
+    }
+
+    /// Requests a DMA transfer whenever a frame was received (`RXDMAEN`)
+    pub fn enable_rx_dma(&mut self) {
+        self.spi.cr2.modify(|_, w| w.rxdmaen().set_bit());
+    }
+
+    /// Stops requesting DMA transfers on reception
+    pub fn disable_rx_dma(&mut self) {
+        self.spi.cr2.modify(|_, w| w.rxdmaen().clear_bit());
+    }
+
+    /// Returns the fill level of the RX FIFO
+    pub fn rx_fifo_level(&self) -> FifoLevel {
+        FifoLevel::from_bits(self.spi.sr.read().frlvl().bits())
+    }
+
+    /// Returns the fill level of the TX FIFO
+    pub fn tx_fifo_level(&self) -> FifoLevel {
+        FifoLevel::from_bits(self.spi.sr.read().ftlvl().bits())
+    }
+
+    /// Waits until the last frame has been clocked out completely
+    ///
+    /// `TXE` only means there is room in the TX FIFO: the frame it held may still be in the
+    /// shift register. `BSY` stays set until the last bit went out on MOSI, so wait for this
+    /// before deasserting a chip select or releasing the bus, otherwise the slave may lose the
+    /// end of the final frame.
+    pub fn flush(&mut self) -> Result<(), Error> {
+        while self.spi.sr.read().ftlvl().bits() != 0 {
+            self.check_errors()?;
+        }
+        while self.spi.sr.read().bsy().bit_is_set() {
+            self.check_errors()?;
+        }
+        Ok(())
+    }
+
+    /// Returns all status flags from a single read of SR
+    ///
+    /// The FIFO levels are available separately from [`Spi::rx_fifo_level`] and
+    /// [`Spi::tx_fifo_level`].
+    pub fn status(&self) -> Status {
+        Status::from_bits_truncate(self.spi.sr.read().bits() as u8)
+    }
+
+    /// Clears the error flags in `flags`
+    ///
+    /// Clearing OVR drains the RX FIFO. Clearing MODF re-enables the peripheral as master,
+    /// the fault cleared `SPE` and `MSTR`. The other flags follow the FIFO state and are ignored.
+    pub fn clear(&mut self, flags: Status) {
+        if flags.contains(Status::OVR) {
+            // OVR is cleared by reading DR followed by SR; drain the whole RX FIFO
+            while self.spi.sr.read().frlvl().bits() != 0 {
+                self.read_u8();
+            }
+            let _ = self.spi.sr.read();
+        }
+        if flags.contains(Status::MODF) {
+            // MODF is cleared by reading SR followed by writing CR1
+            let _ = self.spi.sr.read();
+            self.spi
+                .cr1
+                .modify(|_, w| w.mstr().set_bit().spe().set_bit());
+        }
+    }
+
     fn set_send_only(&mut self) {
         self.spi
             .cr1
@@ -247,18 +490,29 @@ where
             .modify(|_, w| w.bidimode().clear_bit().bidioe().clear_bit());
     }
 
+    /// Checks for overrun and mode fault, clearing the flag of a detected error
+    fn check_errors(&mut self) -> Result<(), Error> {
+        let status = self.status();
+
+        if status.contains(Status::OVR) {
+            self.clear(Status::OVR);
+            Err(Error::Overrun)
+        } else if status.contains(Status::MODF) {
+            self.clear(Status::MODF);
+            Err(Error::ModeFault)
+        } else {
+            Ok(())
+        }
+    }
+
     fn check_read(&mut self) -> nb::Result<(), Error> {
-        let sr = self.spi.sr.read();
+        self.check_errors()?;
 
-        Err(if sr.ovr().bit_is_set() {
-            nb::Error::Other(Error::Overrun)
-        } else if sr.modf().bit_is_set() {
-            nb::Error::Other(Error::ModeFault)
-        } else if sr.rxne().bit_is_set() {
-            return Ok(());
+        if self.spi.sr.read().rxne().bit_is_set() {
+            Ok(())
         } else {
-            nb::Error::WouldBlock
-        })
+            Err(nb::Error::WouldBlock)
+        }
     }
 
     fn send_buffer_size(&mut self) -> u8 {
@@ -275,17 +529,14 @@ where
     }
 
     fn check_send(&mut self) -> nb::Result<(), Error> {
-        let sr = self.spi.sr.read();
+        self.check_errors()?;
 
-        Err(if sr.ovr().bit_is_set() {
-            nb::Error::Other(Error::Overrun)
-        } else if sr.modf().bit_is_set() {
-            nb::Error::Other(Error::ModeFault)
-        } else if sr.txe().bit_is_set() && sr.bsy().bit_is_clear() {
-            return Ok(());
+        let sr = self.spi.sr.read();
+        if sr.txe().bit_is_set() && sr.bsy().bit_is_clear() {
+            Ok(())
         } else {
-            nb::Error::WouldBlock
-        })
+            Err(nb::Error::WouldBlock)
+        }
     }
 
     fn read_u8(&mut self) -> u8 {
@@ -311,8 +562,120 @@ where
         unsafe { ptr::write_volatile(write_ptr, byte) }
     }
 
-    pub fn release(self) -> (SPI, (SCKPIN, MISOPIN, MOSIPIN)) {
-        (self.spi, self.pins)
+    /// Disables the SPI and releases the peripheral and pins
+    ///
+    /// The SPI is reset and its clock gated, the pins are returned in analog mode.
+    pub fn release(self) -> (SPI, ReleasedPins<SCKPIN, MISOPIN, MOSIPIN>)
+    where
+        SPI: Instance,
+        SCKPIN: Deinit,
+        MISOPIN: Deinit,
+        MOSIPIN: Deinit,
+    {
+        self.spi.cr1.modify(|_, w| w.spe().clear_bit());
+        crate::rcc::reset(SPI::PERIPHERAL);
+        crate::rcc::disable(SPI::PERIPHERAL);
+        let (sck, miso, mosi) = self.pins;
+        let pins =
+            cortex_m::interrupt::free(|cs| (sck.deinit(cs), miso.deinit(cs), mosi.deinit(cs)));
+        (self.spi, pins)
+    }
+}
+
+impl<SPI, SCKPIN, MISOPIN, MOSIPIN> Spi<SPI, SCKPIN, MISOPIN, MOSIPIN, EightBit>
+where
+    SPI: Deref<Target = SpiRegisterBlock>,
+{
+    /// Fills `words` with received bytes, clocking out `0x00` as dummy data
+    ///
+    /// Unlike `transfer` the buffer doesn't have to be prepared with data to send. For the
+    /// write-only direction use `blocking::spi::Write`, which never reads the data register.
+    pub fn read(&mut self, words: &mut [u8]) -> Result<(), Error> {
+        // We want to transfer bidirectionally, make sure we're in the correct mode
+        self.set_bidi();
+
+        for word in words.iter_mut() {
+            nb::block!(self.check_send())?;
+            self.send_u8(0);
+            nb::block!(self.check_read())?;
+            *word = self.read_u8();
+        }
+
+        Ok(())
+    }
+
+    /// Sends `tx` while receiving into `rx`, buffers may differ in length
+    ///
+    /// `max(tx.len(), rx.len())` frames are clocked: `0x00` is sent past the end of `tx` and
+    /// bytes received past the end of `rx` are discarded. Returns the number of bytes stored in
+    /// `rx`.
+    pub fn transfer_split(&mut self, tx: &[u8], rx: &mut [u8]) -> Result<usize, Error> {
+        // We want to transfer bidirectionally, make sure we're in the correct mode
+        self.set_bidi();
+
+        for i in 0..tx.len().max(rx.len()) {
+            nb::block!(self.check_send())?;
+            self.send_u8(tx.get(i).copied().unwrap_or(0));
+            nb::block!(self.check_read())?;
+            let byte = self.read_u8();
+            if let Some(word) = rx.get_mut(i) {
+                *word = byte;
+            }
+        }
+
+        Ok(rx.len())
+    }
+
+    /// Like `Transfer::transfer`, but waits `gap_us` between frames
+    ///
+    /// Each frame is fully exchanged before the gap starts, so slow slaves get processing time
+    /// between bytes independent of the SCK frequency.
+    pub fn transfer_with_delay<'w, D>(
+        &mut self,
+        words: &'w mut [u8],
+        delay: &mut D,
+        gap_us: u16,
+    ) -> Result<&'w [u8], Error>
+    where
+        D: DelayUs<u16>,
+    {
+        // We want to transfer bidirectionally, make sure we're in the correct mode
+        self.set_bidi();
+
+        for (i, word) in words.iter_mut().enumerate() {
+            if i != 0 {
+                delay.delay_us(gap_us);
+            }
+            nb::block!(self.check_send())?;
+            self.send_u8(*word);
+            nb::block!(self.check_read())?;
+            *word = self.read_u8();
+        }
+
+        Ok(words)
+    }
+}
+
+impl<SPI, SCKPIN, MISOPIN, MOSIPIN> Spi<SPI, SCKPIN, MISOPIN, MOSIPIN, SixteenBit>
+where
+    SPI: Deref<Target = SpiRegisterBlock>,
+{
+    /// Fills `words` with received half-words, clocking out `0x0000` as dummy data
+    ///
+    /// Unlike `transfer` the buffer doesn't have to be prepared with data to send. For the
+    /// write-only direction use `blocking::spi::Write`, which never reads the data register.
+    pub fn read(&mut self, words: &mut [u16]) -> Result<(), Error> {
+        // We want to transfer bidirectionally, make sure we're in the correct mode
+        self.set_bidi();
+
+        for word in words.iter_mut() {
+            nb::block!(self.check_send())?;
+            self.send_u16(0);
+            nb::block!(self.check_read())?;
+            *word = self.read_u16();
+        }
+
+        Ok(())
     }
 }
 
@@ -358,6 +721,7 @@ where
         for word in words {
             // Loop as long as our send buffer is full
             while bufcap == 0 {
+                self.check_errors()?;
                 bufcap = self.send_buffer_size();
             }
 
@@ -366,8 +730,7 @@ where
         }
 
         // Do one last status register check before continuing
-        nb::block!(self.check_send()).ok();
-        Ok(())
+        nb::block!(self.check_send())
     }
 }
 
@@ -410,7 +773,158 @@ where
         }
 
         // Do one last status register check before continuing
-        nb::block!(self.check_send()).ok();
-        Ok(())
+        nb::block!(self.check_send())
+    }
+}
+
+#[cfg(feature = "embedded-hal-1")]
+impl embedded_hal_1::spi::Error for Error {
+    fn kind(&self) -> embedded_hal_1::spi::ErrorKind {
+        match self {
+            Error::Overrun => embedded_hal_1::spi::ErrorKind::Overrun,
+            Error::ModeFault => embedded_hal_1::spi::ErrorKind::ModeFault,
+            Error::Crc => embedded_hal_1::spi::ErrorKind::Other,
+        }
+    }
+}
+
+#[cfg(feature = "embedded-hal-1")]
+impl<SPI, SCKPIN, MISOPIN, MOSIPIN, WIDTH> embedded_hal_1::spi::ErrorType
+    for Spi<SPI, SCKPIN, MISOPIN, MOSIPIN, WIDTH>
+{
+    type Error = Error;
+}
+
+#[cfg(feature = "async")]
+pub use asynch::{on_spi1_interrupt, on_spi2_interrupt};
+
+#[cfg(feature = "async")]
+mod asynch {
+    use core::future::poll_fn;
+    use core::ops::Deref;
+    use core::ptr;
+    use core::task::{Context, Poll};
+
+    use super::{EightBit, Error, Spi, SpiRegisterBlock, SPI1, SPI2};
+    use crate::waker::WakerCell;
+
+    static WAKERS: [WakerCell; 2] = [WakerCell::new(), WakerCell::new()];
+
+    macro_rules! on_interrupt {
+        ($($SPI:ident: ($on_interrupt:ident, $index:expr),)+) => {
+            $(
+                /// Wakes the task waiting on the SPI, call this from its interrupt handler
+                pub fn $on_interrupt() {
+                    // NOTE(unsafe) the interrupt enables are only changed by the waiting task,
+                    // which doesn't run while the handler does
+                    unsafe {
+                        (*$SPI::ptr()).cr2.modify(|_, w| {
+                            w.rxneie().clear_bit().txeie().clear_bit().errie().clear_bit()
+                        })
+                    };
+                    WAKERS[$index].wake();
+                }
+            )+
+        };
+    }
+
+    on_interrupt! {
+        SPI1: (on_spi1_interrupt, 0),
+        SPI2: (on_spi2_interrupt, 1),
+    }
+
+    #[derive(Clone, Copy)]
+    enum Wait {
+        Txe,
+        Rxne,
+    }
+
+    impl<SPI, SCKPIN, MISOPIN, MOSIPIN> Spi<SPI, SCKPIN, MISOPIN, MOSIPIN, EightBit>
+    where
+        SPI: Deref<Target = SpiRegisterBlock>,
+    {
+        fn waker(&self) -> &'static WakerCell {
+            if ptr::eq(&*self.spi, SPI1::ptr()) {
+                &WAKERS[0]
+            } else {
+                &WAKERS[1]
+            }
+        }
+
+        fn poll_flag(&mut self, cx: &mut Context<'_>, wait: Wait) -> Poll<Result<(), Error>> {
+            self.check_errors()?;
+
+            let sr = self.spi.sr.read();
+            let ready = match wait {
+                Wait::Txe => sr.txe().bit_is_set(),
+                Wait::Rxne => sr.rxne().bit_is_set(),
+            };
+            if ready {
+                return Poll::Ready(Ok(()));
+            }
+
+            // The flags are level triggered: if the event happened since the check above, the
+            // interrupt fires as soon as it is enabled
+            self.waker().register(cx.waker());
+            self.spi.cr2.modify(|_, w| match wait {
+                Wait::Txe => w.txeie().set_bit().errie().set_bit(),
+                Wait::Rxne => w.rxneie().set_bit().errie().set_bit(),
+            });
+            Poll::Pending
+        }
+
+        async fn exchange(&mut self, byte: u8) -> Result<u8, Error> {
+            poll_fn(|cx| self.poll_flag(cx, Wait::Txe)).await?;
+            self.send_u8(byte);
+            poll_fn(|cx| self.poll_flag(cx, Wait::Rxne)).await?;
+            Ok(self.read_u8())
+        }
+    }
+
+    impl<SPI, SCKPIN, MISOPIN, MOSIPIN> embedded_hal_async::spi::SpiBus<u8>
+        for Spi<SPI, SCKPIN, MISOPIN, MOSIPIN, EightBit>
+    where
+        SPI: Deref<Target = SpiRegisterBlock>,
+    {
+        async fn read(&mut self, words: &mut [u8]) -> Result<(), Error> {
+            self.set_bidi();
+            for word in words.iter_mut() {
+                *word = self.exchange(0).await?;
+            }
+            Ok(())
+        }
+
+        async fn write(&mut self, words: &[u8]) -> Result<(), Error> {
+            self.set_bidi();
+            for word in words {
+                self.exchange(*word).await?;
+            }
+            Ok(())
+        }
+
+        async fn transfer(&mut self, read: &mut [u8], write: &[u8]) -> Result<(), Error> {
+            self.set_bidi();
+            for i in 0..read.len().max(write.len()) {
+                let byte = self.exchange(write.get(i).copied().unwrap_or(0)).await?;
+                if let Some(word) = read.get_mut(i) {
+                    *word = byte;
+                }
+            }
+            Ok(())
+        }
+
+        async fn transfer_in_place(&mut self, words: &mut [u8]) -> Result<(), Error> {
+            self.set_bidi();
+            for word in words.iter_mut() {
+                *word = self.exchange(*word).await?;
+            }
+            Ok(())
+        }
+
+        async fn flush(&mut self) -> Result<(), Error> {
+            // Every frame was already received, BSY only covers the last clock edges
+            while self.spi.sr.read().bsy().bit_is_set() {}
+            Ok(())
+        }
     }
 }
@@ -1,14 +1,18 @@
+use core::ops::{Div, Mul};
+
+pub use fugit::{ExtU32, MicrosDurationU32, MillisDurationU32};
+
 /// Bits per second
-#[derive(PartialEq, PartialOrd, Clone, Copy)]
+#[derive(PartialEq, Eq, PartialOrd, Ord, Clone, Copy, Debug)]
 pub struct Bps(pub u32);
 
-#[derive(PartialEq, PartialOrd, Clone, Copy)]
+#[derive(PartialEq, Eq, PartialOrd, Ord, Clone, Copy, Debug)]
 pub struct Hertz(pub u32);
 
-#[derive(PartialEq, PartialOrd, Clone, Copy)]
+#[derive(PartialEq, Eq, PartialOrd, Ord, Clone, Copy, Debug)]
 pub struct KiloHertz(pub u32);
 
-#[derive(PartialEq, PartialOrd, Clone, Copy)]
+#[derive(PartialEq, Eq, PartialOrd, Ord, Clone, Copy, Debug)]
 pub struct MegaHertz(pub u32);
 
 /// Extension trait that adds convenience methods to the `u32` type
@@ -46,18 +50,112 @@ impl U32Ext for u32 {
 
 impl From<KiloHertz> for Hertz {
     fn from(khz: KiloHertz) -> Self {
-        Hertz(khz.0 * 1_000)
+        Hertz(khz.0.saturating_mul(1_000))
     }
 }
 
 impl From<MegaHertz> for Hertz {
     fn from(mhz: MegaHertz) -> Self {
-        Hertz(mhz.0 * 1_000_000)
+        Hertz(mhz.0.saturating_mul(1_000_000))
     }
 }
 
 impl From<MegaHertz> for KiloHertz {
     fn from(mhz: MegaHertz) -> Self {
-        KiloHertz(mhz.0 * 1_000)
+        KiloHertz(mhz.0.saturating_mul(1_000))
+    }
+}
+
+/// Clock cycles per bit, e.g. the USART baud rate divider
+///
+/// Saturates to `u32::MAX` for a zero baud rate.
+impl Div<Bps> for Hertz {
+    type Output = u32;
+
+    fn div(self, rhs: Bps) -> u32 {
+        self.0.checked_div(rhs.0).unwrap_or(u32::MAX)
+    }
+}
+
+/// Ratio of two frequencies, e.g. a timer prescaler
+///
+/// Saturates to `u32::MAX` for a zero divisor.
+impl Div<Hertz> for Hertz {
+    type Output = u32;
+
+    fn div(self, rhs: Hertz) -> u32 {
+        self.0.checked_div(rhs.0).unwrap_or(u32::MAX)
+    }
+}
+
+// saturating like the unit conversions, a division by zero gives `u32::MAX`
+macro_rules! scale {
+    ($($T:ident,)+) => {
+        $(
+            impl Mul<u32> for $T {
+                type Output = $T;
+
+                fn mul(self, rhs: u32) -> $T {
+                    $T(self.0.saturating_mul(rhs))
+                }
+            }
+
+            impl Div<u32> for $T {
+                type Output = $T;
+
+                fn div(self, rhs: u32) -> $T {
+                    $T(self.0.checked_div(rhs).unwrap_or(u32::MAX))
+                }
+            }
+        )+
+    };
+}
+
+scale! {
+    Bps,
+    Hertz,
+    KiloHertz,
+    MegaHertz,
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn divide_frequencies() {
+        assert_eq!(8_000_000.hz() / 115_200.bps(), 69);
+        assert_eq!(48_000_000.hz() / 1_000.hz(), 48_000);
+        assert_eq!(1.hz() / 2.hz(), 0);
+    }
+
+    #[test]
+    fn divide_by_zero_saturates() {
+        assert_eq!(8_000_000.hz() / 0.bps(), u32::MAX);
+        assert_eq!(8_000_000.hz() / 0.hz(), u32::MAX);
+        assert_eq!(0.hz() / 0.hz(), u32::MAX);
+        assert_eq!(1.hz() / 0, Hertz(u32::MAX));
+        assert_eq!(9_600.bps() / 0, Bps(u32::MAX));
+    }
+
+    #[test]
+    fn scale() {
+        assert_eq!(1.khz() * 3, KiloHertz(3));
+        assert_eq!(48.mhz() / 2, MegaHertz(24));
+        assert_eq!(0.hz() * u32::MAX, Hertz(0));
+        assert_eq!(u32::MAX.hz() / 1, Hertz(u32::MAX));
+    }
+
+    #[test]
+    fn multiply_saturates() {
+        assert_eq!(u32::MAX.hz() * 2, Hertz(u32::MAX));
+        assert_eq!(100_000.bps() * 100_000, Bps(u32::MAX));
+    }
+
+    #[test]
+    fn convert_saturates() {
+        assert_eq!(Hertz::from(24.mhz()), Hertz(24_000_000));
+        assert_eq!(Hertz::from(u32::MAX.khz()), Hertz(u32::MAX));
+        assert_eq!(KiloHertz::from(u32::MAX.mhz()), KiloHertz(u32::MAX));
     }
 }
@@ -1,9 +1,13 @@
+use core::marker::PhantomData;
+use core::sync::atomic::{compiler_fence, Ordering};
+
 use cortex_m::peripheral::syst::SystClkSource;
 use cortex_m::peripheral::SYST;
 
-use crate::rcc::{Clocks, Rcc};
+use crate::dma;
+use crate::rcc::{self, Clocks, Peripheral, Rcc};
 
-use crate::time::Hertz;
+use crate::time::{Hertz, MillisDurationU32};
 use embedded_hal::timer::{CountDown, Periodic};
 use void::Void;
 
@@ -13,10 +17,23 @@ pub struct Timer<TIM> {
     tim: TIM,
 }
 
+/// Capture/compare channel of a timer
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub enum Channel {
+    C1,
+    C2,
+    C3,
+    C4,
+}
+
 /// Interrupt events
 pub enum Event {
     /// Timer timed out / count down ended
     TimeOut,
+    /// Counter matched the compare value of a channel (CCxIF)
+    ///
+    /// Channels the timer does not have are ignored.
+    Compare(Channel),
 }
 
 impl Timer<SYST> {
@@ -38,6 +55,8 @@ impl Timer<SYST> {
     pub fn listen(&mut self, event: &Event) {
         match event {
             Event::TimeOut => self.tim.enable_interrupt(),
+            // SysTick has no compare channels
+            Event::Compare(_) => {}
         }
     }
 
@@ -45,13 +64,15 @@ impl Timer<SYST> {
     pub fn unlisten(&mut self, event: &Event) {
         match event {
             Event::TimeOut => self.tim.disable_interrupt(),
+            Event::Compare(_) => {}
         }
     }
 }
 
 /// Use the systick as a timer
 ///
-/// Be aware that intervals less than 4 Hertz may not function properly
+/// Rates below `sysclk / 2^24` (about 3 Hz at 48 MHz) are clamped to the longest period, rates
+/// above `sysclk / 2` to the shortest one
 impl CountDown for Timer<SYST> {
     type Time = Hertz;
 
@@ -60,9 +81,7 @@ impl CountDown for Timer<SYST> {
     where
         T: Into<Hertz>,
     {
-        let rvr = self.clocks.sysclk().0 / timeout.into().0 - 1;
-
-        assert!(rvr < (1 << 24));
+        let rvr = syst_reload(self.clocks.sysclk(), timeout.into());
 
         self.tim.set_reload(rvr);
         self.tim.clear_current();
@@ -82,8 +101,48 @@ impl CountDown for Timer<SYST> {
 
 impl Periodic for Timer<SYST> {}
 
+/// Reload value for a SysTick period of `sysclk / timeout` cycles
+///
+/// Clamped to the 24 bit counter, a zero timeout gives the longest period. The shortest period
+/// is two cycles, a reload value of 0 would stop the SysTick.
+fn syst_reload(sysclk: Hertz, timeout: Hertz) -> u32 {
+    (sysclk / timeout).clamp(2, 1 << 24) - 1
+}
+
+/// Prescaler and auto-reload values generating a timer period
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub struct TimerPeriod {
+    /// Prescaler, the counter is clocked at `tclk / (psc + 1)`
+    pub psc: u16,
+    /// Auto-reload value, the counter overflows every `arr + 1` counts
+    pub arr: u16,
+}
+
+impl TimerPeriod {
+    /// Computes the prescaler and auto-reload values to overflow at `freq` from the timer input
+    /// clock `tclk`
+    ///
+    /// The smallest prescaler for which the period fits into the 16 bit counter is chosen, which
+    /// keeps ARR and with it the duty cycle resolution as large as possible.
+    pub fn new(tclk: Hertz, freq: Hertz) -> Self {
+        let ticks = (tclk / freq.max(Hertz(1))).max(1);
+        let psc = ((ticks - 1) / (1 << 16)).min(0xFFFF);
+        // round to nearest, `psc.div_ceil(2)` is half the divider `(psc + 1) / 2`
+        let arr = (ticks + psc.div_ceil(2)) / (psc + 1);
+        TimerPeriod {
+            psc: psc as u16,
+            arr: (arr.clamp(1, 1 << 16) - 1) as u16,
+        }
+    }
+
+    /// Returns the number of bits of resolution within one period, i.e. `log2(arr + 1)`
+    pub fn resolution_bits(&self) -> u8 {
+        (31 - (u32::from(self.arr) + 1).leading_zeros()) as u8
+    }
+}
+
 macro_rules! timers {
-    ($($TIM:ident: ($tim:ident, $timXen:ident, $timXrst:ident, $apbenr:ident, $apbrstr:ident),)+) => {
+    ($($TIM:ident: ($tim:ident, $PER:ident, [$($CH:ident: ($ccie:ident, $ccif:ident),)+]),)+) => {
         $(
             use crate::pac::$TIM;
             impl Timer<$TIM> {
@@ -96,9 +155,8 @@ macro_rules! timers {
                     T: Into<Hertz>,
                 {
                     // enable and reset peripheral to a clean slate state
-                    rcc.regs.$apbenr.modify(|_, w| w.$timXen().set_bit());
-                    rcc.regs.$apbrstr.modify(|_, w| w.$timXrst().set_bit());
-                    rcc.regs.$apbrstr.modify(|_, w| w.$timXrst().clear_bit());
+                    rcc.enable(Peripheral::$PER);
+                    rcc.reset(Peripheral::$PER);
 
                     let mut timer = Timer {
                         clocks: rcc.clocks,
@@ -114,8 +172,15 @@ macro_rules! timers {
                     match event {
                         Event::TimeOut => {
                             // Enable update event interrupt
-                            self.tim.dier.write(|w| w.uie().set_bit());
+                            self.tim.dier.modify(|_, w| w.uie().set_bit());
                         }
+                        $(
+                            Event::Compare(Channel::$CH) => {
+                                self.tim.dier.modify(|_, w| w.$ccie().set_bit());
+                            }
+                        )+
+                        #[allow(unreachable_patterns)]
+                        Event::Compare(_) => {}
                     }
                 }
 
@@ -123,26 +188,67 @@ macro_rules! timers {
                 pub fn unlisten(&mut self, event: Event) {
                     match event {
                         Event::TimeOut => {
-                            // Enable update event interrupt
-                            self.tim.dier.write(|w| w.uie().clear_bit());
+                            // Disable update event interrupt
+                            self.tim.dier.modify(|_, w| w.uie().clear_bit());
                         }
+                        $(
+                            Event::Compare(Channel::$CH) => {
+                                self.tim.dier.modify(|_, w| w.$ccie().clear_bit());
+                            }
+                        )+
+                        #[allow(unreachable_patterns)]
+                        Event::Compare(_) => {}
                     }
                 }
 
                 /// Releases the TIM peripheral
+                ///
+                /// The timer is reset, so whatever role it is used for next starts from the
+                /// power-on register state.
                 pub fn release(self) -> $TIM {
-                    let rcc = unsafe { &(*crate::pac::RCC::ptr()) };
                     // Pause counter
                     self.tim.cr1.modify(|_, w| w.cen().clear_bit());
-                    // Disable timer
-                    rcc.$apbenr.modify(|_, w| w.$timXen().clear_bit());
+                    // Clear the configuration and disable timer
+                    rcc::reset(Peripheral::$PER);
+                    rcc::disable(Peripheral::$PER);
                     self.tim
                 }
 
+                /// Returns the number of bits of resolution within one timer period
+                pub fn resolution_bits(&self) -> u8 {
+                    let arr = self.tim.arr.read().bits() & 0xFFFF;
+                    (31 - (arr + 1).leading_zeros()) as u8
+                }
+
                 /// Clears interrupt flag
                 pub fn clear_irq(&mut self) {
                     self.tim.sr.modify(|_, w| w.uif().clear_bit());
                 }
+
+                /// Returns true if the compare flag (CCxIF) of `channel` is set
+                ///
+                /// Always false for channels the timer does not have.
+                pub fn is_compare_pending(&self, channel: Channel) -> bool {
+                    let sr = self.tim.sr.read();
+                    match channel {
+                        $(
+                            Channel::$CH => sr.$ccif().bit_is_set(),
+                        )+
+                        #[allow(unreachable_patterns)]
+                        _ => false,
+                    }
+                }
+
+                /// Clears the compare flag (CCxIF) of `channel` to re-arm its interrupt
+                pub fn clear_compare(&mut self, channel: Channel) {
+                    match channel {
+                        $(
+                            Channel::$CH => self.tim.sr.modify(|_, w| w.$ccif().clear_bit()),
+                        )+
+                        #[allow(unreachable_patterns)]
+                        _ => {}
+                    }
+                }
             }
 
             impl CountDown for Timer<$TIM> {
@@ -159,21 +265,12 @@ macro_rules! timers {
                     self.tim.cnt.reset();
 
                     let frequency = timeout.into().0;
-                    // If pclk is prescaled from hclk, the frequency fed into the timers is doubled
-                    let tclk = if self.clocks.hclk().0 == self.clocks.pclk().0 {
-                        self.clocks.pclk().0
-                    } else {
-                        self.clocks.pclk().0 * 2
-                    };
-                    let ticks = tclk / frequency;
-
-                    let psc = cast::u16((ticks - 1) / (1 << 16)).unwrap();
+                    let tclk = self.clocks.peripheral_clock(Peripheral::$PER);
+                    let period = TimerPeriod::new(tclk, Hertz(frequency));
                     unsafe {
-                        self.tim.psc.write(|w| w.psc().bits(psc));
+                        self.tim.psc.write(|w| w.psc().bits(period.psc));
                     }
-
-                    let arr = cast::u16(ticks / cast::u32(psc + 1)).unwrap();
-                    self.tim.arr.write(|w| unsafe { w.bits(cast::u32(arr)) });
+                    self.tim.arr.write(|w| unsafe { w.bits(u32::from(period.arr)) });
 
                     // start counter
                     self.tim.cr1.modify(|_, w| w.cen().set_bit());
@@ -197,11 +294,132 @@ macro_rules! timers {
 }
 
 timers! {
-    TIM1: (tim1, tim1en, tim1rst, apbenr2, apbrstr2),
-    TIM3: (tim3, tim3en, tim3rst, apbenr1, apbrstr1),
-    TIM14: (tim14, tim14en, tim14rst, apbenr2, apbrstr2),
-    TIM16: (tim16, tim16en, tim16rst, apbenr2, apbrstr2),
-    TIM17: (tim17, tim17en, tim17rst, apbenr2, apbrstr2),
+    TIM1: (tim1, Tim1, [
+        C1: (cc1ie, cc1if),
+        C2: (cc2ie, cc2if),
+        C3: (cc3ie, cc3if),
+        C4: (cc4ie, cc4if),
+    ]),
+    TIM3: (tim3, Tim3, [
+        C1: (cc1ie, cc1if),
+        C2: (cc2ie, cc2if),
+        C3: (cc3ie, cc3if),
+        C4: (cc4ie, cc4if),
+    ]),
+    TIM14: (tim14, Tim14, [C1: (cc1ie, cc1if),]),
+    TIM16: (tim16, Tim16, [C1: (cc1ie, cc1if),]),
+    TIM17: (tim17, Tim17, [C1: (cc1ie, cc1if),]),
+}
+
+/// Free-running tick counter extended to 64 bits in software
+///
+/// The hardware counts the low 16 bits, [`on_overflow`] has to be called from the timer's
+/// update interrupt to count the upper bits. Reading the extended count is only consistent
+/// while the interrupt can't run in between, so when the counter is shared between `main` and
+/// the handler, access it inside a critical section (e.g. with
+/// `Shared` from the `shared` module). The pending update flag is taken into account, so a
+/// read inside a critical section right after an overflow is still correct. If the
+/// interrupt is blocked for longer than one counter period, overflows are lost.
+///
+/// [`on_overflow`]: Counter::on_overflow
+pub struct Counter<TIM> {
+    tim: TIM,
+    tick: Hertz,
+    high: u64,
+}
+
+macro_rules! counter {
+    ($($TIM:ident: ($tim:ident, $PER:ident),)+) => {
+        $(
+            impl Counter<$TIM> {
+                /// Starts counting at `tick`, which is rounded to a divider of the timer clock
+                ///
+                /// The update interrupt is enabled, it still has to be unmasked in the NVIC.
+                pub fn $tim<T>(tim: $TIM, tick: T, rcc: &mut Rcc) -> Self
+                where
+                    T: Into<Hertz>,
+                {
+                    rcc.enable(Peripheral::$PER);
+                    rcc.reset(Peripheral::$PER);
+
+                    let tclk = rcc.clocks.peripheral_clock(Peripheral::$PER);
+                    let psc = (tclk / tick.into().max(Hertz(1))).clamp(1, 1 << 16) - 1;
+                    tim.psc.write(|w| unsafe { w.psc().bits(psc as u16) });
+                    tim.arr.write(|w| unsafe { w.bits(0xFFFF) });
+                    // Load the prescaler without raising an update interrupt
+                    tim.cr1.modify(|_, w| w.urs().set_bit());
+                    tim.egr.write(|w| w.ug().set_bit());
+                    tim.sr.modify(|_, w| w.uif().clear_bit());
+                    tim.dier.modify(|_, w| w.uie().set_bit());
+                    tim.cr1.modify(|_, w| w.cen().set_bit());
+
+                    Counter {
+                        tim,
+                        tick: tclk / (psc + 1),
+                        high: 0,
+                    }
+                }
+
+                /// Returns the actual tick frequency
+                pub fn tick(&self) -> Hertz {
+                    self.tick
+                }
+
+                /// Counts an overflow, to be called from the update interrupt handler
+                pub fn on_overflow(&mut self) {
+                    if self.tim.sr.read().uif().bit_is_set() {
+                        self.tim.sr.modify(|_, w| w.uif().clear_bit());
+                        self.high += 1;
+                    }
+                }
+
+                /// Returns the number of ticks since the counter was started
+                pub fn now(&self) -> u64 {
+                    let cnt = self.tim.cnt.read().bits() & 0xFFFF;
+                    if self.tim.sr.read().uif().bit_is_set() {
+                        // The overflow has not been counted yet and may have happened after
+                        // `cnt` was read; the counter read now is certainly past it
+                        let cnt = self.tim.cnt.read().bits() & 0xFFFF;
+                        ((self.high + 1) << 16) | u64::from(cnt)
+                    } else {
+                        (self.high << 16) | u64::from(cnt)
+                    }
+                }
+
+                /// Returns the number of ticks elapsed since `since`, a value of [`now`]
+                ///
+                /// [`now`]: Counter::now
+                pub fn elapsed(&self, since: u64) -> u64 {
+                    self.now().saturating_sub(since)
+                }
+
+                /// Returns `Ok` once `now()` has reached `tick`
+                pub fn wait_until(&self, tick: u64) -> nb::Result<(), Void> {
+                    if self.now() >= tick {
+                        Ok(())
+                    } else {
+                        Err(nb::Error::WouldBlock)
+                    }
+                }
+
+                /// Stops the counter and releases the TIM peripheral, reset to its power-on state
+                pub fn release(self) -> $TIM {
+                    self.tim.cr1.modify(|_, w| w.cen().clear_bit());
+                    rcc::reset(Peripheral::$PER);
+                    rcc::disable(Peripheral::$PER);
+                    self.tim
+                }
+            }
+        )+
+    };
+}
+
+counter! {
+    TIM1: (tim1, Tim1),
+    TIM3: (tim3, Tim3),
+    TIM14: (tim14, Tim14),
+    TIM16: (tim16, Tim16),
+    TIM17: (tim17, Tim17),
 }
 
 use crate::gpio::{AF0, AF1, AF2, AF4, AF5};
@@ -256,3 +474,828 @@ channel_impl!(
     TIM17, PinC1, PA7, Alternate<AF5>;
     TIM17, PinC1, PB9, Alternate<AF2>;
 );
+
+use crate::pwm::{C1, C2, C3, C4};
+
+/// Output compare mode
+#[derive(Clone, Copy, PartialEq, Eq)]
+pub enum OcMode {
+    /// Set the output to its active level on compare match
+    SetActive,
+    /// Set the output to its inactive level on compare match
+    SetInactive,
+    /// Toggle the output on compare match
+    Toggle,
+}
+
+impl OcMode {
+    fn bits(self) -> u8 {
+        match self {
+            OcMode::SetActive => 0b001,
+            OcMode::SetInactive => 0b010,
+            OcMode::Toggle => 0b011,
+        }
+    }
+}
+
+/// Marker trait for pins usable as output compare `CHANNEL` of timer `TIM`
+pub trait OcPin<TIM, CHANNEL> {}
+
+impl<TIM, P: PinC1<TIM>> OcPin<TIM, C1> for P {}
+impl<TIM, P: PinC2<TIM>> OcPin<TIM, C2> for P {}
+impl<TIM, P: PinC3<TIM>> OcPin<TIM, C3> for P {}
+impl<TIM, P: PinC4<TIM>> OcPin<TIM, C4> for P {}
+
+/// Timer channel that can be driven in output compare mode
+pub trait OcChannel<TIM> {
+    /// Configures the channel for `mode` and enables its output
+    fn configure(mode: OcMode);
+    /// Disables the channel output
+    fn disable();
+    /// Sets the compare value
+    fn set_compare(value: u16);
+    /// Returns the compare value
+    fn get_compare() -> u16;
+    /// Returns true if the compare match flag is set
+    fn is_match() -> bool;
+    /// Clears the compare match flag
+    fn clear_match();
+}
+
+/// Timer channel in output compare mode
+pub struct OutputCompare<TIM, CHANNEL, PIN> {
+    pin: PIN,
+    _tim: PhantomData<TIM>,
+    _channel: PhantomData<CHANNEL>,
+}
+
+impl<TIM, CHANNEL, PIN> OutputCompare<TIM, CHANNEL, PIN>
+where
+    CHANNEL: OcChannel<TIM>,
+{
+    /// Sets the counter value at which the output changes
+    pub fn set_compare(&mut self, value: u16) {
+        CHANNEL::set_compare(value)
+    }
+
+    /// Returns the counter value at which the output changes
+    pub fn get_compare(&self) -> u16 {
+        CHANNEL::get_compare()
+    }
+
+    /// Returns true if the counter matched the compare value since the flag was last cleared
+    ///
+    /// Use this to sequence several compares, e.g. load the next compare value once the
+    /// current one has fired.
+    pub fn is_match(&self) -> bool {
+        CHANNEL::is_match()
+    }
+
+    /// Clears the compare match flag
+    pub fn clear_match(&mut self) {
+        CHANNEL::clear_match()
+    }
+
+    /// Disables the channel output and releases the pin
+    pub fn release(self) -> PIN {
+        CHANNEL::disable();
+        self.pin
+    }
+}
+
+// the following timers have a main output switch, which gates all channel outputs
+macro_rules! moe {
+    (TIM1, $tim:expr) => {
+        $tim.bdtr.modify(|_, w| w.moe().set_bit());
+    };
+    (TIM16, $tim:expr) => {
+        $tim.bdtr.modify(|_, w| w.moe().set_bit());
+    };
+    (TIM17, $tim:expr) => {
+        $tim.bdtr.modify(|_, w| w.moe().set_bit());
+    };
+    ($_other:ident, $_tim:expr) => {};
+}
+
+macro_rules! output_compare {
+    ($($TIM:ident: [$($CX:ident: ($ccmrx_output:ident, $ocxm:ident, $ocxpe:ident, $ccxe:ident, $ccrx:ident, $ccxif:ident),)+],)+) => {
+        $(
+            impl Timer<$TIM> {
+                /// Configures `pin` as an output compare channel
+                ///
+                /// Whenever the counter reaches the channel's compare value the output is changed
+                /// according to `mode`. The timer period set by `start` is left untouched.
+                pub fn output_compare<CHANNEL, PIN>(
+                    &mut self,
+                    pin: PIN,
+                    mode: OcMode,
+                ) -> OutputCompare<$TIM, CHANNEL, PIN>
+                where
+                    CHANNEL: OcChannel<$TIM>,
+                    PIN: OcPin<$TIM, CHANNEL>,
+                {
+                    CHANNEL::configure(mode);
+                    moe!($TIM, self.tim);
+                    OutputCompare {
+                        pin,
+                        _tim: PhantomData,
+                        _channel: PhantomData,
+                    }
+                }
+            }
+
+            $(
+                impl OcChannel<$TIM> for $CX {
+                    //NOTE(unsafe) the channel registers are only accessed through this channel
+                    fn configure(mode: OcMode) {
+                        let tim = unsafe { &*$TIM::ptr() };
+                        tim.$ccmrx_output()
+                            .modify(|_, w| unsafe { w.$ocxpe().clear_bit().$ocxm().bits(mode.bits()) });
+                        tim.ccer.modify(|_, w| w.$ccxe().set_bit());
+                    }
+
+                    //NOTE(unsafe) atomic write with no side effects
+                    fn disable() {
+                        unsafe { (*$TIM::ptr()).ccer.modify(|_, w| w.$ccxe().clear_bit()) };
+                    }
+
+                    //NOTE(unsafe) atomic write with no side effects
+                    fn set_compare(value: u16) {
+                        unsafe { (*$TIM::ptr()).$ccrx.write(|w| w.$ccrx().bits(value)) }
+                    }
+
+                    //NOTE(unsafe) atomic read with no side effects
+                    fn get_compare() -> u16 {
+                        unsafe { (*$TIM::ptr()).$ccrx.read().$ccrx().bits() }
+                    }
+
+                    //NOTE(unsafe) atomic read with no side effects
+                    fn is_match() -> bool {
+                        unsafe { (*$TIM::ptr()).sr.read().$ccxif().bit_is_set() }
+                    }
+
+                    //NOTE(unsafe) atomic write with no side effects
+                    fn clear_match() {
+                        unsafe { (*$TIM::ptr()).sr.modify(|_, w| w.$ccxif().clear_bit()) };
+                    }
+                }
+            )+
+        )+
+    };
+}
+
+output_compare! {
+    TIM1: [
+        C1: (ccmr1_output, oc1m, oc1pe, cc1e, ccr1, cc1if),
+        C2: (ccmr1_output, oc2m, oc2pe, cc2e, ccr2, cc2if),
+        C3: (ccmr2_output, oc3m, oc3pe, cc3e, ccr3, cc3if),
+        C4: (ccmr2_output, oc4m, oc4pe, cc4e, ccr4, cc4if),
+    ],
+    TIM3: [
+        C1: (ccmr1_output, oc1m, oc1pe, cc1e, ccr1, cc1if),
+        C2: (ccmr1_output, oc2m, oc2pe, cc2e, ccr2, cc2if),
+        C3: (ccmr2_output, oc3m, oc3pe, cc3e, ccr3, cc3if),
+        C4: (ccmr2_output, oc4m, oc4pe, cc4e, ccr4, cc4if),
+    ],
+    TIM14: [
+        C1: (ccmr1_output, oc1m, oc1pe, cc1e, ccr1, cc1if),
+    ],
+    TIM16: [
+        C1: (ccmr1_output, oc1m, oc1pe, cc1e, ccr1, cc1if),
+    ],
+    TIM17: [
+        C1: (ccmr1_output, oc1m, oc1pe, cc1e, ccr1, cc1if),
+    ],
+}
+
+/// Sampling clock division between the timer clock and the dead-time / filter clock (`CKD`)
+#[derive(Clone, Copy, PartialEq, Eq)]
+pub enum ClockDivision {
+    /// fDTS = fCK_INT
+    Div1 = 0b00,
+    /// fDTS = fCK_INT / 2
+    Div2 = 0b01,
+    /// fDTS = fCK_INT / 4
+    Div4 = 0b10,
+}
+
+/// Digital input filter (`ICxF`)
+///
+/// An edge is only accepted once `N` consecutive samples taken at the given sampling frequency
+/// agree on the new level.
+#[derive(Clone, Copy, PartialEq, Eq)]
+pub enum InputFilter {
+    /// No filter, sampling at fDTS
+    NoFilter = 0b0000,
+    /// fCK_INT, N = 2
+    CkInt2 = 0b0001,
+    /// fCK_INT, N = 4
+    CkInt4 = 0b0010,
+    /// fCK_INT, N = 8
+    CkInt8 = 0b0011,
+    /// fDTS / 2, N = 6
+    DtsDiv2N6 = 0b0100,
+    /// fDTS / 2, N = 8
+    DtsDiv2N8 = 0b0101,
+    /// fDTS / 4, N = 6
+    DtsDiv4N6 = 0b0110,
+    /// fDTS / 4, N = 8
+    DtsDiv4N8 = 0b0111,
+    /// fDTS / 8, N = 6
+    DtsDiv8N6 = 0b1000,
+    /// fDTS / 8, N = 8
+    DtsDiv8N8 = 0b1001,
+    /// fDTS / 16, N = 5
+    DtsDiv16N5 = 0b1010,
+    /// fDTS / 16, N = 6
+    DtsDiv16N6 = 0b1011,
+    /// fDTS / 16, N = 8
+    DtsDiv16N8 = 0b1100,
+    /// fDTS / 32, N = 5
+    DtsDiv32N5 = 0b1101,
+    /// fDTS / 32, N = 6
+    DtsDiv32N6 = 0b1110,
+    /// fDTS / 32, N = 8
+    DtsDiv32N8 = 0b1111,
+}
+
+/// Input capture edge
+#[derive(Clone, Copy, PartialEq, Eq)]
+pub enum CaptureEdge {
+    /// Capture on rising edges
+    Rising,
+    /// Capture on falling edges
+    Falling,
+    /// Capture on both edges
+    ///
+    /// TIM1 channel 4 has no `CC4NP` bit and captures falling edges only.
+    Both,
+}
+
+/// Input capture configuration
+#[derive(Clone, Copy)]
+pub struct IcConfig {
+    /// Edge that triggers a capture
+    pub edge: CaptureEdge,
+    /// Digital filter applied to the input before edge detection
+    pub filter: InputFilter,
+    /// Filter sampling clock division
+    ///
+    /// This setting is shared by all channels of the timer (and the dead-time generator of the
+    /// advanced timers).
+    pub clock_division: ClockDivision,
+}
+
+impl Default for IcConfig {
+    fn default() -> Self {
+        IcConfig {
+            edge: CaptureEdge::Rising,
+            filter: InputFilter::NoFilter,
+            clock_division: ClockDivision::Div1,
+        }
+    }
+}
+
+/// Marker trait for pins usable as input capture `CHANNEL` of timer `TIM`
+pub trait IcPin<TIM, CHANNEL> {}
+
+impl<TIM, P: PinC1<TIM>> IcPin<TIM, C1> for P {}
+impl<TIM, P: PinC2<TIM>> IcPin<TIM, C2> for P {}
+impl<TIM, P: PinC3<TIM>> IcPin<TIM, C3> for P {}
+impl<TIM, P: PinC4<TIM>> IcPin<TIM, C4> for P {}
+
+/// Timer channel that can be used for input capture
+pub trait IcChannel<TIM> {
+    /// Maps the channel onto its input, configures it and enables capturing
+    fn configure(config: &IcConfig);
+    /// Disables capturing
+    fn disable();
+    /// Returns the last captured counter value
+    fn capture() -> u16;
+    /// Returns true if a new value was captured since the last `capture`
+    fn is_captured() -> bool;
+    /// Returns true if a capture was overwritten before it was read
+    fn is_overcaptured() -> bool;
+    /// Clears the over-capture flag
+    fn clear_overcapture();
+}
+
+/// Timer channel in input capture mode
+pub struct InputCapture<TIM, CHANNEL, PIN> {
+    pin: PIN,
+    _tim: PhantomData<TIM>,
+    _channel: PhantomData<CHANNEL>,
+}
+
+impl<TIM, CHANNEL, PIN> InputCapture<TIM, CHANNEL, PIN>
+where
+    CHANNEL: IcChannel<TIM>,
+{
+    /// Returns the counter value latched by the last capture
+    ///
+    /// Reading the capture clears the capture flag.
+    pub fn capture(&mut self) -> u16 {
+        CHANNEL::capture()
+    }
+
+    /// Returns true if a new value was captured since the last `capture`
+    pub fn is_captured(&self) -> bool {
+        CHANNEL::is_captured()
+    }
+
+    /// Returns true if a capture was overwritten before it was read
+    pub fn is_overcaptured(&self) -> bool {
+        CHANNEL::is_overcaptured()
+    }
+
+    /// Clears the over-capture flag
+    pub fn clear_overcapture(&mut self) {
+        CHANNEL::clear_overcapture()
+    }
+
+    /// Disables capturing and releases the pin
+    pub fn release(self) -> PIN {
+        CHANNEL::disable();
+        self.pin
+    }
+}
+
+macro_rules! input_capture {
+    ($($TIM:ident: [$($CX:ident: ($ccmrx_input:ident, $ccxs:ident, $icxf:ident, $ccxe:ident, $ccxp:ident, $($ccxnp:ident)?; $ccrx:ident, $ccxif:ident, $ccxof:ident),)+],)+) => {
+        $(
+            impl Timer<$TIM> {
+                /// Configures `pin` as an input capture channel
+                ///
+                /// On every selected edge the current counter value is latched into the channel's
+                /// capture register. The counter keeps running with the period set by `start`.
+                pub fn input_capture<CHANNEL, PIN>(
+                    &mut self,
+                    pin: PIN,
+                    config: IcConfig,
+                ) -> InputCapture<$TIM, CHANNEL, PIN>
+                where
+                    CHANNEL: IcChannel<$TIM>,
+                    PIN: IcPin<$TIM, CHANNEL>,
+                {
+                    self.tim
+                        .cr1
+                        .modify(|_, w| unsafe { w.ckd().bits(config.clock_division as u8) });
+                    CHANNEL::configure(&config);
+                    InputCapture {
+                        pin,
+                        _tim: PhantomData,
+                        _channel: PhantomData,
+                    }
+                }
+            }
+
+            $(
+                impl IcChannel<$TIM> for $CX {
+                    //NOTE(unsafe) the channel registers are only accessed through this channel
+                    fn configure(config: &IcConfig) {
+                        let tim = unsafe { &*$TIM::ptr() };
+                        // CCxS can only be written while the channel is disabled
+                        tim.ccer.modify(|_, w| w.$ccxe().clear_bit());
+                        // CCxS = 0b01: channel is an input mapped on TIx
+                        tim.$ccmrx_input()
+                            .modify(|_, w| unsafe { w.$ccxs().bits(0b01).$icxf().bits(config.filter as u8) });
+                        tim.ccer.modify(|_, w| {
+                            w.$ccxp().bit(config.edge != CaptureEdge::Rising);
+                            $(w.$ccxnp().bit(config.edge == CaptureEdge::Both);)?
+                            w.$ccxe().set_bit()
+                        });
+                    }
+
+                    //NOTE(unsafe) atomic write with no side effects
+                    fn disable() {
+                        unsafe { (*$TIM::ptr()).ccer.modify(|_, w| w.$ccxe().clear_bit()) };
+                    }
+
+                    //NOTE(unsafe) atomic read, clears the capture flag
+                    fn capture() -> u16 {
+                        unsafe { (*$TIM::ptr()).$ccrx.read().$ccrx().bits() }
+                    }
+
+                    //NOTE(unsafe) atomic read with no side effects
+                    fn is_captured() -> bool {
+                        unsafe { (*$TIM::ptr()).sr.read().$ccxif().bit_is_set() }
+                    }
+
+                    //NOTE(unsafe) atomic read with no side effects
+                    fn is_overcaptured() -> bool {
+                        unsafe { (*$TIM::ptr()).sr.read().$ccxof().bit_is_set() }
+                    }
+
+                    //NOTE(unsafe) atomic write with no side effects
+                    fn clear_overcapture() {
+                        unsafe { (*$TIM::ptr()).sr.modify(|_, w| w.$ccxof().clear_bit()) };
+                    }
+                }
+            )+
+        )+
+    };
+}
+
+input_capture! {
+    TIM1: [
+        C1: (ccmr1_input, cc1s, ic1f, cc1e, cc1p, cc1np; ccr1, cc1if, cc1of),
+        C2: (ccmr1_input, cc2s, ic2f, cc2e, cc2p, cc2np; ccr2, cc2if, cc2of),
+        C3: (ccmr2_input, cc3s, ic3f, cc3e, cc3p, cc3np; ccr3, cc3if, cc3of),
+        C4: (ccmr2_input, cc4s, ic4f, cc4e, cc4p, ; ccr4, cc4if, cc4of),
+    ],
+    TIM3: [
+        C1: (ccmr1_input, cc1s, ic1f, cc1e, cc1p, cc1np; ccr1, cc1if, cc1of),
+        C2: (ccmr1_input, cc2s, ic2f, cc2e, cc2p, cc2np; ccr2, cc2if, cc2of),
+        C3: (ccmr2_input, cc3s, ic3f, cc3e, cc3p, cc3np; ccr3, cc3if, cc3of),
+        C4: (ccmr2_input, cc4s, ic4f, cc4e, cc4p, cc4np; ccr4, cc4if, cc4of),
+    ],
+    TIM14: [
+        C1: (ccmr1_input, cc1s, ic1f, cc1e, cc1p, cc1np; ccr1, cc1if, cc1of),
+    ],
+    TIM16: [
+        C1: (ccmr1_input, cc1s, ic1f, cc1e, cc1p, cc1np; ccr1, cc1if, cc1of),
+    ],
+    TIM17: [
+        C1: (ccmr1_input, cc1s, ic1f, cc1e, cc1p, cc1np; ccr1, cc1if, cc1of),
+    ],
+}
+
+/// Counting direction (`DIR`)
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub enum Direction {
+    /// Count from 0 up to the auto-reload value
+    Up,
+    /// Count from the auto-reload value down to 0
+    Down,
+}
+
+macro_rules! direction {
+    ($($TIM:ident,)+) => {
+        $(
+            impl Timer<$TIM> {
+                /// Selects the counting direction
+                ///
+                /// The period is the same in both directions, the update event is raised on
+                /// overflow when counting up and on underflow when counting down. Only TIM1 and
+                /// TIM3 have a `DIR` bit, TIM14, TIM16 and TIM17 always count up.
+                ///
+                /// In PWM mode 1 the output is active while the counter is below the compare
+                /// value in either direction, so the duty cycle is unchanged; only the active
+                /// part moves from the start to the end of the period.
+                pub fn set_direction(&mut self, direction: Direction) {
+                    self.tim.cr1.modify(|_, w| w.dir().bit(direction == Direction::Down));
+                }
+
+                /// Returns the current counting direction
+                pub fn direction(&self) -> Direction {
+                    if self.tim.cr1.read().dir().bit_is_set() {
+                        Direction::Down
+                    } else {
+                        Direction::Up
+                    }
+                }
+            }
+        )+
+    };
+}
+
+direction! {
+    TIM1,
+    TIM3,
+}
+
+/// First register written by a DMA burst (`DBA`), as word offset into the timer registers
+#[derive(Clone, Copy, PartialEq, Eq)]
+pub enum DmaBurstBase {
+    /// Prescaler register
+    Psc = 10,
+    /// Auto-reload register
+    Arr = 11,
+    /// Repetition counter register (TIM1, TIM16 and TIM17 only)
+    Rcr = 12,
+    /// Capture/compare register 1
+    Ccr1 = 13,
+    /// Capture/compare register 2
+    Ccr2 = 14,
+    /// Capture/compare register 3
+    Ccr3 = 15,
+    /// Capture/compare register 4
+    Ccr4 = 16,
+}
+
+macro_rules! dma_burst {
+    ($($TIM:ident: $request:ident,)+) => {
+        $(
+            impl Timer<$TIM> {
+                /// Configures the DMA burst registers
+                ///
+                /// Every DMA access to the DMAR register is redirected to the `transfers`
+                /// consecutive registers starting at `base`. With `DmaBurstBase::Ccr1` and a
+                /// single transfer, a DMA channel feeding DMAR from a `&'static [u16]` on every
+                /// update event loads a new duty cycle per PWM period, see
+                /// [`dma_burst`](Self::dma_burst).
+                pub fn configure_dma_burst(&mut self, base: DmaBurstBase, transfers: u8) {
+                    let length = transfers.clamp(1, 18) - 1;
+                    self.tim
+                        .dcr
+                        .write(|w| unsafe { w.dba().bits(base as u8).dbl().bits(length) });
+                }
+
+                /// Enables the DMA request on every update event (`UDE`)
+                pub fn enable_update_dma(&mut self) {
+                    self.tim.dier.modify(|_, w| w.ude().set_bit());
+                }
+
+                /// Disables the DMA request on update events
+                pub fn disable_update_dma(&mut self) {
+                    self.tim.dier.modify(|_, w| w.ude().clear_bit());
+                }
+
+                /// Returns the address of the DMA burst register (DMAR), to be used as the
+                /// peripheral address of a DMA channel
+                pub fn dmar_address(&self) -> u32 {
+                    &self.tim.dmar as *const _ as u32
+                }
+
+                /// Writes one of `values` to the `base` register on every update event, with DMA
+                /// on `channel`
+                ///
+                /// With `DmaBurstBase::Ccr1` this loads a new duty cycle per PWM period, e.g. to
+                /// drive WS2812 LEDs. The channel is mapped to this timer's update request. Once
+                /// `channel.is_complete()`, stop the requests with
+                /// [`disable_update_dma`](Self::disable_update_dma). `values` must not be empty
+                /// or longer than 65535 entries.
+                pub fn dma_burst<CH>(
+                    &mut self,
+                    channel: &mut CH,
+                    base: DmaBurstBase,
+                    values: &'static [u16],
+                ) where
+                    CH: dma::Channel,
+                {
+                    assert!(!values.is_empty() && values.len() <= usize::from(u16::MAX));
+
+                    self.disable_update_dma();
+                    self.configure_dma_burst(base, 1);
+                    channel.stop();
+                    channel.clear_flags();
+                    channel.set_request(dma::Request::$request);
+                    channel.configure_u16(
+                        self.dmar_address(),
+                        values.as_ptr() as u32,
+                        values.len() as u16,
+                        dma::Direction::MemoryToPeripheral,
+                        false,
+                    );
+                    // The values must be completely written before the DMA reads them
+                    compiler_fence(Ordering::Release);
+                    channel.start();
+                    self.enable_update_dma();
+                }
+            }
+        )+
+    };
+}
+
+dma_burst! {
+    TIM1: Tim1Up,
+    TIM3: Tim3Up,
+    TIM16: Tim16Up,
+    TIM17: Tim17Up,
+}
+
+/// 32 bit free-running counter made of two chained 16 bit timers
+///
+/// TIM1 counts the prescaled timer clock and provides the low half. Its update event is routed
+/// through TRGO to the internal trigger ITR0 of TIM3, which counts the overflows and provides the
+/// high half. Both timers are consumed.
+pub struct Timer32 {
+    low: TIM1,
+    high: TIM3,
+}
+
+impl Timer32 {
+    /// Chains TIM1 (low half) and TIM3 (high half) into a counter incrementing at `freq`
+    pub fn tim1_tim3<T>(low: TIM1, high: TIM3, freq: T, rcc: &mut Rcc) -> Self
+    where
+        T: Into<Hertz>,
+    {
+        // enable and reset peripherals to a clean slate state
+        rcc.enable(Peripheral::Tim1);
+        rcc.reset(Peripheral::Tim1);
+        rcc.enable(Peripheral::Tim3);
+        rcc.reset(Peripheral::Tim3);
+
+        let tclk = rcc.clocks.peripheral_clock(Peripheral::Tim1);
+        let psc = (tclk / freq.into().max(Hertz(1))).clamp(1, 1 << 16) - 1;
+
+        low.psc.write(|w| unsafe { w.psc().bits(psc as u16) });
+        low.arr.write(|w| unsafe { w.bits(0xFFFF) });
+        // Load the prescaler while the slave is still stopped, so the update isn't counted
+        low.egr.write(|w| w.ug().set_bit());
+        low.sr.modify(|_, w| w.uif().clear_bit());
+        // MMS = 0b010: the update event is the trigger output
+        low.cr2.modify(|_, w| unsafe { w.mms().bits(0b010) });
+
+        high.arr.write(|w| unsafe { w.bits(0xFFFF) });
+        // TS = ITR0 (TIM1 TRGO), SMS = 0b111: external clock mode 1
+        high.smcr
+            .write(|w| unsafe { w.ts().bits(0b000).sms().bits(0b111) });
+
+        high.cr1.modify(|_, w| w.cen().set_bit());
+        low.cr1.modify(|_, w| w.cen().set_bit());
+
+        Timer32 { low, high }
+    }
+
+    /// Returns the current 32 bit count
+    ///
+    /// The high half is read before and after the low half, and the read is repeated if the low
+    /// half rolled over in between.
+    pub fn count(&self) -> u32 {
+        loop {
+            let high = self.high.cnt.read().bits() & 0xFFFF;
+            let low = self.low.cnt.read().bits() & 0xFFFF;
+            if self.high.cnt.read().bits() & 0xFFFF == high {
+                return (high << 16) | low;
+            }
+        }
+    }
+
+    /// Restarts counting from zero
+    pub fn reset(&mut self) {
+        self.low.cr1.modify(|_, w| w.cen().clear_bit());
+        self.low.cnt.reset();
+        self.high.cnt.reset();
+        self.low.cr1.modify(|_, w| w.cen().set_bit());
+    }
+
+    /// Stops the counter and releases the two timers, reset to their power-on state
+    pub fn release(self) -> (TIM1, TIM3) {
+        self.low.cr1.modify(|_, w| w.cen().clear_bit());
+        self.high.cr1.modify(|_, w| w.cen().clear_bit());
+        rcc::reset(Peripheral::Tim1);
+        rcc::reset(Peripheral::Tim3);
+        rcc::disable(Peripheral::Tim1);
+        rcc::disable(Peripheral::Tim3);
+        (self.low, self.high)
+    }
+}
+
+/// Average frequency measurement over a gate window
+///
+/// TIM1 runs in external clock mode 1 and counts the rising edges on its channel 1 input. TIM3
+/// runs in one-pulse mode and times the gate window, counted from the timer clock. Both timers are
+/// started together, and the edge count is latched by stopping TIM1 as soon as TIM3 signals the
+/// end of the window.
+///
+/// The slave controller of TIM1 is busy clocking the counter from TI1, so it can't also be gated
+/// by the TRGO of TIM3. The gate boundary is polled instead, which adds a few CPU cycles of
+/// uncertainty to the window; that is negligible against windows of a few milliseconds or more.
+/// Counter overflows are accumulated while waiting, interrupts must not block the measurement
+/// for longer than 65536 input periods. The input frequency must stay below half the timer clock.
+pub struct FrequencyCounter<PIN> {
+    counter: TIM1,
+    gate: TIM3,
+    pin: PIN,
+    clocks: Clocks,
+}
+
+impl<PIN> FrequencyCounter<PIN>
+where
+    PIN: PinC1<TIM1>,
+{
+    /// Configures TIM1 to count the edges on `pin` and TIM3 as the gate timer
+    pub fn tim1_tim3(counter: TIM1, gate: TIM3, pin: PIN, rcc: &mut Rcc) -> Self {
+        // enable and reset peripherals to a clean slate state
+        rcc.enable(Peripheral::Tim1);
+        rcc.reset(Peripheral::Tim1);
+        rcc.enable(Peripheral::Tim3);
+        rcc.reset(Peripheral::Tim3);
+
+        // CC1S = 0b01: IC1 mapped on TI1, rising edges, no filter
+        counter
+            .ccmr1_input()
+            .modify(|_, w| unsafe { w.cc1s().bits(0b01) });
+        counter
+            .ccer
+            .modify(|_, w| w.cc1p().clear_bit().cc1np().clear_bit());
+        counter.arr.write(|w| unsafe { w.bits(0xFFFF) });
+        // TS = TI1FP1, SMS = 0b111: external clock mode 1
+        counter
+            .smcr
+            .write(|w| unsafe { w.ts().bits(0b101).sms().bits(0b111) });
+
+        gate.cr1.modify(|_, w| w.opm().set_bit());
+
+        FrequencyCounter {
+            counter,
+            gate,
+            pin,
+            clocks: rcc.clocks,
+        }
+    }
+
+    /// Counts the input edges during `window` and returns the average frequency
+    ///
+    /// Blocks for the duration of the window. The window is rounded to the resolution of the gate
+    /// timer, the result is computed from the rounded window.
+    pub fn measure(&mut self, window: MillisDurationU32) -> Hertz {
+        let tclk = self.clocks.peripheral_clock(Peripheral::Tim3).0 as u64;
+        let ticks = (tclk * window.to_millis().max(1) as u64 / 1000).clamp(1, 1 << 32);
+        let psc = (ticks - 1) / (1 << 16);
+        let arr = (ticks / (psc + 1)).max(1) - 1;
+
+        self.gate.psc.write(|w| unsafe { w.psc().bits(psc as u16) });
+        self.gate.arr.write(|w| unsafe { w.bits(arr as u32) });
+        self.gate.cnt.reset();
+        // Load the prescaler, the update flag it raises doesn't mark the end of a window
+        self.gate.egr.write(|w| w.ug().set_bit());
+        self.gate.sr.modify(|_, w| w.uif().clear_bit());
+
+        self.counter.cnt.reset();
+        self.counter.sr.modify(|_, w| w.uif().clear_bit());
+
+        cortex_m::interrupt::free(|_| {
+            self.counter.cr1.modify(|_, w| w.cen().set_bit());
+            self.gate.cr1.modify(|_, w| w.cen().set_bit());
+        });
+
+        let mut overflows = 0u64;
+        let count = loop {
+            if self.counter.sr.read().uif().bit_is_set() {
+                self.counter.sr.modify(|_, w| w.uif().clear_bit());
+                overflows += 1;
+            }
+            if self.gate.sr.read().uif().bit_is_set() {
+                // Stop counting before reading, so no overflow slips in between
+                self.counter.cr1.modify(|_, w| w.cen().clear_bit());
+                if self.counter.sr.read().uif().bit_is_set() {
+                    self.counter.sr.modify(|_, w| w.uif().clear_bit());
+                    overflows += 1;
+                }
+                break (overflows << 16) | (self.counter.cnt.read().bits() & 0xFFFF) as u64;
+            }
+        };
+        self.gate.sr.modify(|_, w| w.uif().clear_bit());
+
+        let gate_ticks = (psc + 1) * (arr + 1);
+        Hertz((count * tclk / gate_ticks) as u32)
+    }
+
+    /// Releases the two timers, reset to their power-on state, and the input pin
+    pub fn release(self) -> (TIM1, TIM3, PIN) {
+        self.counter.cr1.modify(|_, w| w.cen().clear_bit());
+        self.gate.cr1.modify(|_, w| w.cen().clear_bit());
+        rcc::reset(Peripheral::Tim1);
+        rcc::reset(Peripheral::Tim3);
+        rcc::disable(Peripheral::Tim1);
+        rcc::disable(Peripheral::Tim3);
+        (self.counter, self.gate, self.pin)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{syst_reload, TimerPeriod};
+    use crate::time::Hertz;
+
+    const TCLK: Hertz = Hertz(24_000_000);
+
+    #[test]
+    fn period_at_1_hz_uses_prescaler() {
+        let period = TimerPeriod::new(TCLK, Hertz(1));
+        assert_eq!((period.psc, period.arr), (366, 65394));
+        assert_eq!(period.resolution_bits(), 15);
+    }
+
+    #[test]
+    fn period_at_tclk_is_one_tick() {
+        let period = TimerPeriod::new(TCLK, TCLK);
+        assert_eq!((period.psc, period.arr), (0, 0));
+        assert_eq!(period.resolution_bits(), 0);
+    }
+
+    #[test]
+    fn period_above_tclk_saturates_to_one_tick() {
+        let period = TimerPeriod::new(TCLK, Hertz(2 * TCLK.0));
+        assert_eq!((period.psc, period.arr), (0, 0));
+        assert_eq!(period.resolution_bits(), 0);
+    }
+
+    #[test]
+    fn period_without_prescaler_keeps_full_resolution() {
+        let period = TimerPeriod::new(Hertz(65_536_000), Hertz(1_000));
+        assert_eq!((period.psc, period.arr), (0, 65535));
+        assert_eq!(period.resolution_bits(), 16);
+    }
+
+    #[test]
+    fn syst_reload_clamps() {
+        assert_eq!(syst_reload(TCLK, Hertz(1_000)), 23_999);
+        assert_eq!(syst_reload(TCLK, Hertz(0)), 0xFF_FFFF);
+        assert_eq!(syst_reload(TCLK, Hertz(1)), 0xFF_FFFF);
+        assert_eq!(syst_reload(TCLK, TCLK), 1);
+        assert_eq!(syst_reload(TCLK, Hertz(u32::MAX)), 1);
+    }
+}
@@ -0,0 +1,37 @@
+//! Waker storage shared between a future and an interrupt handler
+
+use core::cell::RefCell;
+use core::task::Waker;
+
+use cortex_m::interrupt::Mutex;
+
+/// Holds the waker of the task waiting on a peripheral interrupt
+pub(crate) struct WakerCell {
+    waker: Mutex<RefCell<Option<Waker>>>,
+}
+
+impl WakerCell {
+    pub(crate) const fn new() -> Self {
+        WakerCell {
+            waker: Mutex::new(RefCell::new(None)),
+        }
+    }
+
+    /// Stores `waker`, replacing the previous one unless both wake the same task
+    pub(crate) fn register(&self, waker: &Waker) {
+        cortex_m::interrupt::free(|cs| {
+            let mut slot = self.waker.borrow(cs).borrow_mut();
+            match &*slot {
+                Some(stored) if stored.will_wake(waker) => {}
+                _ => *slot = Some(waker.clone()),
+            }
+        });
+    }
+
+    /// Wakes the stored task, if any
+    pub(crate) fn wake(&self) {
+        if let Some(waker) = cortex_m::interrupt::free(|cs| self.waker.borrow(cs).take()) {
+            waker.wake();
+        }
+    }
+}
@@ -1,12 +1,26 @@
+//! # Independent and Window Watchdogs
+//!
+//! The IWDG is clocked by the LSI and keeps counting in Stop mode. The Air001 has no option byte
+//! or register to freeze it there, so a device that sleeps longer than the watchdog period must
+//! wake up periodically (e.g. from the RTC or LPTIM) to feed it. The only freeze control is
+//! `DBG_IWDG_STOP`, which stops the counter while the core is halted by a debugger.
+//!
+//! The IWDG has no interrupt, it resets without warning. Only the window watchdog
+//! ([`WindowWatchdog`]) can raise an early wake-up interrupt right before the reset, to save
+//! state or log the cause.
+
 use embedded_hal::watchdog;
 
-use crate::pac::IWDG;
-use crate::time::Hertz;
+use crate::pac::{DBGMCU, FLASH, IWDG, WWDG};
+use crate::rcc::{Peripheral, Rcc};
+use crate::time::{Hertz, MillisDurationU32};
 
 const FEED: u16 = 0xAAAA; // Reset the watchdog value
 const START: u16 = 0xCCCC; // Start the watchdog
 const ENABLE: u16 = 0x5555; // Enable access to PR, RLR and WINR registers
 
+const LSI: u32 = 40_000; // Nominal LSI frequency
+
 /// Watchdog instance
 pub struct Watchdog {
     iwdg: IWDG,
@@ -27,13 +41,58 @@ pub struct IwdgTimeout {
     reload: u16,
 }
 
+/// Watchdog configuration errors
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum WatchdogError {
+    /// The timeout exceeds the longest period of the IWDG, which is returned
+    TooLong(MillisDurationU32),
+    /// A zero timeout was requested
+    TooShort,
+}
+
+impl IwdgTimeout {
+    /// Longest timeout at the nominal LSI frequency (prescaler 256, reload 0xFFF)
+    pub const MAX: MillisDurationU32 = MillisDurationU32::from_ticks(256 * 0xFFF * 1000 / LSI);
+
+    /// Converts `timeout` to a prescaler and reload value
+    ///
+    /// Unlike the `From<Hertz>` conversion this fails instead of clamping when the timeout can't
+    /// be represented. The LSI is not trimmed and may deviate from its nominal 40 kHz by a large
+    /// margin, so the actual timeout should not be relied upon to be exact.
+    pub fn try_from_duration(timeout: MillisDurationU32) -> Result<Self, WatchdogError> {
+        let ms = timeout.ticks();
+        if ms == 0 {
+            return Err(WatchdogError::TooShort);
+        }
+        if ms > Self::MAX.ticks() {
+            return Err(WatchdogError::TooLong(Self::MAX));
+        }
+
+        // Number of LSI cycles, fits as the timeout is at most ~26 s
+        let cycles = ms * (LSI / 1000);
+        let mut psc = 0;
+        loop {
+            let divider = 4 << psc;
+            let reload = (cycles + divider / 2) / divider;
+            if reload <= 0xFFF || psc == 6 {
+                return Ok(IwdgTimeout {
+                    psc,
+                    reload: reload.clamp(1, 0xFFF) as u16,
+                });
+            }
+            psc += 1;
+        }
+    }
+}
+
 impl From<Hertz> for IwdgTimeout {
     /// This converts the value so it's usable by the IWDG
     /// Due to conversion losses, the specified frequency is a maximum
     ///
-    /// It can also only represent values < 10000 Hertz
+    /// It can also only represent values < 10000 Hertz. Frequencies too low for the longest
+    /// period are silently clamped to it, use [`IwdgTimeout::try_from_duration`] to detect this.
     fn from(hz: Hertz) -> Self {
-        let mut time = 40_000 / 4 / hz.0;
+        let mut time = LSI / 4 / hz.0.max(1);
         let mut psc = 0;
         let mut reload = 0;
         while psc < 7 {
@@ -44,8 +103,9 @@ impl From<Hertz> for IwdgTimeout {
             psc += 1;
             time /= 2;
         }
-        // As we get an integer value, reload is always below 0xFFF
-        let reload = reload as u16;
+        // As we get an integer value, reload is always below 0xFFF. A reload of 0 would reset
+        // immediately, so frequencies above the maximum are clamped to the shortest timeout.
+        let reload = reload.max(1) as u16;
         IwdgTimeout { psc, reload }
     }
 }
@@ -54,6 +114,30 @@ impl Watchdog {
     pub fn new(iwdg: IWDG) -> Self {
         Self { iwdg }
     }
+
+    /// Returns true if the IWDG is started by hardware after every reset
+    ///
+    /// This is selected by the `IWDG_SW` option bit; a hardware watchdog cannot be kept from
+    /// running and only its timeout can be changed.
+    pub fn is_hardware_started(flash: &FLASH) -> bool {
+        flash.optr.read().idwg_sw().bit_is_clear()
+    }
+
+    /// Selects whether the counter stops while the core is halted by a debugger
+    ///
+    /// The debug support clock (`Peripheral::Dbg`) has to be enabled for this to take effect.
+    /// It has no influence on the behavior in Stop mode.
+    pub fn freeze_in_debug(&mut self, dbgmcu: &mut DBGMCU, freeze: bool) {
+        dbgmcu.apb_fz1.modify(|_, w| w.dbg_iwdg_stop().bit(freeze));
+    }
+
+    /// Releases the IWDG peripheral
+    ///
+    /// Once started the IWDG cannot be stopped by software, it keeps running until the next
+    /// reset and has to be fed through the returned peripheral.
+    pub fn release(self) -> IWDG {
+        self.iwdg
+    }
 }
 
 impl watchdog::WatchdogEnable for Watchdog {
@@ -80,3 +164,99 @@ impl watchdog::WatchdogEnable for Watchdog {
         self.iwdg.kr.write(|w| unsafe { w.key().bits(FEED) });
     }
 }
+
+/// Divider of the WWDG counter clock (`WDGTB`), on top of the fixed PCLK / 4096
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub enum WwdgPrescaler {
+    Div1 = 0b00,
+    Div2 = 0b01,
+    Div4 = 0b10,
+    Div8 = 0b11,
+}
+
+/// Window watchdog
+///
+/// The 7-bit counter decrements at PCLK / 4096 / prescaler and resets the device when it rolls
+/// over from 0x40 to 0x3F, or when it is fed while still above the window value. With the
+/// early wake-up interrupt enabled, the `WWDG` interrupt fires when the counter reaches 0x40,
+/// one counter tick before the reset.
+pub struct WindowWatchdog {
+    wwdg: WWDG,
+    counter: u8,
+}
+
+impl WindowWatchdog {
+    /// Enables the WWDG clock, the watchdog is started with [`WindowWatchdog::start`]
+    pub fn new(wwdg: WWDG, rcc: &mut Rcc) -> Self {
+        rcc.enable(Peripheral::Wwdg);
+        Self {
+            wwdg,
+            counter: 0x7F,
+        }
+    }
+
+    /// Starts the watchdog
+    ///
+    /// `counter` (0x40 to 0x7F) is loaded on every feed, feeding is only allowed once the counter
+    /// dropped to `window` or below. A `window` of 0x7F disables the window check. Once started,
+    /// the WWDG can only be stopped by a reset.
+    pub fn start(&mut self, prescaler: WwdgPrescaler, counter: u8, window: u8) {
+        let counter = counter.clamp(0x40, 0x7F);
+        self.counter = counter;
+        self.wwdg
+            .cfr
+            .modify(|_, w| unsafe { w.wdgtb().bits(prescaler as u8).w().bits(window & 0x7F) });
+        self.wwdg
+            .cr
+            .write(|w| unsafe { w.t().bits(counter).wdga().set_bit() });
+    }
+
+    /// Reloads the counter
+    pub fn feed(&mut self) {
+        self.wwdg
+            .cr
+            .write(|w| unsafe { w.t().bits(self.counter).wdga().set_bit() });
+    }
+
+    /// Returns the current counter value
+    pub fn counter(&self) -> u8 {
+        self.wwdg.cr.read().t().bits()
+    }
+
+    /// Enables the early wake-up interrupt (`EWI`)
+    ///
+    /// The `WWDG` interrupt has to be unmasked in the NVIC as well. The handler runs about one
+    /// counter tick before the reset: either use it to save state and let the reset happen, or
+    /// feed the watchdog from it. The interrupt can only be disabled by a reset.
+    pub fn listen_early_wakeup(&mut self) {
+        self.wwdg.cfr.modify(|_, w| w.ewi().set_bit());
+    }
+
+    /// Returns true if the counter reached 0x40 (`EWIF`)
+    pub fn is_early_wakeup_pending(&self) -> bool {
+        self.wwdg.sr.read().ewif().bit_is_set()
+    }
+
+    /// Clears the early wake-up flag, to be done in the interrupt handler
+    pub fn clear_early_wakeup(&mut self) {
+        self.wwdg.sr.write(|w| w.ewif().clear_bit());
+    }
+
+    /// Selects whether the counter stops while the core is halted by a debugger
+    ///
+    /// The debug support clock (`Peripheral::Dbg`) has to be enabled for this to take effect.
+    pub fn freeze_in_debug(&mut self, dbgmcu: &mut DBGMCU, freeze: bool) {
+        dbgmcu.apb_fz1.modify(|_, w| w.dbg_wwdg_stop().bit(freeze));
+    }
+
+    /// Releases the WWDG peripheral, a started watchdog keeps running
+    pub fn release(self) -> WWDG {
+        self.wwdg
+    }
+}
+
+impl watchdog::Watchdog for WindowWatchdog {
+    fn feed(&mut self) {
+        WindowWatchdog::feed(self)
+    }
+}